@@ -0,0 +1,95 @@
+//! `cache` stores compiled objects on disk, keyed by everything that affects the output.
+//!
+//! The key covers the Wasm bytes, the wasker version, the target triple, CPU and features,
+//! and the codegen options including the content of the import adapter file. On a hit the cached object is copied to the output file
+//! without translating the module. The `.ll` file isn't written on a hit.
+
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+use crate::compiler::{target_cpu_and_features, Args};
+
+/// Compute the cache key of compiling `wasm` with `args`.
+pub fn cache_key(wasm: &[u8], args: &Args) -> Result<String> {
+    // Paths don't affect the output, except the input file recorded in DWARF
+    let options = Args {
+        input_file: if args.debug_info {
+            args.input_file.clone()
+        } else {
+            PathBuf::new()
+        },
+        output_file: PathBuf::new(),
+        cache_dir: None,
+        import_adapter_file: None,
+        ..args.clone()
+    };
+    let options = format!("{options:?}");
+    let (cpu, features) = target_cpu_and_features(args);
+    let triple = inkwell::targets::TargetMachine::get_default_triple()
+        .as_str()
+        .to_string_lossy()
+        .into_owned();
+
+    let mut hasher = Fnv128::new();
+    hasher.write(wasm);
+    if let Some(path) = &args.import_adapter_file {
+        hasher.write(&std::fs::read(path).context("fail read import adapter file")?);
+    }
+    for field in [
+        env!("CARGO_PKG_VERSION"),
+        triple.as_str(),
+        cpu.as_str(),
+        features.as_str(),
+        options.as_str(),
+    ] {
+        // Separate fields so that their boundaries are part of the key
+        hasher.write(&(field.len() as u64).to_le_bytes());
+        hasher.write(field.as_bytes());
+    }
+    Ok(format!("{:032x}", hasher.finish()))
+}
+
+/// Copy the cached object to `output_file`. Return false on a miss.
+pub fn restore(cache_dir: &Path, key: &str, output_file: &Path) -> Result<bool> {
+    let cached = cache_dir.join(format!("{key}.o"));
+    if !cached.exists() {
+        return Ok(false);
+    }
+    std::fs::copy(&cached, output_file).context("fail copy cached object")?;
+    Ok(true)
+}
+
+/// Store `output_file` in the cache.
+pub fn store(cache_dir: &Path, key: &str, output_file: &Path) -> Result<()> {
+    std::fs::create_dir_all(cache_dir).context("fail create cache dir")?;
+
+    // Write to a temporary file first, so that a concurrent build never reads a partial object
+    let tmp = cache_dir.join(format!("{key}.o.tmp{}", std::process::id()));
+    std::fs::copy(output_file, &tmp).context("fail copy object to cache")?;
+    std::fs::rename(&tmp, cache_dir.join(format!("{key}.o")))
+        .context("fail rename cached object")?;
+    Ok(())
+}
+
+// 128-bit FNV-1a, stable across Rust versions unlike `DefaultHasher`
+struct Fnv128(u128);
+
+impl Fnv128 {
+    const OFFSET_BASIS: u128 = 0x6c62272e07bb014262b821756295c58d;
+    const PRIME: u128 = 0x0000000001000000000000000000013b;
+
+    fn new() -> Self {
+        Self(Self::OFFSET_BASIS)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 ^= b as u128;
+            self.0 = self.0.wrapping_mul(Self::PRIME);
+        }
+    }
+
+    fn finish(&self) -> u128 {
+        self.0
+    }
+}
@@ -1,20 +1,204 @@
 //! `compiler` is the root module of Wasker compiler.
 
+use crate::cache;
+use crate::coverage::Coverage;
 use crate::environment::Environment;
+use crate::fuel::FuelCost;
+use crate::import_adapter::ImportAdapter;
 use crate::inkwell::init_inkwell;
+use crate::partition::{chunk_functions, split_functions, Partition};
 use crate::section::translate_module;
-use anyhow::{anyhow, Context, Result};
-use clap::Parser;
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{Parser, ValueEnum};
 use inkwell::{context, module::Module, passes::PassManager, targets};
 use std::path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
 use wat;
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Default, Clone)]
 pub struct Args {
     pub input_file: path::PathBuf,
 
     #[arg(short, long, default_value = "./wasm.o")]
     pub output_file: path::PathBuf,
+
+    /// Replace NaNs produced by float instructions with the canonical NaN
+    #[arg(long)]
+    pub canonicalize_nans: bool,
+
+    /// Generate fully deterministic code for replay and consensus workloads.
+    /// Implies --canonicalize-nans and targets a generic CPU instead of the host CPU.
+    #[arg(long)]
+    pub deterministic: bool,
+
+    /// Relocation model of the output object
+    #[arg(long, value_enum, default_value_t = RelocModel::Default)]
+    pub reloc_model: RelocModel,
+
+    /// Generate position-independent code for PIE executables and shared libraries.
+    /// Shorthand for --reloc-model=pic
+    #[arg(long)]
+    pub pic: bool,
+
+    /// Code model of the output object, e.g. `kernel` for higher-half kernels
+    #[arg(long, value_enum, default_value_t = CodeModel::Default)]
+    pub code_model: CodeModel,
+
+    /// Additional target features, e.g. `-sse,-sse2,+soft-float` for code that must not use FP registers
+    #[arg(long, default_value = "")]
+    pub target_features: String,
+
+    /// Keep frame pointers in generated functions
+    #[arg(long, value_enum, default_value_t = FramePointer::Default)]
+    pub frame_pointer: FramePointer,
+
+    /// Emit DWARF which maps native code to offsets in the Wasm module
+    #[arg(short = 'g', long)]
+    pub debug_info: bool,
+
+    /// Copy the custom section NAME into the ELF section `.wasm.custom.NAME`,
+    /// between the symbols `wasker_custom_SYMBOL_start` and `wasker_custom_SYMBOL_end`
+    /// where SYMBOL is NAME with characters other than ASCII letters and digits replaced by `_`.
+    /// Sections with the same SYMBOL are an error.
+    /// Can be specified multiple times
+    #[arg(long = "custom-section", value_name = "NAME")]
+    pub custom_sections: Vec<String>,
+
+    /// Compile modules using the proposal FEATURE even though wasker doesn't support it,
+    /// e.g. when the module declares but doesn't use it. Can be specified multiple times
+    #[arg(long = "enable-feature", value_name = "FEATURE")]
+    pub enabled_features: Vec<String>,
+
+    /// Reject modules using the proposal FEATURE. Can be specified multiple times
+    #[arg(long = "disable-feature", value_name = "FEATURE")]
+    pub disabled_features: Vec<String>,
+
+    /// Compile functions on N threads.
+    /// The output doesn't depend on N other than the split of functions
+    #[arg(short, long, default_value_t = 1)]
+    pub jobs: usize,
+
+    /// Generate one object for every N functions, compiled on --jobs threads.
+    /// Requires --emit archive
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub functions_per_object: Option<u32>,
+
+    /// Kind of the output file
+    #[arg(long, value_enum, default_value_t = Emit::Object)]
+    pub emit: Emit,
+
+    /// Reuse objects compiled with the same module and options from DIR
+    #[arg(long, value_name = "DIR")]
+    pub cache_dir: Option<path::PathBuf>,
+
+    /// Translate arguments of an import, written as `<module> <name> <param>...` where each param is
+    /// `val`, `ptr` (guest pointer) or `len` (length of the preceding pointer).
+    /// No import is adapted by default, including `myenv print`.
+    /// Can be specified multiple times
+    #[arg(long = "import-adapter", value_name = "SPEC")]
+    pub import_adapters: Vec<ImportAdapter>,
+
+    /// Read import adapters from FILE, one per line
+    #[arg(long, value_name = "FILE")]
+    pub import_adapter_file: Option<path::PathBuf>,
+
+    /// Pass a pointer to `{ memory_base, memory_size }` as the first argument of every import
+    #[arg(long)]
+    pub import_context: bool,
+
+    /// Stack probing of generated functions
+    #[arg(long, value_enum, default_value_t = StackProbe::Default)]
+    pub stack_probe: StackProbe,
+
+    /// Charge fuel for executed instructions from the host-defined `wasker_fuel`,
+    /// and call `wasker_out_of_fuel` when it runs out
+    #[arg(long)]
+    pub fuel: bool,
+
+    /// Fuel cost of a class of instructions, written as `CLASS=N` where CLASS is
+    /// control, call, memory, variable or numeric. Can be specified multiple times
+    #[arg(long = "fuel-cost", value_name = "CLASS=N", requires = "fuel")]
+    pub fuel_costs: Vec<FuelCost>,
+
+    /// Check the host-advanced `wasker_epoch` against `wasker_epoch_deadline` at function entries
+    /// and loop headers, and call `wasker_interrupt` once the deadline has passed
+    #[arg(long)]
+    pub epoch_interruption: bool,
+
+    /// Trap when the stack pointer is below the host-defined SYMBOL at function entry.
+    /// SYMBOL is `wasker_stack_limit` if omitted
+    #[arg(
+        long,
+        value_name = "SYMBOL",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "wasker_stack_limit"
+    )]
+    pub stack_limit: Option<String>,
+
+    /// Call `wasker_trace_enter` and `wasker_trace_exit` on entry and exit of every function,
+    /// and `wasker_trace_import` and `wasker_trace_import_exit` around calls to imports
+    #[arg(long)]
+    pub trace: bool,
+
+    /// Count executions of functions or blocks in the ELF section `wasker_coverage`
+    #[arg(long, value_enum, value_name = "LEVEL")]
+    pub coverage: Option<Coverage>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Emit {
+    /// A single relocatable object
+    #[default]
+    Object,
+    /// A static archive of per-partition objects with a symbol index
+    Archive,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RelocModel {
+    /// Default of the host target
+    #[default]
+    Default,
+    Static,
+    Pic,
+    DynamicNoPic,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CodeModel {
+    /// Default of the host target
+    #[default]
+    Default,
+    Small,
+    Kernel,
+    Medium,
+    Large,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FramePointer {
+    /// Let LLVM decide
+    #[default]
+    Default,
+    /// Omit frame pointers
+    None,
+    /// Keep frame pointers in functions which call other functions
+    NonLeaf,
+    /// Keep frame pointers in all functions
+    All,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StackProbe {
+    /// Let LLVM decide
+    #[default]
+    Default,
+    /// Probe large stack frames with inline code
+    Inline,
+    /// Never probe the stack
+    None,
 }
 
 /// Receive a path to a Wasm binary or WAT and compile it into ELF binary.
@@ -33,22 +217,214 @@ pub fn compile_wasm_from_file(args: &Args) -> Result<()> {
 
 /// Receive a Wasm binary and compile it into ELF binary.
 pub fn compile_wasm(wasm: &[u8], args: &Args) -> Result<()> {
+    check_args(args)?;
+
+    let cache_key = match &args.cache_dir {
+        Some(cache_dir) => {
+            let key = cache::cache_key(wasm, args)?;
+            if cache::restore(cache_dir, &key, &args.output_file)? {
+                log::info!("Compile success (cached {key})");
+                return Ok(());
+            }
+            Some(key)
+        }
+        None => None,
+    };
+
+    let partitions = match args.functions_per_object {
+        Some(n) => chunk_functions(wasm, n as usize).context("error chunk_functions")?,
+        None if args.jobs > 1 => {
+            split_functions(wasm, args.jobs).context("error split_functions")?
+        }
+        None => Vec::new(),
+    };
+
+    // Objects are combined by binutils. Check them before spending time on compilation
+    let combine_tool = match args.emit {
+        Emit::Object if partitions.len() > 1 => {
+            Some(find_tool("ld", "to combine objects compiled with --jobs")?)
+        }
+        Emit::Object => None,
+        Emit::Archive => Some(find_tool("ar", "for --emit archive")?),
+    };
+
+    let part_paths = if partitions.len() > 1 {
+        compile_partitions(wasm, args, partitions)?
+    } else if args.emit == Emit::Archive {
+        let part_path = part_path(&args.output_file, 0);
+        compile_module(wasm, args, &part_path, None)?;
+        vec![part_path]
+    } else {
+        compile_module(wasm, args, &args.output_file, None)?;
+        Vec::new()
+    };
+    if let Some(tool) = &combine_tool {
+        match args.emit {
+            Emit::Object => {
+                link_objects(tool, &part_paths, &args.output_file).context("error link_objects")?
+            }
+            Emit::Archive => archive_objects(tool, &part_paths, &args.output_file)
+                .context("error archive_objects")?,
+        }
+        for part_path in &part_paths {
+            std::fs::remove_file(part_path).context("fail remove partition object")?;
+            std::fs::remove_file(part_path.with_extension("ll"))
+                .context("fail remove partition IR")?;
+        }
+    }
+    if let (Some(cache_dir), Some(key)) = (&args.cache_dir, &cache_key) {
+        cache::store(cache_dir, key, &args.output_file).context("error store cache")?;
+    }
+
+    log::info!("Compile success");
+    Ok(())
+}
+
+/// Check combinations of options which clap can't express.
+pub fn check_args(args: &Args) -> Result<()> {
+    // Objects of N functions would be linked back into one object
+    if args.functions_per_object.is_some() && args.emit != Emit::Archive {
+        bail!("--functions-per-object requires --emit archive");
+    }
+    Ok(())
+}
+
+// LLVM recurses deeply on large functions
+const THREAD_STACK_SIZE: usize = 16 << 20;
+
+// Compile each partition into its own object on up to `args.jobs` threads.
+// Return the paths of the objects in the order of partitions.
+fn compile_partitions(
+    wasm: &[u8],
+    args: &Args,
+    partitions: Vec<Partition>,
+) -> Result<Vec<path::PathBuf>> {
+    let num_threads = args.jobs.clamp(1, partitions.len());
+    log::info!(
+        "compile {} partitions on {num_threads} threads",
+        partitions.len()
+    );
+    let part_paths: Vec<path::PathBuf> = partitions
+        .iter()
+        .map(|partition| part_path(&args.output_file, partition.index))
+        .collect();
+    // Each thread takes the next partition until none is left
+    let next = AtomicUsize::new(0);
+    let worker = || -> Result<()> {
+        loop {
+            let i = next.fetch_add(1, Ordering::Relaxed);
+            let Some(partition) = partitions.get(i) else {
+                return Ok(());
+            };
+            compile_module(wasm, args, &part_paths[i], Some(partition.clone()))?;
+        }
+    };
+    std::thread::scope(|scope| {
+        let handles: Vec<std::thread::ScopedJoinHandle<'_, Result<()>>> = (0..num_threads)
+            .map(|_| {
+                std::thread::Builder::new()
+                    .stack_size(THREAD_STACK_SIZE)
+                    .spawn_scoped(scope, worker)
+                    .context("fail to spawn compile thread")
+            })
+            .collect::<Result<_>>()?;
+        handles.into_iter().try_for_each(|handle| {
+            handle
+                .join()
+                .map_err(|_| anyhow!("compile thread panicked"))?
+        })
+    })?;
+    Ok(part_paths)
+}
+
+fn part_path(output_file: &path::Path, index: usize) -> path::PathBuf {
+    let stem = output_file
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("wasm");
+    output_file.with_file_name(format!("{stem}.part{index}.o"))
+}
+
+// Find an external tool in PATH
+fn find_tool(name: &str, purpose: &str) -> Result<path::PathBuf> {
+    std::env::var_os("PATH")
+        .and_then(|paths| {
+            std::env::split_paths(&paths)
+                .map(|dir| dir.join(name))
+                .find(|path| path.is_file())
+        })
+        .ok_or_else(|| anyhow!("`{name}` is required {purpose}, but it isn't found in PATH"))
+}
+
+// Package objects into a static archive with a symbol index, so that the linker pulls only needed members
+fn archive_objects(
+    ar: &path::Path,
+    objects: &[path::PathBuf],
+    output_file: &path::Path,
+) -> Result<()> {
+    // `ar` would add members to an existing archive
+    if output_file.exists() {
+        std::fs::remove_file(output_file).context("fail remove old archive")?;
+    }
+    let status = std::process::Command::new(ar)
+        .arg("rcs")
+        .arg(output_file)
+        .args(objects)
+        .status()
+        .context("fail to run ar")?;
+    if !status.success() {
+        bail!("ar rcs failed with {status}");
+    }
+    Ok(())
+}
+
+// Combine relocatable objects with `ld -r`
+fn link_objects(
+    ld: &path::Path,
+    objects: &[path::PathBuf],
+    output_file: &path::Path,
+) -> Result<()> {
+    let status = std::process::Command::new(ld)
+        .arg("-r")
+        .arg("-o")
+        .arg(output_file)
+        .args(objects)
+        .status()
+        .context("fail to run ld")?;
+    if !status.success() {
+        bail!("ld -r failed with {status}");
+    }
+    Ok(())
+}
+
+// Compile the whole module, or only functions of `partition`, into `output_file`
+fn compile_module(
+    wasm: &[u8],
+    args: &Args,
+    output_file: &path::Path,
+    partition: Option<Partition>,
+) -> Result<()> {
     // Prepare inkwell (Rust-wrapper of LLVM) instances
     let context = context::Context::create();
     let module = context.create_module("wasker_module");
     let builder = context.create_builder();
     let (inkwell_types, inkwell_insts) = init_inkwell(&context, &module);
     let mut environment = Environment::new(
-        args.output_file.as_path(),
+        output_file,
+        args,
         &context,
         &module,
         builder,
         inkwell_types,
         inkwell_insts,
     );
+    environment.partition = partition;
 
     // translate wasm to LLVM IR
     translate_module(wasm, &mut environment)?;
+    if let Some(debug_info) = &environment.debug_info {
+        debug_info.finalize();
+    }
 
     let pass_manager: PassManager<Module<'_>> = PassManager::create(());
     pass_manager.add_type_based_alias_analysis_pass();
@@ -83,7 +459,6 @@ pub fn compile_wasm(wasm: &[u8], args: &Args) -> Result<()> {
     // output LLVM IR to native ELF
     output_elf(environment).context("error output_elf")?;
 
-    log::info!("Compile success");
     Ok(())
 }
 
@@ -99,7 +474,7 @@ fn output_elf(environment: Environment) -> Result<()> {
         .context("fail print_to_file")?;
 
     log::info!("write to {}, it may take a while", obj_path.display());
-    get_host_target_machine()
+    get_host_target_machine(environment.args)
         .expect("error get_host_target_machine")
         .write_to_file(
             environment.module,
@@ -111,30 +486,66 @@ fn output_elf(environment: Environment) -> Result<()> {
     Ok(())
 }
 
-fn get_host_target_machine() -> Result<targets::TargetMachine, String> {
+/// CPU and features which the object is compiled for.
+pub fn target_cpu_and_features(args: &Args) -> (String, String) {
+    use targets::TargetMachine;
+
+    // Deterministic output must not depend on the CPU of the build host
+    let (cpu, mut features) = if args.deterministic {
+        ("generic".to_string(), String::new())
+    } else {
+        (
+            TargetMachine::get_host_cpu_name()
+                .to_str()
+                .expect("error get cpu info")
+                .to_string(),
+            TargetMachine::get_host_cpu_features()
+                .to_str()
+                .expect("error get features")
+                .to_string(),
+        )
+    };
+
+    if !args.target_features.is_empty() {
+        if !features.is_empty() {
+            features.push(',');
+        }
+        features.push_str(&args.target_features);
+    }
+    (cpu, features)
+}
+
+fn get_host_target_machine(args: &Args) -> Result<targets::TargetMachine, String> {
     use targets::*;
 
-    Target::initialize_native(&InitializationConfig::default())
+    // Partitions create target machines concurrently, so initialize LLVM only once
+    static INITIALIZED: OnceLock<Result<(), String>> = OnceLock::new();
+    INITIALIZED
+        .get_or_init(|| Target::initialize_native(&InitializationConfig::default()))
+        .clone()
         .map_err(|e| format!("failed to initialize native target: {e}"))?;
 
     let triple = TargetMachine::get_default_triple();
     let target = Target::from_triple(&triple).map_err(|e| format!("failed to get target: {e}"))?;
 
-    let cpu = TargetMachine::get_host_cpu_name();
-    let features = TargetMachine::get_host_cpu_features();
+    let (cpu, features) = target_cpu_and_features(args);
 
     let opt_level = inkwell::OptimizationLevel::Aggressive;
-    let reloc_mode = RelocMode::Default;
-    let code_model = CodeModel::Default;
+    let reloc_mode = match (args.pic, args.reloc_model) {
+        (true, _) | (false, RelocModel::Pic) => RelocMode::PIC,
+        (false, RelocModel::Default) => RelocMode::Default,
+        (false, RelocModel::Static) => RelocMode::Static,
+        (false, RelocModel::DynamicNoPic) => RelocMode::DynamicNoPic,
+    };
+    let code_model = match args.code_model {
+        self::CodeModel::Default => CodeModel::Default,
+        self::CodeModel::Small => CodeModel::Small,
+        self::CodeModel::Kernel => CodeModel::Kernel,
+        self::CodeModel::Medium => CodeModel::Medium,
+        self::CodeModel::Large => CodeModel::Large,
+    };
 
     target
-        .create_target_machine(
-            &triple,
-            cpu.to_str().expect("error get cpu info"),
-            features.to_str().expect("error get features"),
-            opt_level,
-            reloc_mode,
-            code_model,
-        )
+        .create_target_machine(&triple, &cpu, &features, opt_level, reloc_mode, code_model)
         .ok_or("failed to get target machine".to_string())
 }
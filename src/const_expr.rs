@@ -0,0 +1,142 @@
+//! `const_expr` evaluates constant expressions of global initializers and segment offsets.
+//!
+//! Expressions are built with the builder positioned in `wasker_init`.
+//! When all operands are constant, LLVM folds the expression into a constant at compile time.
+//! Otherwise, e.g. `global.get` of an imported global, the value is computed when `wasker_init` runs.
+
+use anyhow::{anyhow, bail, Result};
+use inkwell::values::{BasicValue, BasicValueEnum, IntValue};
+use wasmparser::{ConstExpr, Operator};
+
+use crate::environment::{Environment, Global};
+
+/// Evaluate a constant expression in `wasker_init`.
+pub fn eval_const_expr<'a>(
+    environment: &mut Environment<'a, '_>,
+    expr: &ConstExpr,
+) -> Result<BasicValueEnum<'a>> {
+    environment.builder.position_at_end(
+        environment
+            .wasker_init_block
+            .expect("should define wasker_init_block"),
+    );
+
+    let mut stack: Vec<BasicValueEnum<'a>> = Vec::new();
+    let mut reader = expr.get_operators_reader();
+    loop {
+        let op = reader.read()?;
+        log::trace!("- const expr {op:?}");
+        match op {
+            Operator::I32Const { value } => {
+                let v = environment
+                    .inkwell_types
+                    .i32_type
+                    .const_int(value as u64, false);
+                stack.push(v.as_basic_value_enum());
+            }
+            Operator::I64Const { value } => {
+                let v = environment
+                    .inkwell_types
+                    .i64_type
+                    .const_int(value as u64, false);
+                stack.push(v.as_basic_value_enum());
+            }
+            Operator::F32Const { value } => {
+                let bits = environment
+                    .inkwell_types
+                    .i32_type
+                    .const_int(value.bits() as u64, false);
+                let v =
+                    environment
+                        .builder
+                        .build_bitcast(bits, environment.inkwell_types.f32_type, "");
+                stack.push(v);
+            }
+            Operator::F64Const { value } => {
+                let bits = environment
+                    .inkwell_types
+                    .i64_type
+                    .const_int(value.bits(), false);
+                let v =
+                    environment
+                        .builder
+                        .build_bitcast(bits, environment.inkwell_types.f64_type, "");
+                stack.push(v);
+            }
+            Operator::GlobalGet { global_index } => {
+                let value = match &environment.global[global_index as usize] {
+                    Global::Const { value } => *value,
+                    Global::Mut { ptr_to_value, ty } => environment.builder.build_load(
+                        *ty,
+                        ptr_to_value.as_pointer_value(),
+                        "global_get",
+                    ),
+                };
+                stack.push(value);
+            }
+            Operator::RefNull { .. } => {
+                stack.push(
+                    environment
+                        .inkwell_types
+                        .i8_ptr_type
+                        .const_null()
+                        .as_basic_value_enum(),
+                );
+            }
+            Operator::RefFunc { function_index } => {
+                if function_index as usize >= environment.function_list.len() {
+                    bail!("ref.func {function_index} before functions are defined");
+                }
+                let func = environment.tailcc_function(function_index);
+                stack.push(
+                    func.as_global_value()
+                        .as_pointer_value()
+                        .as_basic_value_enum(),
+                );
+            }
+            // extended-const
+            Operator::I32Add | Operator::I64Add => {
+                let (v1, v2) = pop2(&mut stack)?;
+                let res = environment.builder.build_int_add(v1, v2, "const_add");
+                stack.push(res.as_basic_value_enum());
+            }
+            Operator::I32Sub | Operator::I64Sub => {
+                let (v1, v2) = pop2(&mut stack)?;
+                let res = environment.builder.build_int_sub(v1, v2, "const_sub");
+                stack.push(res.as_basic_value_enum());
+            }
+            Operator::I32Mul | Operator::I64Mul => {
+                let (v1, v2) = pop2(&mut stack)?;
+                let res = environment.builder.build_int_mul(v1, v2, "const_mul");
+                stack.push(res.as_basic_value_enum());
+            }
+            Operator::End => break,
+            other => {
+                bail!("Unsupported const expr operator {other:?}");
+            }
+        }
+    }
+    stack
+        .pop()
+        .ok_or_else(|| anyhow!("const expr has no value"))
+}
+
+/// Whether the value was folded into a constant at compile time.
+pub fn is_const(value: &BasicValueEnum) -> bool {
+    match value {
+        BasicValueEnum::IntValue(v) => v.is_const(),
+        BasicValueEnum::FloatValue(v) => v.is_const(),
+        BasicValueEnum::PointerValue(v) => v.is_const(),
+        _ => false,
+    }
+}
+
+fn pop2<'a>(stack: &mut Vec<BasicValueEnum<'a>>) -> Result<(IntValue<'a>, IntValue<'a>)> {
+    let v2 = stack
+        .pop()
+        .ok_or_else(|| anyhow!("const expr stack empty"))?;
+    let v1 = stack
+        .pop()
+        .ok_or_else(|| anyhow!("const expr stack empty"))?;
+    Ok((v1.into_int_value(), v2.into_int_value()))
+}
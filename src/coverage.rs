@@ -0,0 +1,96 @@
+//! `coverage` counts how many times each function or block of the guest ran.
+//!
+//! With `--coverage=function`, each function has a counter incremented at its entry.
+//! With `--coverage=block`, the code following `loop`, `if`, `else`, `end`, `br_if`, `catch` and
+//! `catch_all` gets a counter too. Each counter is a record in the ELF section `wasker_coverage`,
+//! which the host walks with the symbols the linker defines around it.
+//!
+//! ```c
+//! struct wasker_coverage_record {
+//!     uint64_t count;
+//!     uint32_t func_idx; // Wasm function index
+//!     uint32_t offset;   // byte offset in the module of the function body or the first instruction of the block
+//! };
+//! extern struct wasker_coverage_record __start_wasker_coverage[], __stop_wasker_coverage[];
+//! ```
+//!
+//! Records aren't sorted. Counters aren't atomic, so they are approximate with multiple threads.
+
+use clap::ValueEnum;
+use inkwell::{module::Linkage, values::GlobalValue, AddressSpace, GlobalVisibility};
+use wasmparser::Operator;
+
+use crate::environment::Environment;
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Coverage {
+    /// Count function entries
+    Function,
+    /// Count function entries and blocks
+    Block,
+}
+
+/// Whether the code following `op` starts a block with its own counter.
+pub(crate) fn starts_block(op: &Operator) -> bool {
+    matches!(
+        op,
+        Operator::Loop { .. }
+            | Operator::If { .. }
+            | Operator::Else
+            | Operator::End
+            | Operator::BrIf { .. }
+            | Operator::Catch { .. }
+            | Operator::CatchAll
+    )
+}
+
+// Prefix of the names of records
+const RECORD_PREFIX: &str = "wasker_coverage.";
+
+/// Whether `global` is a record of a counter.
+/// Records are defined by the partition that compiles their function.
+pub(crate) fn is_record(global: GlobalValue) -> bool {
+    global
+        .get_name()
+        .to_bytes()
+        .starts_with(RECORD_PREFIX.as_bytes())
+}
+
+/// Increment a new counter for the code of the current function at `offset` in the module.
+pub(crate) fn gen_counter(environment: &mut Environment<'_, '_>, offset: usize) {
+    let i64_type = environment.inkwell_types.i64_type;
+    let i32_type = environment.inkwell_types.i32_type;
+    let func_idx = environment.current_function_idx;
+    let record_type = environment
+        .context
+        .struct_type(&[i64_type.into(), i32_type.into(), i32_type.into()], false);
+
+    // The host finds records through the section, not by their names.
+    // Records are external under stable names, so that no pass drops them as unreferenced.
+    let record = environment.module.add_global(
+        record_type,
+        Some(AddressSpace::default()),
+        &format!("{RECORD_PREFIX}{func_idx}.{offset}"),
+    );
+    record.set_initializer(&record_type.const_named_struct(&[
+        i64_type.const_zero().into(),
+        i32_type.const_int(func_idx as u64, false).into(),
+        i32_type.const_int(offset as u64, false).into(),
+    ]));
+    record.set_linkage(Linkage::External);
+    record.set_visibility(GlobalVisibility::Hidden);
+    record.set_alignment(8);
+    record.set_section("wasker_coverage");
+
+    // count is the first field
+    let count_ptr = record.as_pointer_value();
+    let count = environment
+        .builder
+        .build_load(i64_type, count_ptr, "coverage_count")
+        .into_int_value();
+    let count =
+        environment
+            .builder
+            .build_int_add(count, i64_type.const_int(1, false), "coverage_count");
+    environment.builder.build_store(count_ptr, count);
+}
@@ -0,0 +1,132 @@
+//! `debug_info` emits DWARF which maps native code back to the Wasm module.
+//!
+//! Each defined function gets a subprogram named after the name section or its export.
+//! The line of each instruction is the byte offset of the Wasm instruction in the module,
+//! so that a native address resolves to `<input>:<offset>` in gdb or addr2line.
+//! The `.debug_*` custom sections embedded in the module are not translated yet.
+
+use inkwell::{
+    builder::Builder,
+    context::Context,
+    debug_info::{
+        AsDIScope, DICompileUnit, DIFlags, DIFlagsConstants, DISubprogram, DWARFEmissionKind,
+        DWARFSourceLanguage, DebugInfoBuilder,
+    },
+    module::{FlagBehavior, Module},
+    values::FunctionValue,
+};
+use std::path::Path;
+
+pub struct DebugInfo<'a> {
+    context: &'a Context,
+    builder: DebugInfoBuilder<'a>,
+    compile_unit: DICompileUnit<'a>,
+
+    // Subprogram of the function being translated
+    subprogram: Option<DISubprogram<'a>>,
+}
+
+impl<'a> DebugInfo<'a> {
+    pub fn new(context: &'a Context, module: &Module<'a>, input_file: &Path) -> Self {
+        let filename = input_file
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("module.wasm");
+        let directory = input_file
+            .parent()
+            .and_then(|dir| dir.to_str())
+            .unwrap_or(".");
+
+        module.add_basic_value_flag(
+            "Debug Info Version",
+            FlagBehavior::Warning,
+            context.i32_type().const_int(3, false),
+        );
+        module.add_basic_value_flag(
+            "Dwarf Version",
+            FlagBehavior::Warning,
+            context.i32_type().const_int(4, false),
+        );
+
+        let (builder, compile_unit) = module.create_debug_info_builder(
+            true,
+            // DWARF has no language code for Wasm
+            DWARFSourceLanguage::C,
+            filename,
+            directory,
+            "wasker",
+            true,
+            "",
+            0,
+            "",
+            DWARFEmissionKind::Full,
+            0,
+            false,
+            false,
+            "",
+            "",
+        );
+
+        Self {
+            context,
+            builder,
+            compile_unit,
+            subprogram: None,
+        }
+    }
+
+    /// Attach a subprogram to `function` and locate following instructions in it.
+    pub fn enter_function(
+        &mut self,
+        builder: &Builder<'a>,
+        function: FunctionValue<'a>,
+        name: &str,
+        offset: usize,
+    ) {
+        let file = self.compile_unit.get_file();
+        let subroutine_type = self
+            .builder
+            .create_subroutine_type(file, None, &[], DIFlags::PUBLIC);
+        let line = offset as u32;
+        let subprogram = self.builder.create_function(
+            self.compile_unit.as_debug_info_scope(),
+            name,
+            function.get_name().to_str().ok(),
+            file,
+            line,
+            subroutine_type,
+            false,
+            true,
+            line,
+            DIFlags::PUBLIC,
+            true,
+        );
+        function.set_subprogram(subprogram);
+        self.subprogram = Some(subprogram);
+        self.set_location(builder, offset);
+    }
+
+    /// Locate following instructions at `offset` of the current function.
+    pub fn set_location(&self, builder: &Builder<'a>, offset: usize) {
+        let subprogram = self.subprogram.expect("should enter function");
+        let location = self.builder.create_debug_location(
+            self.context,
+            offset as u32,
+            0,
+            subprogram.as_debug_info_scope(),
+            None,
+        );
+        builder.set_current_debug_location(self.context, location);
+    }
+
+    /// Stop locating instructions, e.g. before building wasker_main.
+    pub fn leave_function(&mut self, builder: &Builder<'a>) {
+        builder.unset_current_debug_location();
+        self.subprogram = None;
+    }
+
+    /// Resolve forward references. Must be called before emitting the object.
+    pub fn finalize(&self) {
+        self.builder.finalize();
+    }
+}
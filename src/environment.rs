@@ -6,13 +6,18 @@ use inkwell::{
     builder::Builder,
     context::Context,
     module::Module,
-    types::{BasicTypeEnum, FunctionType},
-    values::{BasicValueEnum, FunctionValue, GlobalValue, IntValue},
+    types::{BasicType, BasicTypeEnum, FunctionType, StructType},
+    values::{BasicValueEnum, FunctionValue, GlobalValue, IntValue, PointerValue},
 };
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
 
+use crate::compiler::Args;
+use crate::debug_info::DebugInfo;
+use crate::import_adapter::ParamKind;
 use crate::inkwell::{InkwellInsts, InkwellTypes};
 use crate::insts::control::{ControlFrame, UnreachableReason};
+use crate::partition::Partition;
 
 pub enum Global<'a> {
     Mut {
@@ -24,10 +29,38 @@ pub enum Global<'a> {
     },
 }
 
+/// Memory requirements of a dynamic-linking module, read from its `dylink.0` section.
+#[derive(Debug, Default)]
+pub struct DylinkInfo {
+    pub memory_size: u32,
+    pub memory_alignment: u32,
+    pub table_size: u32,
+    pub table_alignment: u32,
+    pub needed: Vec<String>,
+}
+
+/// Names from the `name` custom section, keyed by Wasm index.
+#[derive(Debug, Default)]
+pub struct Names {
+    pub functions: HashMap<u32, String>,
+    // function index -> local index -> name
+    pub locals: HashMap<u32, HashMap<u32, String>>,
+    // function index -> label index -> name
+    pub labels: HashMap<u32, HashMap<u32, String>>,
+    pub globals: HashMap<u32, String>,
+    pub data: HashMap<u32, String>,
+}
+
 pub struct Environment<'a, 'b> {
     // Output dir
     pub output_file: &'b Path,
 
+    // Compile options
+    pub args: &'b Args,
+
+    // Functions compiled into this module, None for the whole module
+    pub partition: Option<Partition>,
+
     // Inkwell code generator
     pub context: &'a Context,
     pub module: &'b Module<'a>,
@@ -39,12 +72,24 @@ pub struct Environment<'a, 'b> {
 
     // List of all signatures
     pub function_signature_list: Vec<FunctionType<'a>>,
+    // Canonical id of each signature. Structurally equal signatures share the same id.
+    pub function_signature_ids: Vec<u32>,
 
-    // List of functions
+    // List of functions. Functions defined by the module use tailcc.
     pub function_list: Vec<FunctionValue<'a>>,
     pub function_list_signature: Vec<u32>,
     pub function_list_name: Vec<String>,
 
+    // Parameters of imported functions translated at the call site
+    pub import_adapters: HashMap<u32, Vec<ParamKind>>,
+
+    // Functions exported to the host, which are entered through C thunks under their export names
+    pub exported_functions: HashSet<u32>,
+    // Functions reachable through the table or `ref.func`, which are called with tailcc
+    pub address_taken_functions: HashSet<u32>,
+    // tailcc thunks of address-taken imports
+    pub import_thunks: HashMap<u32, FunctionValue<'a>>,
+
     // Stack for Wasm binary
     pub stack: Vec<BasicValueEnum<'a>>,
 
@@ -56,6 +101,10 @@ pub struct Environment<'a, 'b> {
 
     pub current_function_idx: u32,
 
+    // Offsets of the code section are reported relative to a second parse,
+    // this converts them back to offsets in the module
+    pub code_offset_delta: usize,
+
     // ControlFrame
     pub control_frames: Vec<ControlFrame<'a>>,
 
@@ -74,15 +123,38 @@ pub struct Environment<'a, 'b> {
 
     // Table
     pub global_table: Option<GlobalValue<'a>>,
+    pub table_size: u32,
 
     // Memory
     pub global_memory_size: Option<GlobalValue<'a>>,
     pub fn_memory_grow: Option<FunctionValue<'a>>,
+
+    // Dynamic linking
+    pub dylink: Option<DylinkInfo>,
+
+    // Name section, parsed in advance
+    pub names: Names,
+
+    // Tags
+    pub tag_list_signature: Vec<u32>,
+    pub tag_list_name: Vec<String>,
+
+    // Exception handling
+    pub exception_handling: bool,
+    pub exception_tag: Option<GlobalValue<'a>>,
+    pub exception_payload: Option<GlobalValue<'a>>,
+    pub exception_payload_len: u32,
+    // Caution: Reset for each function
+    pub exception_unwind_block: Option<BasicBlock<'a>>,
+
+    // DWARF, only with --debug-info
+    pub debug_info: Option<DebugInfo<'a>>,
 }
 
 impl<'a, 'b> Environment<'a, 'b> {
     pub fn new(
         output_file: &'b Path,
+        args: &'b Args,
         context: &'a Context,
         module: &'b Module<'a>,
         builder: Builder<'a>,
@@ -91,20 +163,28 @@ impl<'a, 'b> Environment<'a, 'b> {
     ) -> Self {
         Self {
             output_file,
+            args,
+            partition: None,
             context,
             module,
             builder,
             inkwell_types,
             inkwell_insts,
             function_signature_list: Vec::new(),
+            function_signature_ids: Vec::new(),
             function_list: Vec::new(),
             function_list_signature: Vec::new(),
             function_list_name: Vec::new(),
+            import_adapters: HashMap::new(),
+            exported_functions: HashSet::new(),
+            address_taken_functions: HashSet::new(),
+            import_thunks: HashMap::new(),
             stack: Vec::new(),
             global: Vec::new(),
             import_section_size: 0,
             function_section_size: 0,
             current_function_idx: u32::MAX,
+            code_offset_delta: 0,
             control_frames: Vec::new(),
             wasker_init_block: None,
             wasker_main_block: None,
@@ -114,11 +194,64 @@ impl<'a, 'b> Environment<'a, 'b> {
             unreachable_depth: 0,
             unreachable_reason: UnreachableReason::Reachable,
             global_table: None,
+            table_size: 0,
             global_memory_size: None,
+            dylink: None,
+            names: Names::default(),
             fn_memory_grow: None,
+            tag_list_signature: Vec::new(),
+            tag_list_name: Vec::new(),
+            exception_handling: false,
+            exception_tag: None,
+            exception_payload: None,
+            exception_payload_len: 0,
+            exception_unwind_block: None,
+            debug_info: args
+                .debug_info
+                .then(|| DebugInfo::new(context, module, &args.input_file)),
         }
     }
 
+    /// Whether the body of function `idx` is generated in this module.
+    pub fn compiles_function(&self, idx: u32) -> bool {
+        self.partition
+            .as_ref()
+            .map_or(true, |partition| partition.functions.contains(&idx))
+    }
+
+    /// Whether this module defines module-level state such as globals, the table and `wasker_main`.
+    pub fn is_primary(&self) -> bool {
+        self.partition
+            .as_ref()
+            .map_or(true, |partition| partition.index == 0)
+    }
+
+    /// Function stored in the table or a funcref for function `idx`, which is called with tailcc.
+    pub fn tailcc_function(&self, idx: u32) -> FunctionValue<'a> {
+        match self.import_thunks.get(&idx) {
+            Some(thunk) => *thunk,
+            None => self.function_list[idx as usize],
+        }
+    }
+
+    /// Allocate a local variable in the entry block, so that it is allocated once per call.
+    pub fn build_entry_alloca<T: BasicType<'a>>(&self, ty: T, name: &str) -> PointerValue<'a> {
+        let current_block = self
+            .builder
+            .get_insert_block()
+            .expect("fail to get_insert_block");
+        let entry_block = self.function_list[self.current_function_idx as usize]
+            .get_first_basic_block()
+            .expect("fail to get entry block");
+        match entry_block.get_first_instruction() {
+            Some(inst) => self.builder.position_before(&inst),
+            None => self.builder.position_at_end(entry_block),
+        }
+        let alloca = self.builder.build_alloca(ty, name);
+        self.builder.position_at_end(current_block);
+        alloca
+    }
+
     /// Restore the stack to the specified size.
     pub fn reset_stack(&mut self, stack_size: usize) {
         self.stack.truncate(stack_size);
@@ -145,6 +278,17 @@ impl<'a, 'b> Environment<'a, 'b> {
         &self.control_frames[frame_len - 1]
     }
 
+    /// Type of table elements, a pair of function pointer and canonical signature id.
+    pub fn table_entry_type(&self) -> StructType<'a> {
+        self.context.struct_type(
+            &[
+                self.inkwell_types.i8_ptr_type.into(),
+                self.inkwell_types.i32_type.into(),
+            ],
+            false,
+        )
+    }
+
     /// Pop two values from the stack.
     pub fn pop2(&mut self) -> (BasicValueEnum<'a>, BasicValueEnum<'a>) {
         let v2 = self.stack.pop().expect("stack empty");
@@ -0,0 +1,92 @@
+//! `epoch` lets the host interrupt guest code which never calls out, e.g. a runaway `loop`.
+//!
+//! With `--epoch-interruption`, function entries and loop headers compare the host-visible epoch
+//! with the deadline, and call the unresolved hook `wasker_interrupt` once the deadline has passed.
+//! The host advances the epoch from a timer or another thread, and the hook may switch to
+//! another green thread, move the deadline and return, or never return to stop the guest.
+//!
+//! ```c
+//! volatile uint64_t wasker_epoch;
+//! uint64_t wasker_epoch_deadline;
+//! void wasker_interrupt(void);
+//! ```
+//!
+//! The hook is called again at the next check unless it moves the deadline past the epoch.
+
+use anyhow::{anyhow, Result};
+use inkwell::{
+    module::Linkage,
+    values::{BasicValue, GlobalValue, IntValue},
+    IntPredicate,
+};
+
+use crate::environment::Environment;
+
+/// Call `wasker_interrupt` if `wasker_epoch` has reached `wasker_epoch_deadline`, then continue in a new block.
+pub(crate) fn gen_epoch_check(environment: &mut Environment<'_, '_>) -> Result<()> {
+    let current_fn = environment
+        .builder
+        .get_insert_block()
+        .and_then(|block| block.get_parent())
+        .expect("fail to get current function");
+    let epoch = load_volatile(environment, "wasker_epoch")?;
+    let deadline = load_volatile(environment, "wasker_epoch_deadline")?;
+    let passed = environment.builder.build_int_compare(
+        IntPredicate::UGE,
+        epoch,
+        deadline,
+        "deadline_passed",
+    );
+
+    let interrupt_block = environment
+        .context
+        .append_basic_block(current_fn, "interrupt");
+    let cont_block = environment
+        .context
+        .append_basic_block(current_fn, "interrupt_cont");
+    environment
+        .builder
+        .build_conditional_branch(passed, interrupt_block, cont_block);
+
+    environment.builder.position_at_end(interrupt_block);
+    let hook = environment
+        .module
+        .get_function("wasker_interrupt")
+        .unwrap_or_else(|| {
+            let fn_type = environment.inkwell_types.void_type.fn_type(&[], false);
+            environment
+                .module
+                .add_function("wasker_interrupt", fn_type, Some(Linkage::External))
+        });
+    environment.builder.build_call(hook, &[], "");
+    environment.builder.build_unconditional_branch(cont_block);
+
+    environment.builder.position_at_end(cont_block);
+    Ok(())
+}
+
+// Load the host-defined u64 `name`. The load is volatile, as the host may update it at any time.
+fn load_volatile<'a>(environment: &Environment<'a, '_>, name: &str) -> Result<IntValue<'a>> {
+    let global = host_global(environment, name);
+    let value = environment.builder.build_load(
+        environment.inkwell_types.i64_type,
+        global.as_pointer_value(),
+        name,
+    );
+    value
+        .as_instruction_value()
+        .expect("load is an instruction")
+        .set_volatile(true)
+        .map_err(|err| anyhow!(err))?;
+    Ok(value.into_int_value())
+}
+
+fn host_global<'a>(environment: &Environment<'a, '_>, name: &str) -> GlobalValue<'a> {
+    environment.module.get_global(name).unwrap_or_else(|| {
+        let global = environment
+            .module
+            .add_global(environment.inkwell_types.i64_type, None, name);
+        global.set_linkage(Linkage::External);
+        global
+    })
+}
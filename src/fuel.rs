@@ -0,0 +1,264 @@
+//! `fuel` bounds how long guest code runs by charging fuel for executed instructions.
+//!
+//! With `--fuel`, the cost of instructions is summed up to the next control instruction
+//! and subtracted from the host-defined counter there, so that every loop iteration and call is charged.
+//! When the counter goes negative, the unresolved hook `wasker_out_of_fuel` is called.
+//! The hook may refill the counter and return to continue, or never return to stop the guest.
+//!
+//! ```c
+//! int64_t wasker_fuel;
+//! void wasker_out_of_fuel(void);
+//! ```
+//!
+//! The counter isn't atomic, so it must not be shared by guests running on multiple threads.
+//! `nop`, `drop`, `block`, `loop`, `else` and `end` are free. Other instructions cost 1 by default,
+//! which can be changed per class with `--fuel-cost CLASS=N`.
+
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use inkwell::{module::Linkage, values::GlobalValue, IntPredicate};
+use std::str::FromStr;
+use wasmparser::Operator;
+
+use crate::environment::Environment;
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FuelClass {
+    /// Branches, `if`, `return`, `select`, `unreachable` and exception instructions
+    Control,
+    /// Direct and indirect calls
+    Call,
+    /// Loads, stores, and memory and table instructions
+    Memory,
+    /// Accesses to locals and globals
+    Variable,
+    /// All other instructions
+    Numeric,
+}
+
+/// Cost of a class of instructions, written as `CLASS=N`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FuelCost {
+    pub class: FuelClass,
+    pub cost: u32,
+}
+
+impl FromStr for FuelCost {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (class, cost) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("expected CLASS=N"))?;
+        Ok(Self {
+            class: <FuelClass as ValueEnum>::from_str(class, true).map_err(|err| anyhow!(err))?,
+            cost: cost
+                .parse()
+                .with_context(|| format!("invalid cost `{cost}`"))?,
+        })
+    }
+}
+
+/// Fuel charged so far in the current block of a function.
+pub struct FuelMeter {
+    costs: [u64; 5],
+    pending: u64,
+}
+
+impl FuelMeter {
+    pub fn new(costs: &[FuelCost]) -> Self {
+        let mut meter = Self {
+            costs: [1; 5],
+            pending: 0,
+        };
+        // Later options override earlier ones
+        for cost in costs {
+            meter.costs[cost.class as usize] = cost.cost as u64;
+        }
+        meter
+    }
+
+    /// Charge `op`, and subtract the pending fuel from the counter if `op` is a control instruction.
+    /// Must be called before `op` is translated.
+    pub fn charge(&mut self, environment: &mut Environment<'_, '_>, op: &Operator) -> Result<()> {
+        // Instructions under unreachable are never executed
+        if environment.unreachable_depth != 0 {
+            return Ok(());
+        }
+        if let Some(class) = classify(op) {
+            self.pending += self.costs[class as usize];
+        }
+        if ends_block(op) && self.pending != 0 {
+            gen_consume(environment, self.pending).context("error gen fuel check")?;
+            self.pending = 0;
+        }
+        Ok(())
+    }
+}
+
+// Class of `op`, or None if it is free
+fn classify(op: &Operator) -> Option<FuelClass> {
+    match op {
+        Operator::Nop
+        | Operator::Drop
+        | Operator::Block { .. }
+        | Operator::Loop { .. }
+        | Operator::Else
+        | Operator::End => None,
+        Operator::Call { .. }
+        | Operator::CallIndirect { .. }
+        | Operator::ReturnCall { .. }
+        | Operator::ReturnCallIndirect { .. } => Some(FuelClass::Call),
+        Operator::If { .. }
+        | Operator::Br { .. }
+        | Operator::BrIf { .. }
+        | Operator::BrTable { .. }
+        | Operator::Return
+        | Operator::Select
+        | Operator::TypedSelect { .. }
+        | Operator::Unreachable
+        | Operator::Try { .. }
+        | Operator::Catch { .. }
+        | Operator::CatchAll
+        | Operator::Throw { .. }
+        | Operator::Rethrow { .. }
+        | Operator::Delegate { .. } => Some(FuelClass::Control),
+        Operator::LocalGet { .. }
+        | Operator::LocalSet { .. }
+        | Operator::LocalTee { .. }
+        | Operator::GlobalGet { .. }
+        | Operator::GlobalSet { .. } => Some(FuelClass::Variable),
+        Operator::I32Load { .. }
+        | Operator::I64Load { .. }
+        | Operator::F32Load { .. }
+        | Operator::F64Load { .. }
+        | Operator::I32Load8S { .. }
+        | Operator::I32Load8U { .. }
+        | Operator::I32Load16S { .. }
+        | Operator::I32Load16U { .. }
+        | Operator::I64Load8S { .. }
+        | Operator::I64Load8U { .. }
+        | Operator::I64Load16S { .. }
+        | Operator::I64Load16U { .. }
+        | Operator::I64Load32S { .. }
+        | Operator::I64Load32U { .. }
+        | Operator::I32Store { .. }
+        | Operator::I64Store { .. }
+        | Operator::F32Store { .. }
+        | Operator::F64Store { .. }
+        | Operator::I32Store8 { .. }
+        | Operator::I32Store16 { .. }
+        | Operator::I64Store8 { .. }
+        | Operator::I64Store16 { .. }
+        | Operator::I64Store32 { .. }
+        | Operator::MemorySize { .. }
+        | Operator::MemoryGrow { .. }
+        | Operator::MemoryCopy { .. }
+        | Operator::MemoryFill { .. }
+        | Operator::MemoryInit { .. }
+        | Operator::DataDrop { .. }
+        | Operator::TableGet { .. }
+        | Operator::TableSet { .. }
+        | Operator::TableSize { .. }
+        | Operator::TableGrow { .. }
+        | Operator::TableFill { .. }
+        | Operator::TableCopy { .. }
+        | Operator::TableInit { .. }
+        | Operator::ElemDrop { .. } => Some(FuelClass::Memory),
+        _ => Some(FuelClass::Numeric),
+    }
+}
+
+// Whether `op` may transfer control, or is the destination of a branch
+fn ends_block(op: &Operator) -> bool {
+    matches!(
+        op,
+        Operator::Block { .. }
+            | Operator::Loop { .. }
+            | Operator::If { .. }
+            | Operator::Else
+            | Operator::End
+            | Operator::Br { .. }
+            | Operator::BrIf { .. }
+            | Operator::BrTable { .. }
+            | Operator::Return
+            | Operator::Unreachable
+            | Operator::Call { .. }
+            | Operator::CallIndirect { .. }
+            | Operator::ReturnCall { .. }
+            | Operator::ReturnCallIndirect { .. }
+            | Operator::Try { .. }
+            | Operator::Catch { .. }
+            | Operator::CatchAll
+            | Operator::Throw { .. }
+            | Operator::Rethrow { .. }
+            | Operator::Delegate { .. }
+    )
+}
+
+// Subtract `cost` from wasker_fuel, and call wasker_out_of_fuel if it goes negative
+fn gen_consume(environment: &mut Environment<'_, '_>, cost: u64) -> Result<()> {
+    let current_fn = environment
+        .builder
+        .get_insert_block()
+        .and_then(|block| block.get_parent())
+        .expect("fail to get current function");
+    let i64_type = environment.inkwell_types.i64_type;
+    let fuel = fuel_global(environment).as_pointer_value();
+
+    let old = environment
+        .builder
+        .build_load(i64_type, fuel, "fuel")
+        .into_int_value();
+    let new = environment
+        .builder
+        .build_int_sub(old, i64_type.const_int(cost, false), "fuel_left");
+    environment.builder.build_store(fuel, new);
+    let exhausted = environment.builder.build_int_compare(
+        IntPredicate::SLT,
+        new,
+        i64_type.const_zero(),
+        "fuel_exhausted",
+    );
+
+    let hook_block = environment
+        .context
+        .append_basic_block(current_fn, "out_of_fuel");
+    let cont_block = environment
+        .context
+        .append_basic_block(current_fn, "fuel_cont");
+    environment
+        .builder
+        .build_conditional_branch(exhausted, hook_block, cont_block);
+
+    environment.builder.position_at_end(hook_block);
+    let hook = environment
+        .module
+        .get_function("wasker_out_of_fuel")
+        .unwrap_or_else(|| {
+            let fn_type = environment.inkwell_types.void_type.fn_type(&[], false);
+            environment
+                .module
+                .add_function("wasker_out_of_fuel", fn_type, Some(Linkage::External))
+        });
+    environment.builder.build_call(hook, &[], "");
+    environment.builder.build_unconditional_branch(cont_block);
+
+    environment.builder.position_at_end(cont_block);
+    Ok(())
+}
+
+fn fuel_global<'a>(environment: &Environment<'a, '_>) -> GlobalValue<'a> {
+    environment
+        .module
+        .get_global("wasker_fuel")
+        .unwrap_or_else(|| {
+            let global = environment.module.add_global(
+                environment.inkwell_types.i64_type,
+                None,
+                "wasker_fuel",
+            );
+            global.set_linkage(Linkage::External);
+            global
+        })
+}
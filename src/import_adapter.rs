@@ -0,0 +1,303 @@
+//! `import_adapter` translates arguments of imported functions at the call site.
+//!
+//! Imports receive Wasm values unchanged, so the host has to translate guest offsets by itself.
+//! An adapter marks parameters of an import as guest pointers instead,
+//! which are passed to the host as native pointers into the linear memory.
+//!
+//! An adapter is written as `<module> <name> <param>...`, where each param is one of
+//! - `val`: passed unchanged
+//! - `ptr`: guest offset, passed as a native pointer
+//! - `len`: length of the preceding `ptr`. The call traps if the range is out of the linear memory.
+//!
+//! An adapter file has one adapter per line. Empty lines and lines starting with `#` are ignored.
+//! No import is adapted by default.
+//!
+//! With `--import-context`, every import also receives a pointer to the context below as its first
+//! argument, so that host functions can access the linear memory without global state.
+//! The context lives on the stack of the caller and is valid only during the call.
+//!
+//! ```c
+//! struct wasker_import_context {
+//!     uint8_t *memory_base;
+//!     uint64_t memory_size; // in bytes
+//! };
+//! ```
+
+use anyhow::{anyhow, bail, Context, Result};
+use inkwell::{
+    types::{BasicMetadataTypeEnum, BasicType, FunctionType},
+    values::{BasicValueEnum, IntValue},
+    IntPredicate,
+};
+use std::{collections::HashMap, str::FromStr};
+
+use crate::compiler::Args;
+use crate::environment::Environment;
+use crate::insts::trap::{self, TrapKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamKind {
+    Val,
+    Ptr,
+    Len,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportAdapter {
+    pub module: String,
+    pub name: String,
+    pub params: Vec<ParamKind>,
+}
+
+impl FromStr for ImportAdapter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut words = s.split_whitespace();
+        let module = words.next().ok_or_else(|| anyhow!("missing module name"))?;
+        let name = words.next().ok_or_else(|| anyhow!("missing import name"))?;
+        let params = words
+            .map(|word| match word {
+                "val" => Ok(ParamKind::Val),
+                "ptr" => Ok(ParamKind::Ptr),
+                "len" => Ok(ParamKind::Len),
+                other => bail!("unknown param kind `{other}`"),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        for (i, param) in params.iter().enumerate() {
+            if *param == ParamKind::Len && (i == 0 || params[i - 1] != ParamKind::Ptr) {
+                bail!("`len` must follow `ptr`");
+            }
+        }
+        Ok(Self {
+            module: module.to_string(),
+            name: name.to_string(),
+            params,
+        })
+    }
+}
+
+/// Parse an adapter file.
+pub fn parse_import_adapters(text: &str) -> Result<Vec<ImportAdapter>> {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| (i, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(i, line)| {
+            line.parse()
+                .with_context(|| format!("import adapter at line {}", i + 1))
+        })
+        .collect()
+}
+
+/// Collect adapters from the adapter file and the command line, in this order.
+/// A later adapter of the same import replaces an earlier one.
+pub(crate) fn resolve_import_adapters(
+    args: &Args,
+) -> Result<HashMap<(String, String), Vec<ParamKind>>> {
+    let mut adapters = Vec::new();
+    if let Some(path) = &args.import_adapter_file {
+        let text = std::fs::read_to_string(path).context("fail read import adapter file")?;
+        adapters.extend(parse_import_adapters(&text)?);
+    }
+    adapters.extend(args.import_adapters.iter().cloned());
+    Ok(adapters
+        .into_iter()
+        .map(|adapter| ((adapter.module, adapter.name), adapter.params))
+        .collect())
+}
+
+/// Signature of an import taking the context as its first parameter.
+pub(crate) fn with_context_param<'a>(
+    environment: &Environment<'a, '_>,
+    signature: FunctionType<'a>,
+) -> FunctionType<'a> {
+    let param_types: Vec<BasicMetadataTypeEnum> =
+        std::iter::once(environment.inkwell_types.i8_ptr_type.into())
+            .chain(signature.get_param_types().into_iter().map(Into::into))
+            .collect();
+    match signature.get_return_type() {
+        Some(ret) => ret.fn_type(&param_types, false),
+        None => environment
+            .inkwell_types
+            .void_type
+            .fn_type(&param_types, false),
+    }
+}
+
+/// Fill a context of the current call and return a pointer to it.
+/// The context is filled at each call, so that it reflects `memory.grow` without tracking it.
+/// Each call gets its own context, so that imports may call back into the guest.
+pub(crate) fn build_import_context<'a>(
+    environment: &mut Environment<'a, '_>,
+) -> BasicValueEnum<'a> {
+    let context_type = environment.context.struct_type(
+        &[
+            environment.inkwell_types.i8_ptr_type.into(),
+            environment.inkwell_types.i64_type.into(),
+        ],
+        false,
+    );
+    let context = environment.build_entry_alloca(context_type, "import_context");
+
+    // memory_base
+    let linear_memory_base = environment.builder.build_load(
+        environment.inkwell_types.i8_ptr_type,
+        environment
+            .linear_memory_offset_global
+            .expect("should define linear_memory_offset_global")
+            .as_pointer_value(),
+        "linm_local",
+    );
+    let base_field = environment
+        .builder
+        .build_struct_gep(context_type, context, 0, "context_memory_base")
+        .expect("fail build_struct_gep");
+    environment
+        .builder
+        .build_store(base_field, linear_memory_base);
+
+    // memory_size
+    let mem_bytes = match environment.global_memory_size {
+        Some(global_memory_size) => {
+            let pages = environment.builder.build_load(
+                environment.inkwell_types.i32_type,
+                global_memory_size.as_pointer_value(),
+                "mem_size",
+            );
+            let pages = environment.builder.build_int_z_extend(
+                pages.into_int_value(),
+                environment.inkwell_types.i64_type,
+                "mem_size_i64",
+            );
+            environment.builder.build_left_shift(
+                pages,
+                environment.inkwell_types.i64_type.const_int(16, false),
+                "mem_bytes",
+            )
+        }
+        None => environment.inkwell_types.i64_type.const_zero(),
+    };
+    let size_field = environment
+        .builder
+        .build_struct_gep(context_type, context, 1, "context_memory_size")
+        .expect("fail build_struct_gep");
+    environment.builder.build_store(size_field, mem_bytes);
+
+    context.into()
+}
+
+/// Signature of an adapted import as seen by the host. Guest pointers become native pointers.
+pub(crate) fn adapt_signature<'a>(
+    environment: &Environment<'a, '_>,
+    signature: FunctionType<'a>,
+    params: &[ParamKind],
+) -> FunctionType<'a> {
+    let param_types: Vec<BasicMetadataTypeEnum> = signature
+        .get_param_types()
+        .into_iter()
+        .zip(params)
+        .map(|(ty, param)| match param {
+            ParamKind::Ptr => environment.inkwell_types.i8_ptr_type.into(),
+            _ => ty.into(),
+        })
+        .collect();
+    match signature.get_return_type() {
+        Some(ret) => ret.fn_type(&param_types, false),
+        None => environment
+            .inkwell_types
+            .void_type
+            .fn_type(&param_types, false),
+    }
+}
+
+/// Translate arguments of a call to an adapted import.
+pub(crate) fn adapt_arguments<'a>(
+    environment: &mut Environment<'a, '_>,
+    params: &[ParamKind],
+    args: &mut [BasicValueEnum<'a>],
+) -> Result<()> {
+    for i in 0..params.len() {
+        if params[i] != ParamKind::Ptr {
+            continue;
+        }
+        let offset = to_i64(environment, args[i])?;
+
+        // Trap if [ptr, ptr + len) is out of the linear memory
+        if let (Some(ParamKind::Len), Some(global_memory_size)) =
+            (params.get(i + 1), environment.global_memory_size)
+        {
+            let len = to_i64(environment, args[i + 1])?;
+            let pages = environment.builder.build_load(
+                environment.inkwell_types.i32_type,
+                global_memory_size.as_pointer_value(),
+                "mem_size",
+            );
+            let pages = environment.builder.build_int_z_extend(
+                pages.into_int_value(),
+                environment.inkwell_types.i64_type,
+                "mem_size_i64",
+            );
+            let mem_bytes = environment.builder.build_left_shift(
+                pages,
+                environment.inkwell_types.i64_type.const_int(16, false),
+                "mem_bytes",
+            );
+            // offset + len may wrap with i64 params, so compare len with the remaining bytes
+            let offset_out = environment.builder.build_int_compare(
+                IntPredicate::UGT,
+                offset,
+                mem_bytes,
+                "offset_out_of_bounds",
+            );
+            let remaining = environment
+                .builder
+                .build_int_sub(mem_bytes, offset, "remaining");
+            let len_out = environment.builder.build_int_compare(
+                IntPredicate::UGT,
+                len,
+                remaining,
+                "len_out_of_bounds",
+            );
+            let out_of_bounds =
+                environment
+                    .builder
+                    .build_or(offset_out, len_out, "ptr_out_of_bounds");
+            trap::gen_trap_if(environment, out_of_bounds, TrapKind::MemoryOutOfBounds)?;
+        }
+
+        // base of the linear memory + offset
+        let linear_memory_base = environment.builder.build_load(
+            environment.inkwell_types.i8_ptr_type,
+            environment
+                .linear_memory_offset_global
+                .expect("should define linear_memory_offset_global")
+                .as_pointer_value(),
+            "linm_local",
+        );
+        let ptr = unsafe {
+            environment.builder.build_gep(
+                environment.inkwell_types.i8_type,
+                linear_memory_base.into_pointer_value(),
+                &[offset],
+                "guest_ptr",
+            )
+        };
+        args[i] = ptr.into();
+    }
+    Ok(())
+}
+
+fn to_i64<'a>(
+    environment: &Environment<'a, '_>,
+    value: BasicValueEnum<'a>,
+) -> Result<IntValue<'a>> {
+    let BasicValueEnum::IntValue(value) = value else {
+        bail!("import adapter: pointer and length must be integers");
+    };
+    Ok(environment.builder.build_int_z_extend_or_bit_cast(
+        value,
+        environment.inkwell_types.i64_type,
+        "to_i64",
+    ))
+}
@@ -46,16 +46,22 @@ pub struct InkwellInsts<'ctx> {
     pub floor_f64: FunctionValue<'ctx>,
     pub trunc_f32: FunctionValue<'ctx>,
     pub trunc_f64: FunctionValue<'ctx>,
-    pub nearbyint_f32: FunctionValue<'ctx>,
-    pub nearbyint_f64: FunctionValue<'ctx>,
+    pub roundeven_f32: FunctionValue<'ctx>,
+    pub roundeven_f64: FunctionValue<'ctx>,
     pub sqrt_f32: FunctionValue<'ctx>,
     pub sqrt_f64: FunctionValue<'ctx>,
-    pub minnum_f32: FunctionValue<'ctx>,
-    pub minnum_f64: FunctionValue<'ctx>,
-    pub maxnum_f32: FunctionValue<'ctx>,
-    pub maxnum_f64: FunctionValue<'ctx>,
     pub copysign_f32: FunctionValue<'ctx>,
     pub copysign_f64: FunctionValue<'ctx>,
+    pub fptosi_sat_i32_f32: FunctionValue<'ctx>,
+    pub fptoui_sat_i32_f32: FunctionValue<'ctx>,
+    pub fptosi_sat_i32_f64: FunctionValue<'ctx>,
+    pub fptoui_sat_i32_f64: FunctionValue<'ctx>,
+    pub fptosi_sat_i64_f32: FunctionValue<'ctx>,
+    pub fptoui_sat_i64_f32: FunctionValue<'ctx>,
+    pub fptosi_sat_i64_f64: FunctionValue<'ctx>,
+    pub fptoui_sat_i64_f64: FunctionValue<'ctx>,
+    pub trap: FunctionValue<'ctx>,
+    pub stacksave: FunctionValue<'ctx>,
 }
 
 impl<'ctx> InkwellTypes<'ctx> {
@@ -129,6 +135,7 @@ pub fn init_inkwell<'a>(
     let f64_ty_basic_md: BasicMetadataTypeEnum = f64_type.into();
 
     // function type
+    let ret_void = void_type.fn_type(&[], false);
     let ret_i32_take_i32_i1 = i32_type.fn_type(&[i32_ty_basic_md, i1_ty_basic_md], false);
     let ret_i64_take_i64_i1 = i64_type.fn_type(&[i64_ty_basic_md, i1_ty_basic_md], false);
     let ret_i32_take_i32 = i32_type.fn_type(&[i32_ty_basic_md], false);
@@ -137,6 +144,10 @@ pub fn init_inkwell<'a>(
     let ret_f32_take_f32 = f32_type.fn_type(&[f32_ty_basic_md], false);
     let ret_f32_take_f32_f32 = f32_type.fn_type(&[f32_ty_basic_md, f32_ty_basic_md], false);
     let ret_f64_take_f64_f64 = f64_type.fn_type(&[f64_ty_basic_md, f64_ty_basic_md], false);
+    let ret_i32_take_f32 = i32_type.fn_type(&[f32_ty_basic_md], false);
+    let ret_i32_take_f64 = i32_type.fn_type(&[f64_ty_basic_md], false);
+    let ret_i64_take_f32 = i64_type.fn_type(&[f32_ty_basic_md], false);
+    let ret_i64_take_f64 = i64_type.fn_type(&[f64_ty_basic_md], false);
 
     // Declare insts
     let ctlz_i32 = module.add_function("llvm.ctlz.i32", ret_i32_take_i32_i1, None);
@@ -151,18 +162,24 @@ pub fn init_inkwell<'a>(
     let ceil_f64 = module.add_function("llvm.ceil.f64", ret_f64_take_f64, None);
     let trunc_f32 = module.add_function("llvm.trunc.f32", ret_f32_take_f32, None);
     let trunc_f64 = module.add_function("llvm.trunc.f64", ret_f64_take_f64, None);
-    let nearbyint_f32 = module.add_function("llvm.nearbyint.f32", ret_f32_take_f32, None);
-    let nearbyint_f64 = module.add_function("llvm.nearbyint.f64", ret_f64_take_f64, None);
+    let roundeven_f32 = module.add_function("llvm.roundeven.f32", ret_f32_take_f32, None);
+    let roundeven_f64 = module.add_function("llvm.roundeven.f64", ret_f64_take_f64, None);
     let floor_f32 = module.add_function("llvm.floor.f32", ret_f32_take_f32, None);
     let floor_f64 = module.add_function("llvm.floor.f64", ret_f64_take_f64, None);
     let sqrt_f32 = module.add_function("llvm.sqrt.f32", ret_f32_take_f32, None);
     let sqrt_f64 = module.add_function("llvm.sqrt.f64", ret_f64_take_f64, None);
-    let minnum_f32 = module.add_function("llvm.minnum.f32", ret_f32_take_f32_f32, None);
-    let minnum_f64 = module.add_function("llvm.minnum.f64", ret_f64_take_f64_f64, None);
-    let maxnum_f32 = module.add_function("llvm.maxnum.f32", ret_f32_take_f32_f32, None);
-    let maxnum_f64 = module.add_function("llvm.maxnum.f64", ret_f64_take_f64_f64, None);
     let copysign_f32 = module.add_function("llvm.copysign.f32", ret_f32_take_f32_f32, None);
     let copysign_f64 = module.add_function("llvm.copysign.f64", ret_f64_take_f64_f64, None);
+    let fptosi_sat_i32_f32 = module.add_function("llvm.fptosi.sat.i32.f32", ret_i32_take_f32, None);
+    let fptoui_sat_i32_f32 = module.add_function("llvm.fptoui.sat.i32.f32", ret_i32_take_f32, None);
+    let fptosi_sat_i32_f64 = module.add_function("llvm.fptosi.sat.i32.f64", ret_i32_take_f64, None);
+    let fptoui_sat_i32_f64 = module.add_function("llvm.fptoui.sat.i32.f64", ret_i32_take_f64, None);
+    let fptosi_sat_i64_f32 = module.add_function("llvm.fptosi.sat.i64.f32", ret_i64_take_f32, None);
+    let fptoui_sat_i64_f32 = module.add_function("llvm.fptoui.sat.i64.f32", ret_i64_take_f32, None);
+    let fptosi_sat_i64_f64 = module.add_function("llvm.fptosi.sat.i64.f64", ret_i64_take_f64, None);
+    let fptoui_sat_i64_f64 = module.add_function("llvm.fptoui.sat.i64.f64", ret_i64_take_f64, None);
+    let trap = module.add_function("llvm.trap", ret_void, None);
+    let stacksave = module.add_function("llvm.stacksave", i8_ptr_type.fn_type(&[], false), None);
 
     (
         InkwellTypes {
@@ -196,16 +213,22 @@ pub fn init_inkwell<'a>(
             floor_f64,
             trunc_f32,
             trunc_f64,
-            nearbyint_f32,
-            nearbyint_f64,
+            roundeven_f32,
+            roundeven_f64,
             sqrt_f32,
             sqrt_f64,
-            minnum_f32,
-            minnum_f64,
-            maxnum_f32,
-            maxnum_f64,
             copysign_f32,
             copysign_f64,
+            fptosi_sat_i32_f32,
+            fptoui_sat_i32_f32,
+            fptosi_sat_i32_f64,
+            fptoui_sat_i32_f64,
+            fptosi_sat_i64_f32,
+            fptoui_sat_i64_f32,
+            fptosi_sat_i64_f64,
+            fptoui_sat_i64_f64,
+            trap,
+            stacksave,
         },
     )
 }
@@ -1,11 +1,19 @@
 //! Definition of control instructions.
 
 use crate::environment::Environment;
+use crate::epoch;
+use crate::import_adapter;
+use crate::insts::exception;
+use crate::insts::trap::{self, TrapKind};
 use crate::section;
-use anyhow::{bail, Result};
+use crate::trace;
+use anyhow::{bail, Context, Result};
 use inkwell::{
     basic_block::BasicBlock,
-    values::{BasicMetadataValueEnum, FunctionValue, PhiValue},
+    values::{
+        BasicMetadataValueEnum, BasicValueEnum, CallSiteValue, FunctionValue, PhiValue,
+        PointerValue,
+    },
 };
 use wasmparser::{BlockType, BrTable};
 
@@ -16,6 +24,13 @@ pub enum IfElseState {
     Else,
 }
 
+/// Holds the state of try-catch.
+#[derive(Eq, PartialEq, Debug)]
+pub enum TryState {
+    Try,
+    Catch,
+}
+
 /// Holds the state of unreachable.
 #[derive(Eq, PartialEq, Debug)]
 pub enum UnreachableReason {
@@ -26,7 +41,7 @@ pub enum UnreachableReason {
 }
 
 impl UnreachableReason {
-    fn is_jumped(&self) -> bool {
+    pub(super) fn is_jumped(&self) -> bool {
         match self {
             UnreachableReason::Br | UnreachableReason::Unreachable | UnreachableReason::Return => {
                 true
@@ -36,7 +51,7 @@ impl UnreachableReason {
     }
 }
 
-/// Holds the state of control instructions: block, loop, if-else.
+/// Holds the state of control instructions: block, loop, if-else, try.
 #[derive(Debug)]
 pub enum ControlFrame<'a> {
     Loop {
@@ -59,14 +74,26 @@ pub enum ControlFrame<'a> {
         end_phis: Vec<PhiValue<'a>>,
         stack_size: usize,
     },
+    Try {
+        next: BasicBlock<'a>,
+        // Head of the catch clauses which are not generated yet.
+        // None after CatchAll or Delegate.
+        dispatch: Option<BasicBlock<'a>>,
+        try_state: TryState,
+        caught_tag: PointerValue<'a>,
+        caught_payload: PointerValue<'a>,
+        end_phis: Vec<PhiValue<'a>>,
+        stack_size: usize,
+    },
 }
 
 impl<'a> ControlFrame<'a> {
-    fn br_dest(&self) -> &BasicBlock<'a> {
+    pub(crate) fn br_dest(&self) -> &BasicBlock<'a> {
         match self {
             ControlFrame::Loop { ref loop_body, .. } => loop_body,
             ControlFrame::Block { ref next, .. } => next,
             ControlFrame::IfElse { ref if_end, .. } => if_end,
+            ControlFrame::Try { ref next, .. } => next,
         }
     }
 }
@@ -149,6 +176,11 @@ pub(super) fn gen_loop(environment: &mut Environment<'_, '_>, blockty: &BlockTyp
     environment.builder.position_at_end(current_block);
     environment.builder.build_unconditional_branch(body_block);
     environment.builder.position_at_end(body_block);
+
+    // Every iteration branches back to the check
+    if environment.args.epoch_interruption {
+        epoch::gen_epoch_check(environment).context("error gen epoch check")?;
+    }
     Ok(())
 }
 
@@ -275,6 +307,7 @@ pub(super) fn gen_br(environment: &mut Environment<'_, '_>, relative_depth: u32)
     let phis = match frame {
         ControlFrame::Block { end_phis, .. } => end_phis,
         ControlFrame::IfElse { end_phis, .. } => end_phis,
+        ControlFrame::Try { end_phis, .. } => end_phis,
         ControlFrame::Loop { body_phis, .. } => body_phis,
     };
     for phi in phis {
@@ -311,6 +344,7 @@ pub(super) fn gen_brif(environment: &mut Environment<'_, '_>, relative_depth: u3
     let phis = match frame {
         ControlFrame::Block { end_phis, .. } => end_phis,
         ControlFrame::IfElse { end_phis, .. } => end_phis,
+        ControlFrame::Try { end_phis, .. } => end_phis,
         ControlFrame::Loop { body_phis, .. } => body_phis,
     };
     let values = environment.peekn(phis.len()).expect("fail stack peekn");
@@ -349,6 +383,7 @@ pub(super) fn gen_br_table(environment: &mut Environment<'_, '_>, targets: &BrTa
     let phis = match default_frame {
         ControlFrame::Block { end_phis, .. } => end_phis,
         ControlFrame::IfElse { end_phis, .. } => end_phis,
+        ControlFrame::Try { end_phis, .. } => end_phis,
         ControlFrame::Loop { body_phis, .. } => body_phis,
     };
     let values = environment.peekn(phis.len()).expect("fail stack peekn");
@@ -372,6 +407,7 @@ pub(super) fn gen_br_table(environment: &mut Environment<'_, '_>, targets: &BrTa
         let phis = match dest {
             ControlFrame::Block { end_phis, .. } => end_phis,
             ControlFrame::IfElse { end_phis, .. } => end_phis,
+            ControlFrame::Try { end_phis, .. } => end_phis,
             ControlFrame::Loop { body_phis, .. } => body_phis,
         };
         let values = environment.peekn(phis.len()).expect("fail stack peekn");
@@ -409,6 +445,9 @@ pub(super) fn gen_end<'a>(
         match environment.unreachable_reason {
             UnreachableReason::Unreachable | UnreachableReason::Return => {
                 environment.builder.position_at_end(*frame.br_dest());
+                if environment.args.trace {
+                    trace::gen_exit(environment);
+                }
                 if current_fn.get_type().get_return_type().is_none() {
                     environment.builder.build_return(None);
                 } else {
@@ -425,6 +464,9 @@ pub(super) fn gen_end<'a>(
                     .builder
                     .build_unconditional_branch(*frame.br_dest());
                 environment.builder.position_at_end(*frame.br_dest());
+                if environment.args.trace {
+                    trace::gen_exit(environment);
+                }
                 if current_fn.get_type().get_return_type().is_none() {
                     environment.builder.build_return(None);
                 } else {
@@ -480,6 +522,21 @@ pub(super) fn gen_end<'a>(
                 }
                 (if_end, end_phis, stack_size)
             }
+            ControlFrame::Try {
+                next,
+                dispatch,
+                end_phis,
+                stack_size,
+                ..
+            } => {
+                // Exceptions not caught by any catch clause go to the outer handler
+                if let Some(dispatch) = dispatch {
+                    let handler = exception::handler_block(environment, 0);
+                    environment.builder.position_at_end(dispatch);
+                    environment.builder.build_unconditional_branch(handler);
+                }
+                (next, end_phis, stack_size)
+            }
         };
         if environment.unreachable_reason == UnreachableReason::Reachable {
             // Collect Phi
@@ -514,42 +571,7 @@ pub(super) fn gen_end<'a>(
 }
 
 pub(super) fn gen_call(environment: &mut Environment<'_, '_>, function_index: u32) -> Result<()> {
-    let fn_called = environment.function_list[function_index as usize];
-
-    // collect args from stack
-    let mut args: Vec<BasicMetadataValueEnum> = Vec::new();
-
-    if fn_called.get_name().to_str() == anyhow::Result::Ok("print") {
-        args.push(environment.pop_and_load().into());
-
-        let arg0 = environment.pop_and_load();
-        let linear_memory_offset_local = environment.builder.build_load(
-            environment.inkwell_types.i8_ptr_type,
-            environment
-                .linear_memory_offset_global
-                .expect("should define linet_memory_offset_global")
-                .as_pointer_value(),
-            "linm_local",
-        );
-        let linear_memory_offset_int = environment.builder.build_ptr_to_int(
-            linear_memory_offset_local.into_pointer_value(),
-            environment.inkwell_types.i64_type,
-            "linm_int",
-        );
-        let offset = arg0.into_int_value();
-        let translated_address =
-            environment
-                .builder
-                .build_int_add(linear_memory_offset_int, offset, "transed_addr");
-        args.push(translated_address.into());
-    } else {
-        for _ in 0..fn_called.count_params() {
-            args.push(environment.stack.pop().expect("stack empty").into());
-        }
-    }
-    // call
-    args.reverse();
-    let call_site = environment.builder.build_call(fn_called, &args[..], "");
+    let call_site = build_call(environment, function_index)?;
     if call_site.try_as_basic_value().is_left() {
         environment.stack.push(
             call_site
@@ -558,6 +580,9 @@ pub(super) fn gen_call(environment: &mut Environment<'_, '_>, function_index: u3
                 .expect("fail translate call_site"),
         );
     }
+    if environment.exception_handling {
+        exception::gen_exception_check(environment)?;
+    }
     Ok(())
 }
 
@@ -567,30 +592,187 @@ pub(super) fn gen_call_indirect(
     table_index: u32,
     _table_byte: u8,
 ) -> Result<()> {
+    let call_site = build_call_indirect(environment, type_index, table_index)?;
+    if call_site.try_as_basic_value().is_left() {
+        environment.stack.push(
+            call_site
+                .try_as_basic_value()
+                .left()
+                .expect("fail translate call_site"),
+        );
+    }
+    if environment.exception_handling {
+        exception::gen_exception_check(environment)?;
+    }
+    Ok(())
+}
+
+pub(super) fn gen_return_call<'a>(
+    environment: &mut Environment<'a, '_>,
+    function_index: u32,
+    current_fn: &FunctionValue<'a>,
+) -> Result<()> {
+    if environment.args.trace {
+        // The import hooks can't surround a tail call
+        if function_index < environment.import_section_size {
+            gen_call(environment, function_index)?;
+            return gen_return(environment, current_fn);
+        }
+        trace::gen_exit(environment);
+    }
+    let call_site = build_call(environment, function_index)?;
+    build_tail_return(environment, call_site, current_fn)
+}
+
+pub(super) fn gen_return_call_indirect<'a>(
+    environment: &mut Environment<'a, '_>,
+    type_index: u32,
+    table_index: u32,
+    current_fn: &FunctionValue<'a>,
+) -> Result<()> {
+    if environment.args.trace {
+        trace::gen_exit(environment);
+    }
+    let call_site = build_call_indirect(environment, type_index, table_index)?;
+    build_tail_return(environment, call_site, current_fn)
+}
+
+// Mark the call as a tail call and return its result.
+// Exceptions thrown by the callee are checked by the caller of the current function.
+// LLVM 15 doesn't expose `musttail` through its C API, but calls marked `tail`
+// between two `tailcc` functions followed by `ret` are guaranteed to be optimized.
+// Defined functions and table entries use `tailcc`, so only tail calls to imports,
+// which use the C calling convention, are optimized on a best-effort basis.
+fn build_tail_return<'a>(
+    environment: &mut Environment<'a, '_>,
+    call_site: CallSiteValue<'a>,
+    current_fn: &FunctionValue<'a>,
+) -> Result<()> {
+    call_site.set_tail_call(true);
+    environment.unreachable_depth += 1;
+    environment.unreachable_reason = UnreachableReason::Return;
+
+    if current_fn.get_type().get_return_type().is_none() {
+        environment.builder.build_return(None);
+    } else {
+        let ret = call_site
+            .try_as_basic_value()
+            .left()
+            .expect("fail translate call_site");
+        environment.builder.build_return(Some(&ret));
+    }
+    Ok(())
+}
+
+fn build_call<'a>(
+    environment: &mut Environment<'a, '_>,
+    function_index: u32,
+) -> Result<CallSiteValue<'a>> {
+    let fn_called = environment.function_list[function_index as usize];
+
+    // collect args from stack
+    // The Wasm signature, as the host one may have an extra context param
+    let signature = environment.function_signature_list
+        [environment.function_list_signature[function_index as usize] as usize];
+    let mut args: Vec<BasicValueEnum> = Vec::new();
+    for _ in 0..signature.count_param_types() {
+        args.push(environment.stack.pop().expect("stack empty"));
+    }
+    args.reverse();
+
+    // Translate guest pointers passed to the host
+    if let Some(params) = environment.import_adapters.get(&function_index).cloned() {
+        import_adapter::adapt_arguments(environment, &params, &mut args)?;
+    }
+    if environment.args.import_context && function_index < environment.import_section_size {
+        args.insert(0, import_adapter::build_import_context(environment));
+    }
+
+    // call
+    let traced_import = environment.args.trace && function_index < environment.import_section_size;
+    if traced_import {
+        trace::gen_import(environment, function_index);
+    }
+    let args: Vec<BasicMetadataValueEnum> = args.into_iter().map(Into::into).collect();
+    let call_site = environment.builder.build_call(fn_called, &args[..], "");
+    call_site.set_call_convention(fn_called.get_call_conventions());
+    if traced_import {
+        trace::gen_import_exit(environment, function_index);
+    }
+    Ok(call_site)
+}
+
+fn build_call_indirect<'a>(
+    environment: &mut Environment<'a, '_>,
+    type_index: u32,
+    table_index: u32,
+) -> Result<CallSiteValue<'a>> {
     // TODO: support larger
     assert_eq!(table_index, 0);
 
-    // Load function pointer
     let idx = environment
         .stack
         .pop()
         .expect("stack empty")
         .into_int_value();
-    let dst_addr = unsafe {
+
+    // Trap if idx is out of the table
+    let table_size = environment
+        .inkwell_types
+        .i32_type
+        .const_int(environment.table_size as u64, false);
+    let out_of_bounds = environment.builder.build_int_compare(
+        inkwell::IntPredicate::UGE,
+        idx,
+        table_size,
+        "out_of_bounds",
+    );
+    trap::gen_trap_if(environment, out_of_bounds, TrapKind::TableOutOfBounds)?;
+
+    // Load table element
+    let entry_type = environment.table_entry_type();
+    let entry_addr = unsafe {
         environment.builder.build_gep(
-            environment.inkwell_types.i8_ptr_type,
+            entry_type,
             environment
                 .global_table
                 .expect("should define global_table")
                 .as_pointer_value(),
             &[idx],
-            "dst_addr",
+            "entry_addr",
         )
     };
-    let fptr =
-        environment
-            .builder
-            .build_load(environment.inkwell_types.i8_ptr_type, dst_addr, "fptr");
+    let fptr_addr = environment
+        .builder
+        .build_struct_gep(entry_type, entry_addr, 0, "fptr_addr")
+        .expect("error build_struct_gep");
+    let fptr = environment
+        .builder
+        .build_load(environment.inkwell_types.i8_ptr_type, fptr_addr, "fptr")
+        .into_pointer_value();
+    let sig_addr = environment
+        .builder
+        .build_struct_gep(entry_type, entry_addr, 1, "sig_addr")
+        .expect("error build_struct_gep");
+    let sig = environment
+        .builder
+        .build_load(environment.inkwell_types.i32_type, sig_addr, "sig")
+        .into_int_value();
+
+    // Trap if the element is null or has a different signature
+    let is_null = environment.builder.build_is_null(fptr, "is_null");
+    trap::gen_trap_if(environment, is_null, TrapKind::IndirectCallToNull)?;
+    let expected_sig = environment.inkwell_types.i32_type.const_int(
+        environment.function_signature_ids[type_index as usize] as u64,
+        false,
+    );
+    let bad_signature = environment.builder.build_int_compare(
+        inkwell::IntPredicate::NE,
+        sig,
+        expected_sig,
+        "bad_signature",
+    );
+    trap::gen_trap_if(environment, bad_signature, TrapKind::BadSignature)?;
 
     // args
     let func_type = environment.function_signature_list[type_index as usize];
@@ -599,23 +781,13 @@ pub(super) fn gen_call_indirect(
         args.push(environment.stack.pop().expect("stack empty").into());
     }
 
-    // call and push result
+    // call
     args.reverse();
-    let call_site = environment.builder.build_indirect_call(
-        func_type,
-        fptr.into_pointer_value(),
-        &args,
-        "call_site",
-    );
-    if call_site.try_as_basic_value().is_left() {
-        environment.stack.push(
-            call_site
-                .try_as_basic_value()
-                .left()
-                .expect("fail translate call_site"),
-        );
-    }
-    Ok(())
+    let call_site = environment
+        .builder
+        .build_indirect_call(func_type, fptr, &args, "call_site");
+    call_site.set_call_convention(section::LLVM_TAILCC);
+    Ok(call_site)
 }
 
 pub(super) fn gen_drop(environment: &mut Environment<'_, '_>) -> Result<()> {
@@ -631,6 +803,9 @@ pub(super) fn gen_return(
     environment.unreachable_depth += 1;
     environment.unreachable_reason = UnreachableReason::Return;
 
+    if environment.args.trace {
+        trace::gen_exit(environment);
+    }
     if current_fn.get_type().get_return_type().is_none() {
         environment.builder.build_return(None);
     } else {
@@ -0,0 +1,497 @@
+//! Definition of exception handling instructions.
+//!
+//! Exceptions are lowered without an unwinder, so that the output stays OS-independent.
+//! A thrown exception is recorded in `wasker_exception_tag` and `wasker_exception_payload`,
+//! then control is transferred to the innermost handler of the current function,
+//! or the function returns a dummy value when there is no handler.
+//! Every call is followed by a check of `wasker_exception_tag`, which propagates the exception
+//! to the caller's handler.
+//! The host can throw into guest code by setting these globals before returning from an import,
+//! and can catch exceptions from guest code by checking them after calling an exported function.
+//! An exception escaping `_start` traps with `TrapKind::UncaughtException` in `wasker_main`,
+//! as there is no caller left to handle it.
+
+use crate::environment::Environment;
+use crate::insts::control::{ControlFrame, TryState, UnreachableReason};
+use crate::insts::trap::{self, TrapKind};
+use crate::section;
+use crate::trace;
+use anyhow::{bail, Result};
+use inkwell::{
+    basic_block::BasicBlock,
+    types::{BasicType, BasicTypeEnum},
+    values::{BasicValueEnum, IntValue, PhiValue, PointerValue},
+};
+use wasmparser::BlockType;
+
+/// Value of `wasker_exception_tag` when no exception is in flight.
+pub const NO_EXCEPTION: u64 = u32::MAX as u64;
+
+pub(super) fn gen_try(environment: &mut Environment<'_, '_>, blockty: &BlockType) -> Result<()> {
+    let current_block = environment
+        .builder
+        .get_insert_block()
+        .expect("fail to get_insert_block");
+
+    // Create blocks
+    let next_block = environment.context.append_basic_block(
+        environment.function_list[environment.current_function_idx as usize],
+        "try_next",
+    );
+    let dispatch_block = environment.context.append_basic_block(
+        environment.function_list[environment.current_function_idx as usize],
+        "try_dispatch",
+    );
+
+    // Phi
+    environment.builder.position_at_end(next_block);
+    let mut end_phis: Vec<PhiValue> = Vec::new();
+    match blockty {
+        BlockType::Empty => {}
+        BlockType::Type(valty) => {
+            let phi = environment.builder.build_phi(
+                section::wasmparser_to_inkwell(valty, &environment.inkwell_types)?,
+                "end_phi",
+            );
+            end_phis.push(phi);
+        }
+        BlockType::FuncType(..) => {
+            bail!("Unexpected FuncType");
+        }
+    }
+
+    // Caught exception is saved for rethrow
+    environment.builder.position_at_end(current_block);
+    let payload_type = exception_payload_type(environment);
+    let caught_tag =
+        environment.build_entry_alloca(environment.inkwell_types.i32_type, "caught_tag");
+    let caught_payload = environment.build_entry_alloca(payload_type, "caught_payload");
+
+    environment.control_frames.push(ControlFrame::Try {
+        next: next_block,
+        dispatch: Some(dispatch_block),
+        try_state: TryState::Try,
+        caught_tag,
+        caught_payload,
+        end_phis,
+        stack_size: environment.stack.len(),
+    });
+    Ok(())
+}
+
+pub(super) fn gen_catch(
+    environment: &mut Environment<'_, '_>,
+    tag_index: Option<u32>,
+) -> Result<()> {
+    let current_block = environment
+        .builder
+        .get_insert_block()
+        .expect("fail to get_insert_block");
+    let catch_block = environment.context.append_basic_block(
+        environment.function_list[environment.current_function_idx as usize],
+        "catch",
+    );
+    let exception_tag = environment
+        .exception_tag
+        .expect("should define exception_tag")
+        .as_pointer_value();
+    let exception_payload = environment
+        .exception_payload
+        .expect("should define exception_payload")
+        .as_pointer_value();
+
+    let framelen = environment.control_frames.len();
+    let (caught_tag, caught_payload, stack_size) =
+        match &mut environment.control_frames[framelen - 1] {
+            ControlFrame::Try {
+                next,
+                dispatch,
+                try_state,
+                caught_tag,
+                caught_payload,
+                end_phis,
+                stack_size,
+            } => {
+                // Phi
+                if environment.unreachable_depth == 0 {
+                    for phi in end_phis {
+                        let value = environment.stack.pop().expect("stack empty");
+                        phi.add_incoming(&[(&value, current_block)]);
+                    }
+                }
+
+                // Jump to merge block from current block
+                if !environment.unreachable_reason.is_jumped() {
+                    environment.builder.build_unconditional_branch(*next);
+                }
+
+                // Dispatch the in-flight exception to this catch clause
+                let Some(dispatch_block) = *dispatch else {
+                    bail!("Catch after CatchAll");
+                };
+                environment.builder.position_at_end(dispatch_block);
+                match tag_index {
+                    Some(tag_index) => {
+                        let next_dispatch = environment.context.append_basic_block(
+                            environment.function_list[environment.current_function_idx as usize],
+                            "try_dispatch",
+                        );
+                        let tag = environment.builder.build_load(
+                            environment.inkwell_types.i32_type,
+                            exception_tag,
+                            "exception_tag",
+                        );
+                        let matched = environment.builder.build_int_compare(
+                            inkwell::IntPredicate::EQ,
+                            tag.into_int_value(),
+                            environment
+                                .inkwell_types
+                                .i32_type
+                                .const_int(tag_index as u64, false),
+                            "tag_matched",
+                        );
+                        environment.builder.build_conditional_branch(
+                            matched,
+                            catch_block,
+                            next_dispatch,
+                        );
+                        *dispatch = Some(next_dispatch);
+                    }
+                    None => {
+                        environment.builder.build_unconditional_branch(catch_block);
+                        *dispatch = None;
+                    }
+                }
+                *try_state = TryState::Catch;
+                (*caught_tag, *caught_payload, *stack_size)
+            }
+            _ => {
+                unreachable!("Op Catch with another ControlFrame");
+            }
+        };
+
+    // Take the exception over from the globals
+    environment.builder.position_at_end(catch_block);
+    environment.reset_stack(stack_size);
+    let payload_type = exception_payload_type(environment);
+    let tag = environment.builder.build_load(
+        environment.inkwell_types.i32_type,
+        exception_tag,
+        "exception_tag",
+    );
+    environment.builder.build_store(caught_tag, tag);
+    let payload =
+        environment
+            .builder
+            .build_load(payload_type, exception_payload, "exception_payload");
+    environment.builder.build_store(caught_payload, payload);
+    environment.builder.build_store(
+        exception_tag,
+        environment
+            .inkwell_types
+            .i32_type
+            .const_int(NO_EXCEPTION, false),
+    );
+
+    // Push the values carried by the exception
+    if let Some(tag_index) = tag_index {
+        let params = environment.function_signature_list
+            [environment.tag_list_signature[tag_index as usize] as usize]
+            .get_param_types();
+        for (i, ty) in params.into_iter().enumerate() {
+            let slot = build_payload_slot(environment, caught_payload, i as u64);
+            let bits = environment.builder.build_load(
+                environment.inkwell_types.i64_type,
+                slot,
+                "payload_bits",
+            );
+            let value = from_payload_bits(environment, bits.into_int_value(), ty);
+            environment.stack.push(value);
+        }
+    }
+    Ok(())
+}
+
+pub(super) fn gen_throw(environment: &mut Environment<'_, '_>, tag_index: u32) -> Result<()> {
+    let exception_payload = environment
+        .exception_payload
+        .expect("should define exception_payload")
+        .as_pointer_value();
+
+    // Store the values carried by the exception
+    let num_params = environment.function_signature_list
+        [environment.tag_list_signature[tag_index as usize] as usize]
+        .count_param_types();
+    for i in (0..num_params).rev() {
+        let value = environment.stack.pop().expect("stack empty");
+        let bits = to_payload_bits(environment, value);
+        let slot = build_payload_slot(environment, exception_payload, i as u64);
+        environment.builder.build_store(slot, bits);
+    }
+    environment.builder.build_store(
+        environment
+            .exception_tag
+            .expect("should define exception_tag")
+            .as_pointer_value(),
+        environment
+            .inkwell_types
+            .i32_type
+            .const_int(tag_index as u64, false),
+    );
+
+    let handler = handler_block(environment, 0);
+    environment.builder.build_unconditional_branch(handler);
+    environment.unreachable_depth += 1;
+    environment.unreachable_reason = UnreachableReason::Br;
+    Ok(())
+}
+
+pub(super) fn gen_rethrow(
+    environment: &mut Environment<'_, '_>,
+    relative_depth: u32,
+) -> Result<()> {
+    let frame =
+        &environment.control_frames[environment.control_frames.len() - 1 - relative_depth as usize];
+    let (caught_tag, caught_payload) = match frame {
+        ControlFrame::Try {
+            try_state: TryState::Catch,
+            caught_tag,
+            caught_payload,
+            ..
+        } => (*caught_tag, *caught_payload),
+        _ => {
+            bail!("Rethrow outside of catch");
+        }
+    };
+
+    // Restore the caught exception
+    let payload_type = exception_payload_type(environment);
+    let tag = environment.builder.build_load(
+        environment.inkwell_types.i32_type,
+        caught_tag,
+        "caught_tag",
+    );
+    let payload = environment
+        .builder
+        .build_load(payload_type, caught_payload, "caught_payload");
+    environment.builder.build_store(
+        environment
+            .exception_payload
+            .expect("should define exception_payload")
+            .as_pointer_value(),
+        payload,
+    );
+    environment.builder.build_store(
+        environment
+            .exception_tag
+            .expect("should define exception_tag")
+            .as_pointer_value(),
+        tag,
+    );
+
+    let handler = handler_block(environment, 0);
+    environment.builder.build_unconditional_branch(handler);
+    environment.unreachable_depth += 1;
+    environment.unreachable_reason = UnreachableReason::Br;
+    Ok(())
+}
+
+/// Forward exceptions not caught by the current try block to the handler of `relative_depth`.
+/// The try block itself is closed by `gen_end`.
+pub(super) fn gen_delegate(
+    environment: &mut Environment<'_, '_>,
+    relative_depth: u32,
+) -> Result<()> {
+    let current_block = environment
+        .builder
+        .get_insert_block()
+        .expect("fail to get_insert_block");
+
+    // The label of delegate is counted from the block enclosing the try block
+    let handler = handler_block(environment, relative_depth + 1);
+    let dispatch = match environment.control_frames.last_mut() {
+        Some(ControlFrame::Try { dispatch, .. }) => dispatch.take(),
+        _ => {
+            bail!("Delegate with another ControlFrame");
+        }
+    };
+    if let Some(dispatch_block) = dispatch {
+        environment.builder.position_at_end(dispatch_block);
+        environment.builder.build_unconditional_branch(handler);
+        environment.builder.position_at_end(current_block);
+    }
+    Ok(())
+}
+
+/// Branch to the current handler if the preceding call threw an exception.
+pub(super) fn gen_exception_check(environment: &mut Environment<'_, '_>) -> Result<()> {
+    let thrown = build_thrown(environment);
+    let handler = handler_block(environment, 0);
+    let cont_block = environment.context.append_basic_block(
+        environment.function_list[environment.current_function_idx as usize],
+        "no_exception",
+    );
+    environment
+        .builder
+        .build_conditional_branch(thrown, handler, cont_block);
+    environment.builder.position_at_end(cont_block);
+    Ok(())
+}
+
+/// Trap if the preceding call threw an exception which nobody can catch.
+pub(crate) fn gen_uncaught_check(environment: &mut Environment<'_, '_>) -> Result<()> {
+    let thrown = build_thrown(environment);
+    trap::gen_trap_if(environment, thrown, TrapKind::UncaughtException)
+}
+
+fn build_thrown<'a>(environment: &mut Environment<'a, '_>) -> IntValue<'a> {
+    let tag = environment.builder.build_load(
+        environment.inkwell_types.i32_type,
+        environment
+            .exception_tag
+            .expect("should define exception_tag")
+            .as_pointer_value(),
+        "exception_tag",
+    );
+    environment.builder.build_int_compare(
+        inkwell::IntPredicate::NE,
+        tag.into_int_value(),
+        environment
+            .inkwell_types
+            .i32_type
+            .const_int(NO_EXCEPTION, false),
+        "thrown",
+    )
+}
+
+/// Get the block which handles exceptions thrown inside the label `relative_depth`.
+/// Catch clauses don't handle exceptions thrown by themselves,
+/// so only try blocks whose body is being generated are candidates.
+pub(super) fn handler_block<'a>(
+    environment: &mut Environment<'a, '_>,
+    relative_depth: u32,
+) -> BasicBlock<'a> {
+    let frames = environment
+        .control_frames
+        .len()
+        .saturating_sub(relative_depth as usize);
+    for frame in environment.control_frames[..frames].iter().rev() {
+        if let ControlFrame::Try {
+            try_state: TryState::Try,
+            dispatch: Some(dispatch),
+            ..
+        } = frame
+        {
+            return *dispatch;
+        }
+    }
+    unwind_block(environment)
+}
+
+// Block which propagates the exception to the caller.
+fn unwind_block<'a>(environment: &mut Environment<'a, '_>) -> BasicBlock<'a> {
+    if let Some(block) = environment.exception_unwind_block {
+        return block;
+    }
+    let current_block = environment
+        .builder
+        .get_insert_block()
+        .expect("fail to get_insert_block");
+    let current_fn = environment.function_list[environment.current_function_idx as usize];
+    let block = environment
+        .context
+        .append_basic_block(current_fn, "exception_unwind");
+    environment.builder.position_at_end(block);
+    if environment.args.trace {
+        trace::gen_exit(environment);
+    }
+    match current_fn.get_type().get_return_type() {
+        Some(ret_ty) => {
+            let dummy = ret_ty.const_zero();
+            environment.builder.build_return(Some(&dummy));
+        }
+        None => {
+            environment.builder.build_return(None);
+        }
+    }
+    environment.builder.position_at_end(current_block);
+    environment.exception_unwind_block = Some(block);
+    block
+}
+
+fn exception_payload_type<'a>(environment: &Environment<'a, '_>) -> BasicTypeEnum<'a> {
+    environment
+        .inkwell_types
+        .i64_type
+        .array_type(environment.exception_payload_len)
+        .as_basic_type_enum()
+}
+
+fn build_payload_slot<'a>(
+    environment: &mut Environment<'a, '_>,
+    payload: PointerValue<'a>,
+    index: u64,
+) -> PointerValue<'a> {
+    let payload_type = exception_payload_type(environment);
+    unsafe {
+        environment.builder.build_gep(
+            payload_type,
+            payload,
+            &[
+                environment.inkwell_types.i32_type.const_zero(),
+                environment.inkwell_types.i32_type.const_int(index, false),
+            ],
+            "payload_slot",
+        )
+    }
+}
+
+// Each value carried by an exception is stored as 64 bits.
+fn to_payload_bits<'a>(
+    environment: &mut Environment<'a, '_>,
+    value: BasicValueEnum<'a>,
+) -> IntValue<'a> {
+    let i32_type = environment.inkwell_types.i32_type;
+    let i64_type = environment.inkwell_types.i64_type;
+    match value {
+        BasicValueEnum::IntValue(v) if v.get_type() == i32_type => {
+            environment.builder.build_int_z_extend(v, i64_type, "")
+        }
+        BasicValueEnum::IntValue(v) => v,
+        BasicValueEnum::FloatValue(v) if v.get_type() == environment.inkwell_types.f32_type => {
+            let bits = environment.builder.build_bitcast(v, i32_type, "");
+            environment
+                .builder
+                .build_int_z_extend(bits.into_int_value(), i64_type, "")
+        }
+        BasicValueEnum::FloatValue(v) => environment
+            .builder
+            .build_bitcast(v, i64_type, "")
+            .into_int_value(),
+        // funcref and externref
+        BasicValueEnum::PointerValue(v) => environment.builder.build_ptr_to_int(v, i64_type, ""),
+        _other => unreachable!("unsupported exception value {:?}", value),
+    }
+}
+
+fn from_payload_bits<'a>(
+    environment: &mut Environment<'a, '_>,
+    bits: IntValue<'a>,
+    ty: BasicTypeEnum<'a>,
+) -> BasicValueEnum<'a> {
+    let i32_type = environment.inkwell_types.i32_type;
+    match ty {
+        BasicTypeEnum::IntType(t) if t == i32_type => environment
+            .builder
+            .build_int_truncate(bits, i32_type, "")
+            .into(),
+        BasicTypeEnum::IntType(..) => bits.into(),
+        BasicTypeEnum::FloatType(t) if t == environment.inkwell_types.f32_type => {
+            let narrow = environment.builder.build_int_truncate(bits, i32_type, "");
+            environment.builder.build_bitcast(narrow, t, "")
+        }
+        BasicTypeEnum::FloatType(t) => environment.builder.build_bitcast(bits, t, ""),
+        BasicTypeEnum::PointerType(t) => environment.builder.build_int_to_ptr(bits, t, "").into(),
+        _other => unreachable!("unsupported exception type {:?}", ty),
+    }
+}
@@ -1,8 +1,10 @@
 //! `insts` is a module that contains the definitions of WebAssembly instructions.
 
 pub(crate) mod control;
+pub(crate) mod exception;
 mod memory;
 mod numeric;
+pub mod trap;
 
 use anyhow::{bail, Context, Ok, Result};
 use inkwell::{
@@ -26,7 +28,8 @@ pub(super) fn parse_instruction<'a>(
         match op {
             Operator::Block { blockty: _ }
             | Operator::Loop { blockty: _ }
-            | Operator::If { blockty: _ } => {
+            | Operator::If { blockty: _ }
+            | Operator::Try { blockty: _ } => {
                 environment.unreachable_depth += 1;
                 return Ok(());
             }
@@ -41,6 +44,39 @@ pub(super) fn parse_instruction<'a>(
                     return Ok(());
                 }
             }
+            Operator::Catch { .. } | Operator::CatchAll => {
+                if environment.unreachable_depth == 1 {
+                    let tag_index = match op {
+                        Operator::Catch { tag_index } => Some(*tag_index),
+                        _ => None,
+                    };
+                    exception::gen_catch(environment, tag_index).context("error gen Catch")?;
+                    environment.unreachable_depth -= 1;
+                    environment.unreachable_reason = UnreachableReason::Reachable;
+                    log::trace!("- end of unreachable");
+                    return Ok(());
+                } else {
+                    return Ok(());
+                }
+            }
+            Operator::Delegate { relative_depth } => match environment.unreachable_depth {
+                0 => {
+                    unreachable!("Unexpected depth 0");
+                }
+                1 => {
+                    exception::gen_delegate(environment, *relative_depth)
+                        .context("error gen Delegate")?;
+                    control::gen_end(environment, current_fn).context("error gen End")?;
+                    environment.unreachable_depth -= 1;
+                    environment.unreachable_reason = UnreachableReason::Reachable;
+                    log::trace!("- end of unreachable");
+                    return Ok(());
+                }
+                2_u32..=u32::MAX => {
+                    environment.unreachable_depth -= 1;
+                    return Ok(());
+                }
+            },
             Operator::End => match environment.unreachable_depth {
                 0 => {
                     unreachable!("Unexpected depth 0");
@@ -107,6 +143,36 @@ pub(super) fn parse_instruction<'a>(
             control::gen_call_indirect(environment, *type_index, *table_index, *table_byte)
                 .context("error gen CallIndirect")?;
         }
+        Operator::ReturnCall { function_index } => {
+            control::gen_return_call(environment, *function_index, current_fn)
+                .context("error gen ReturnCall")?;
+        }
+        Operator::ReturnCallIndirect {
+            type_index,
+            table_index,
+        } => {
+            control::gen_return_call_indirect(environment, *type_index, *table_index, current_fn)
+                .context("error gen ReturnCallIndirect")?;
+        }
+        Operator::Try { blockty } => {
+            exception::gen_try(environment, blockty).context("error gen Try")?;
+        }
+        Operator::Catch { tag_index } => {
+            exception::gen_catch(environment, Some(*tag_index)).context("error gen Catch")?;
+        }
+        Operator::CatchAll => {
+            exception::gen_catch(environment, None).context("error gen CatchAll")?;
+        }
+        Operator::Throw { tag_index } => {
+            exception::gen_throw(environment, *tag_index).context("error gen Throw")?;
+        }
+        Operator::Rethrow { relative_depth } => {
+            exception::gen_rethrow(environment, *relative_depth).context("error gen Rethrow")?;
+        }
+        Operator::Delegate { relative_depth } => {
+            exception::gen_delegate(environment, *relative_depth).context("error gen Delegate")?;
+            control::gen_end(environment, current_fn).context("error gen End")?;
+        }
         Operator::Drop => {
             control::gen_drop(environment).context("error gen Drop")?;
         }
@@ -630,31 +696,32 @@ pub(super) fn parse_instruction<'a>(
             );
             environment.stack.push(converted.as_basic_value_enum());
         }
-        Operator::I32TruncSatF32U => {
+        // Saturate NaN and out-of-range values instead of producing poison
+        Operator::I32TruncSatF32S => {
             let v = environment
                 .stack
                 .pop()
                 .expect("stack empty")
                 .into_float_value();
-            let converted = environment.builder.build_float_to_unsigned_int(
-                v,
-                environment.inkwell_types.i32_type,
-                "i32truncsatf32u",
-            );
-            environment.stack.push(converted.as_basic_value_enum());
+            helper_code_gen_llvm_insts(
+                environment,
+                environment.inkwell_insts.fptosi_sat_i32_f32,
+                &[v.into()],
+            )
+            .context("error gen I32TruncSatF32S")?;
         }
-        Operator::I32TruncSatF32S => {
+        Operator::I32TruncSatF32U => {
             let v = environment
                 .stack
                 .pop()
                 .expect("stack empty")
                 .into_float_value();
-            let converted = environment.builder.build_float_to_signed_int(
-                v,
-                environment.inkwell_types.i32_type,
-                "i32truncsatf32s",
-            );
-            environment.stack.push(converted.as_basic_value_enum());
+            helper_code_gen_llvm_insts(
+                environment,
+                environment.inkwell_insts.fptoui_sat_i32_f32,
+                &[v.into()],
+            )
+            .context("error gen I32TruncSatF32U")?;
         }
         Operator::I32TruncSatF64S => {
             let v = environment
@@ -662,12 +729,12 @@ pub(super) fn parse_instruction<'a>(
                 .pop()
                 .expect("stack empty")
                 .into_float_value();
-            let converted = environment.builder.build_float_to_signed_int(
-                v,
-                environment.inkwell_types.i32_type,
-                "i32truncsatf64s",
-            );
-            environment.stack.push(converted.as_basic_value_enum());
+            helper_code_gen_llvm_insts(
+                environment,
+                environment.inkwell_insts.fptosi_sat_i32_f64,
+                &[v.into()],
+            )
+            .context("error gen I32TruncSatF64S")?;
         }
         Operator::I32TruncSatF64U => {
             let v = environment
@@ -675,12 +742,12 @@ pub(super) fn parse_instruction<'a>(
                 .pop()
                 .expect("stack empty")
                 .into_float_value();
-            let converted = environment.builder.build_float_to_unsigned_int(
-                v,
-                environment.inkwell_types.i32_type,
-                "i32truncsatf64u",
-            );
-            environment.stack.push(converted.as_basic_value_enum());
+            helper_code_gen_llvm_insts(
+                environment,
+                environment.inkwell_insts.fptoui_sat_i32_f64,
+                &[v.into()],
+            )
+            .context("error gen I32TruncSatF64U")?;
         }
         Operator::I64TruncSatF32S => {
             let v = environment
@@ -688,12 +755,51 @@ pub(super) fn parse_instruction<'a>(
                 .pop()
                 .expect("stack empty")
                 .into_float_value();
-            let converted = environment.builder.build_float_to_signed_int(
-                v,
-                environment.inkwell_types.i64_type,
-                "i64truncsatf32s",
-            );
-            environment.stack.push(converted.as_basic_value_enum());
+            helper_code_gen_llvm_insts(
+                environment,
+                environment.inkwell_insts.fptosi_sat_i64_f32,
+                &[v.into()],
+            )
+            .context("error gen I64TruncSatF32S")?;
+        }
+        Operator::I64TruncSatF32U => {
+            let v = environment
+                .stack
+                .pop()
+                .expect("stack empty")
+                .into_float_value();
+            helper_code_gen_llvm_insts(
+                environment,
+                environment.inkwell_insts.fptoui_sat_i64_f32,
+                &[v.into()],
+            )
+            .context("error gen I64TruncSatF32U")?;
+        }
+        Operator::I64TruncSatF64S => {
+            let v = environment
+                .stack
+                .pop()
+                .expect("stack empty")
+                .into_float_value();
+            helper_code_gen_llvm_insts(
+                environment,
+                environment.inkwell_insts.fptosi_sat_i64_f64,
+                &[v.into()],
+            )
+            .context("error gen I64TruncSatF64S")?;
+        }
+        Operator::I64TruncSatF64U => {
+            let v = environment
+                .stack
+                .pop()
+                .expect("stack empty")
+                .into_float_value();
+            helper_code_gen_llvm_insts(
+                environment,
+                environment.inkwell_insts.fptoui_sat_i64_f64,
+                &[v.into()],
+            )
+            .context("error gen I64TruncSatF64U")?;
         }
         Operator::F64ReinterpretI64 => {
             let v = environment
@@ -898,7 +1004,7 @@ pub(super) fn parse_instruction<'a>(
                 .into_float_value();
             helper_code_gen_llvm_insts(
                 environment,
-                environment.inkwell_insts.nearbyint_f64,
+                environment.inkwell_insts.roundeven_f64,
                 &[v.into()],
             )
             .context("error gen F64Nearest")?;
@@ -911,7 +1017,7 @@ pub(super) fn parse_instruction<'a>(
                 .into_float_value();
             helper_code_gen_llvm_insts(
                 environment,
-                environment.inkwell_insts.nearbyint_f32,
+                environment.inkwell_insts.roundeven_f32,
                 &[v.into()],
             )
             .context("error gen F32Nearest")?;
@@ -981,40 +1087,16 @@ pub(super) fn parse_instruction<'a>(
         }
 
         Operator::F64Min => {
-            let (v1, v2) = environment.pop2();
-            helper_code_gen_llvm_insts(
-                environment,
-                environment.inkwell_insts.minnum_f64,
-                &[v1.into(), v2.into()],
-            )
-            .context("error gen F64Min")?;
+            numeric::gen_fmin_fmax(environment, false).context("error gen F64Min")?;
         }
         Operator::F32Min => {
-            let (v1, v2) = environment.pop2();
-            helper_code_gen_llvm_insts(
-                environment,
-                environment.inkwell_insts.minnum_f32,
-                &[v1.into(), v2.into()],
-            )
-            .context("error gen F32Min")?;
+            numeric::gen_fmin_fmax(environment, false).context("error gen F32Min")?;
         }
         Operator::F64Max => {
-            let (v1, v2) = environment.pop2();
-            helper_code_gen_llvm_insts(
-                environment,
-                environment.inkwell_insts.maxnum_f64,
-                &[v1.into(), v2.into()],
-            )
-            .context("error gen F64Max")?;
+            numeric::gen_fmin_fmax(environment, true).context("error gen F64Max")?;
         }
         Operator::F32Max => {
-            let (v1, v2) = environment.pop2();
-            helper_code_gen_llvm_insts(
-                environment,
-                environment.inkwell_insts.maxnum_f32,
-                &[v1.into(), v2.into()],
-            )
-            .context("error gen F32Max")?;
+            numeric::gen_fmin_fmax(environment, true).context("error gen F32Max")?;
         }
         Operator::F32Copysign => {
             let (v1, v2) = environment.pop2();
@@ -1404,6 +1486,12 @@ pub(super) fn parse_instruction<'a>(
             unreachable!("- unimplemented inst {:?}", op);
         }
     }
+
+    if (environment.args.canonicalize_nans || environment.args.deterministic)
+        && numeric::is_nan_producing(op)
+    {
+        numeric::canonicalize_nan(environment).context("error canonicalize NaN")?;
+    }
     Ok(())
 }
 
@@ -3,6 +3,7 @@
 use crate::environment::Environment;
 use anyhow::Result;
 use inkwell::values::BasicValue;
+use wasmparser::Operator;
 
 pub fn helper_code_gen_comparison(
     cond: inkwell::IntPredicate,
@@ -143,3 +144,130 @@ pub fn gen_rotr(environment: &mut Environment<'_, '_>, if_32bit: bool) -> Result
     environment.stack.push(res.as_basic_value_enum());
     Ok(())
 }
+
+/// Generate Wasm `min`/`max`, which propagates NaN and orders -0 before +0.
+/// `llvm.minnum`/`llvm.maxnum` return the non-NaN operand, so they can't be used.
+pub fn gen_fmin_fmax(environment: &mut Environment<'_, '_>, is_max: bool) -> Result<()> {
+    let (v1, v2) = environment.pop2();
+    let v1 = v1.into_float_value();
+    let v2 = v2.into_float_value();
+    let float_type = v1.get_type();
+    let int_type = if float_type == environment.inkwell_types.f32_type {
+        environment.inkwell_types.i32_type
+    } else {
+        environment.inkwell_types.i64_type
+    };
+
+    // Either operand is NaN: return a quiet NaN
+    let is_nan =
+        environment
+            .builder
+            .build_float_compare(inkwell::FloatPredicate::UNO, v1, v2, "is_nan");
+    let nan = environment.builder.build_float_add(v1, v2, "nan");
+
+    // Operands are equal: they differ only if they are +0 and -0, so merge the sign bits
+    let is_eq =
+        environment
+            .builder
+            .build_float_compare(inkwell::FloatPredicate::OEQ, v1, v2, "is_eq");
+    let bits1 = environment.builder.build_bitcast(v1, int_type, "");
+    let bits2 = environment.builder.build_bitcast(v2, int_type, "");
+    let merged_bits = if is_max {
+        environment
+            .builder
+            .build_and(bits1.into_int_value(), bits2.into_int_value(), "")
+    } else {
+        environment
+            .builder
+            .build_or(bits1.into_int_value(), bits2.into_int_value(), "")
+    };
+    let merged = environment
+        .builder
+        .build_bitcast(merged_bits, float_type, "merged");
+
+    // Otherwise: ordinary comparison
+    let pred = if is_max {
+        inkwell::FloatPredicate::OGT
+    } else {
+        inkwell::FloatPredicate::OLT
+    };
+    let is_v1 = environment.builder.build_float_compare(pred, v1, v2, "");
+    let ordered = environment.builder.build_select(
+        is_v1,
+        v1.as_basic_value_enum(),
+        v2.as_basic_value_enum(),
+        "ordered",
+    );
+
+    let res = environment.builder.build_select(is_eq, merged, ordered, "");
+    let res = environment
+        .builder
+        .build_select(is_nan, nan.as_basic_value_enum(), res, "");
+    environment.stack.push(res);
+    Ok(())
+}
+
+/// Whether the instruction may produce a NaN whose bit pattern is nondeterministic.
+/// Sign and bitwise instructions (abs, neg, copysign, reinterpret) keep the bits of the operand.
+pub fn is_nan_producing(op: &Operator) -> bool {
+    matches!(
+        op,
+        Operator::F32Add
+            | Operator::F64Add
+            | Operator::F32Sub
+            | Operator::F64Sub
+            | Operator::F32Mul
+            | Operator::F64Mul
+            | Operator::F32Div
+            | Operator::F64Div
+            | Operator::F32Sqrt
+            | Operator::F64Sqrt
+            | Operator::F32Min
+            | Operator::F64Min
+            | Operator::F32Max
+            | Operator::F64Max
+            | Operator::F32Ceil
+            | Operator::F64Ceil
+            | Operator::F32Floor
+            | Operator::F64Floor
+            | Operator::F32Trunc
+            | Operator::F64Trunc
+            | Operator::F32Nearest
+            | Operator::F64Nearest
+            | Operator::F32DemoteF64
+            | Operator::F64PromoteF32
+    )
+}
+
+/// Replace NaN on the top of the stack with the canonical NaN.
+pub fn canonicalize_nan(environment: &mut Environment<'_, '_>) -> Result<()> {
+    let v = environment
+        .stack
+        .pop()
+        .expect("stack empty")
+        .into_float_value();
+    let canonical_bits = if v.get_type() == environment.inkwell_types.f32_type {
+        environment
+            .inkwell_types
+            .i32_type
+            .const_int(0x7fc0_0000, false)
+    } else {
+        environment
+            .inkwell_types
+            .i64_type
+            .const_int(0x7ff8_0000_0000_0000, false)
+    };
+    let canonical =
+        environment
+            .builder
+            .build_bitcast(canonical_bits, v.get_type(), "canonical_nan");
+    let is_nan =
+        environment
+            .builder
+            .build_float_compare(inkwell::FloatPredicate::UNO, v, v, "is_nan");
+    let res = environment
+        .builder
+        .build_select(is_nan, canonical, v.as_basic_value_enum(), "");
+    environment.stack.push(res);
+    Ok(())
+}
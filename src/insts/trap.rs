@@ -0,0 +1,113 @@
+//! Generation of Wasm traps.
+//!
+//! A trap calls the optional host hook `wasker_trap(kind)` and then executes `llvm.trap`.
+//! The hook is declared `extern_weak`, so that the output links even if the host doesn't define it.
+
+use crate::environment::Environment;
+use anyhow::Result;
+use inkwell::{
+    module::Linkage,
+    values::{FunctionValue, IntValue},
+};
+
+/// Reason of a trap, passed to `wasker_trap`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum TrapKind {
+    /// `call_indirect` with an index out of the table
+    TableOutOfBounds = 1,
+    /// `call_indirect` to an uninitialized table element
+    IndirectCallToNull = 2,
+    /// `call_indirect` to a function of a different signature
+    BadSignature = 3,
+    /// Import argument pointing out of the linear memory
+    MemoryOutOfBounds = 4,
+    /// Call stack exhausted, with `--stack-limit`
+    StackExhausted = 5,
+    /// Exception not caught by the guest, reaching `wasker_main`
+    UncaughtException = 6,
+}
+
+/// Trap if `cond` is true, then continue in a new block.
+pub(crate) fn gen_trap_if<'a>(
+    environment: &mut Environment<'a, '_>,
+    cond: IntValue<'a>,
+    kind: TrapKind,
+) -> Result<()> {
+    let current_fn = environment
+        .builder
+        .get_insert_block()
+        .and_then(|block| block.get_parent())
+        .expect("fail to get current function");
+    let trap_block = environment.context.append_basic_block(current_fn, "trap");
+    let cont_block = environment
+        .context
+        .append_basic_block(current_fn, "trap_cont");
+    environment
+        .builder
+        .build_conditional_branch(cond, trap_block, cont_block);
+
+    environment.builder.position_at_end(trap_block);
+    gen_trap(environment, kind)?;
+
+    environment.builder.position_at_end(cont_block);
+    Ok(())
+}
+
+/// Trap unconditionally. This terminates the current block.
+pub(crate) fn gen_trap(environment: &mut Environment<'_, '_>, kind: TrapKind) -> Result<()> {
+    let current_fn = environment
+        .builder
+        .get_insert_block()
+        .and_then(|block| block.get_parent())
+        .expect("fail to get current function");
+    let hook = trap_hook(environment);
+
+    // Call wasker_trap if the host defines it
+    let hook_block = environment
+        .context
+        .append_basic_block(current_fn, "trap_hook");
+    let abort_block = environment
+        .context
+        .append_basic_block(current_fn, "trap_abort");
+    let hook_defined = environment
+        .builder
+        .build_is_not_null(hook.as_global_value().as_pointer_value(), "hook_defined");
+    environment
+        .builder
+        .build_conditional_branch(hook_defined, hook_block, abort_block);
+
+    environment.builder.position_at_end(hook_block);
+    environment.builder.build_call(
+        hook,
+        &[environment
+            .inkwell_types
+            .i32_type
+            .const_int(kind as u64, false)
+            .into()],
+        "",
+    );
+    environment.builder.build_unconditional_branch(abort_block);
+
+    environment.builder.position_at_end(abort_block);
+    environment
+        .builder
+        .build_call(environment.inkwell_insts.trap, &[], "");
+    environment.builder.build_unreachable();
+    Ok(())
+}
+
+fn trap_hook<'a>(environment: &Environment<'a, '_>) -> FunctionValue<'a> {
+    environment
+        .module
+        .get_function("wasker_trap")
+        .unwrap_or_else(|| {
+            let fn_type = environment
+                .inkwell_types
+                .void_type
+                .fn_type(&[environment.inkwell_types.i32_type.into()], false);
+            environment
+                .module
+                .add_function("wasker_trap", fn_type, Some(Linkage::ExternalWeak))
+        })
+}
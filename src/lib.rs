@@ -1,8 +1,20 @@
 //! Wasker is a WebAssembly compiler written in Rust.
 //! It compiles Wasm binary into ELF format binary.
 
+pub mod cache;
 pub mod compiler;
+pub mod const_expr;
+pub mod coverage;
+pub mod debug_info;
 pub mod environment;
+pub mod epoch;
+pub mod fuel;
+pub mod import_adapter;
 pub mod inkwell;
 pub mod insts;
+pub mod link;
+pub mod partition;
 pub mod section;
+pub mod stack_limit;
+pub mod target_features;
+pub mod trace;
@@ -0,0 +1,131 @@
+//! `link` compiles a Wasm module and links it with a WASI host into an executable.
+//!
+//! The host is `libwasker_wasi.a` by default, found through `WASKER_WASI_LIB` or next to the wasker executable.
+//! A weak `main` calling `wasker_main` is linked too, so hosts may or may not define their own `main`.
+
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::compiler::{self, Args, RelocModel};
+
+const WASKER_WASI_LIB: &str = "libwasker_wasi.a";
+
+const START_SOURCE: &str = "\
+extern void wasker_main(void);
+__attribute__((weak)) int main(void) {
+  wasker_main();
+  return 0;
+}
+";
+
+#[derive(clap::Args, Debug, Default, Clone)]
+#[command(mut_arg("output_file", |arg| arg
+    .default_value("./a.out")
+    .help("Path of the executable")))]
+pub struct BuildArgs {
+    #[command(flatten)]
+    pub compile: Args,
+
+    /// WASI host to link instead of wasker-wasi, as an archive, object or C source
+    #[arg(long, value_name = "PATH")]
+    pub host: Option<PathBuf>,
+
+    /// C compiler driver used to link
+    #[arg(long, default_value = "cc")]
+    pub linker: String,
+
+    /// Pass ARG to the linker. Can be specified multiple times
+    #[arg(long = "link-arg", value_name = "ARG", allow_hyphen_values = true)]
+    pub link_args: Vec<String>,
+
+    /// Link a static executable
+    #[arg(long = "static")]
+    pub static_link: bool,
+}
+
+/// Compile `args.compile.input_file` and link it into the executable `args.compile.output_file`.
+pub fn build_executable(args: &BuildArgs) -> Result<()> {
+    let work_dir = work_dir().context("fail create work dir")?;
+    let result = compile_and_link(args, &work_dir);
+    std::fs::remove_dir_all(&work_dir).context("fail remove work dir")?;
+    result
+}
+
+fn compile_and_link(args: &BuildArgs, work_dir: &Path) -> Result<()> {
+    let object = work_dir.join("wasm.o");
+    compiler::compile_wasm_from_file(&Args {
+        output_file: object.clone(),
+        ..args.compile.clone()
+    })?;
+
+    let start = work_dir.join("wasker_start.c");
+    std::fs::write(&start, START_SOURCE).context("fail write wasker_start.c")?;
+
+    let host = match &args.host {
+        Some(host) => host.clone(),
+        None => find_wasker_wasi()?,
+    };
+
+    let pie = args.compile.pic || args.compile.reloc_model == RelocModel::Pic;
+    let mut linker = std::process::Command::new(&args.linker);
+    linker.arg(match (args.static_link, pie) {
+        (true, true) => "-static-pie",
+        (true, false) => "-static",
+        (false, true) => "-pie",
+        (false, false) => "-no-pie",
+    });
+    // wasker_start.c comes first, so that wasker_main is pulled from an archive output
+    linker
+        .arg("-o")
+        .arg(&args.compile.output_file)
+        .arg(&start)
+        .arg(&object)
+        .arg(&host);
+    if args.host.is_none() {
+        // Libraries used by the Rust standard library in wasker-wasi
+        linker.args(["-lpthread", "-ldl", "-lm"]);
+    }
+    linker.args(&args.link_args);
+
+    log::info!("link {}", args.compile.output_file.display());
+    log::debug!("{linker:?}");
+    let status = linker
+        .status()
+        .with_context(|| format!("fail to run {}", args.linker))?;
+    if !status.success() {
+        bail!("{} failed with {status}", args.linker);
+    }
+    Ok(())
+}
+
+fn find_wasker_wasi() -> Result<PathBuf> {
+    if let Some(path) = std::env::var_os("WASKER_WASI_LIB") {
+        return Ok(path.into());
+    }
+    let exe = std::env::current_exe().context("fail get current exe")?;
+    if let Some(path) = exe
+        .parent()
+        .map(|dir| dir.join(WASKER_WASI_LIB))
+        .filter(|path| path.exists())
+    {
+        return Ok(path);
+    }
+    bail!(
+        "{WASKER_WASI_LIB} not found next to {}. \
+         Build it with `cargo build --release -p wasker-wasi`, set WASKER_WASI_LIB, or pass --host",
+        exe.display()
+    );
+}
+
+// Private directory for intermediate files, unique among concurrent builds
+fn work_dir() -> Result<PathBuf> {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let dir = std::env::temp_dir().join(format!(
+        "wasker-build-{}-{}",
+        std::process::id(),
+        COUNT.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
@@ -1,14 +1,57 @@
 use anyhow::Result;
-use clap::Parser;
-use wasker::compiler;
+use clap::{error::ErrorKind, CommandFactory, Parser, Subcommand};
+use wasker::{compiler, link};
+
+#[derive(Parser)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    args: Option<compiler::Args>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Compile a Wasm module and link it with a WASI host into an executable
+    Build(link::BuildArgs),
+}
 
 fn main() -> Result<()> {
     // init logger
     env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
-    let args = compiler::Args::parse();
+    let cli = Cli::parse();
+
+    // Report invalid combinations of options as usage errors
+    let compile_args = match &cli.command {
+        Some(Command::Build(args)) => Some(&args.compile),
+        None => cli.args.as_ref(),
+    };
+    if let Some(Err(e)) = compile_args.map(compiler::check_args) {
+        Cli::command()
+            .error(ErrorKind::ArgumentConflict, e.to_string())
+            .exit();
+    }
 
-    // Compile Wasm and output ELF
-    compiler::compile_wasm_from_file(&args)?;
+    match cli.command {
+        Some(Command::Build(args)) => {
+            // Compile Wasm and link it into an executable
+            link::build_executable(&args)?;
+        }
+        None => {
+            // Compile Wasm and output ELF
+            let Some(args) = cli.args else {
+                Cli::command()
+                    .error(
+                        ErrorKind::MissingRequiredArgument,
+                        "the following required arguments were not provided:\n  <INPUT_FILE>",
+                    )
+                    .exit();
+            };
+            compiler::compile_wasm_from_file(&args)?;
+        }
+    }
 
     Ok(())
 }
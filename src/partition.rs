@@ -0,0 +1,86 @@
+//! `partition` splits the functions of a module for parallel compilation and multi-object output.
+//!
+//! Each partition is translated into its own LLVM module on its own thread.
+//! Only the primary partition defines module-level state such as globals, the table and `wasker_main`;
+//! the others declare them and define just the bodies of their functions.
+
+use anyhow::Result;
+use std::ops::Range;
+use wasmparser::{Parser, Payload, TypeRef};
+
+#[derive(Debug, Clone)]
+pub struct Partition {
+    pub index: usize,
+    // Function indices whose bodies are generated in this partition
+    pub functions: Range<u32>,
+}
+
+/// Split the defined functions into at most `n` contiguous partitions of similar code size.
+/// The split depends only on the module, so that the output is identical between runs.
+pub fn split_functions(wasm: &[u8], n: usize) -> Result<Vec<Partition>> {
+    let (num_imports, sizes) = function_sizes(wasm)?;
+
+    let total: usize = sizes.iter().sum();
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    let mut acc = 0;
+    for (i, size) in sizes.iter().enumerate() {
+        acc += size;
+        if ranges.len() + 1 < n && acc * n >= total * (ranges.len() + 1) {
+            ranges.push(start..i + 1);
+            start = i + 1;
+        }
+    }
+    if start < sizes.len() || ranges.is_empty() {
+        ranges.push(start..sizes.len());
+    }
+
+    Ok(to_partitions(num_imports, ranges))
+}
+
+/// Split the defined functions into contiguous partitions of `per_partition` functions each.
+pub fn chunk_functions(wasm: &[u8], per_partition: usize) -> Result<Vec<Partition>> {
+    let (num_imports, sizes) = function_sizes(wasm)?;
+    let mut ranges: Vec<Range<usize>> = (0..sizes.len())
+        .step_by(per_partition)
+        .map(|start| start..(start + per_partition).min(sizes.len()))
+        .collect();
+    if ranges.is_empty() {
+        ranges.push(0..0);
+    }
+    Ok(to_partitions(num_imports, ranges))
+}
+
+// The number of imported functions and the code size of each defined function
+fn function_sizes(wasm: &[u8]) -> Result<(u32, Vec<usize>)> {
+    let mut num_imports = 0;
+    let mut sizes = Vec::new();
+    for payload in Parser::new(0).parse_all(wasm) {
+        match payload? {
+            Payload::ImportSection(imports) => {
+                for import in imports {
+                    if let TypeRef::Func(_) = import?.ty {
+                        num_imports += 1;
+                    }
+                }
+            }
+            Payload::CodeSectionEntry(body) => {
+                sizes.push(body.range().len());
+            }
+            _other => {}
+        }
+    }
+    Ok((num_imports, sizes))
+}
+
+// Ranges of defined functions to partitions of function indices
+fn to_partitions(num_imports: u32, ranges: Vec<Range<usize>>) -> Vec<Partition> {
+    ranges
+        .into_iter()
+        .enumerate()
+        .map(|(index, range)| Partition {
+            index,
+            functions: range.start as u32 + num_imports..range.end as u32 + num_imports,
+        })
+        .collect()
+}
@@ -2,35 +2,55 @@
 
 use anyhow::{anyhow, bail, Context, Ok, Result};
 use inkwell::{
-    attributes::Attribute,
-    types::{BasicMetadataTypeEnum, BasicType, BasicTypeEnum, FunctionType},
-    values::{BasicValue, BasicValueEnum, PhiValue, PointerValue},
+    attributes::{Attribute, AttributeLoc},
+    module::Linkage,
+    types::{AsTypeRef, BasicMetadataTypeEnum, BasicType, BasicTypeEnum, FunctionType},
+    values::{
+        AsValueRef, BasicMetadataValueEnum, FunctionValue, PhiValue, PointerValue, StructValue,
+    },
     AddressSpace,
 };
 use wasmparser::{
-    Chunk, CustomSectionReader, DataKind, DataSectionReader, Element, ElementItems, ElementKind,
-    ElementSectionReader, ExportSectionReader, FunctionBody, FunctionSectionReader,
-    GlobalSectionReader, ImportSectionReader, MemorySectionReader, Name, NameSectionReader,
-    Operator, Parser, Payload, SectionLimited, TableSectionReader, TypeRef, TypeSectionReader,
-    ValType,
+    BinaryReader, Chunk, CustomSectionReader, DataKind, DataSectionReader, Element, ElementItems,
+    ElementKind, ElementSectionReader, ExportSectionReader, FunctionBody, FunctionSectionReader,
+    GlobalSectionReader, ImportSectionReader, IndirectNameMap, MemorySectionReader, Name, NameMap,
+    NameSectionReader, Operator, Parser, Payload, SectionLimited, TableSectionReader,
+    TagSectionReader, TypeRef, TypeSectionReader, ValType,
 };
 
+use std::collections::HashMap;
+use std::ffi::CString;
+
+use crate::compiler::{FramePointer, StackProbe};
+use crate::const_expr::{eval_const_expr, is_const};
+use crate::coverage::{self, Coverage};
+use crate::epoch;
+use crate::fuel::FuelMeter;
+use crate::import_adapter::{adapt_signature, resolve_import_adapters, with_context_param};
 use crate::inkwell::InkwellTypes;
-use crate::insts::control;
+use crate::insts::trap::{self, TrapKind};
+use crate::insts::{control, exception};
+use crate::stack_limit;
+use crate::target_features::check_target_features;
+use crate::trace;
 use crate::{
-    environment::{Environment, Global},
+    environment::{DylinkInfo, Environment, Global},
     insts::parse_instruction,
 };
 
 /// Parse Wasm binary and generate LLVM IR
 pub fn translate_module(mut data: &[u8], environment: &mut Environment<'_, '_>) -> Result<()> {
+    // Parse CustomSectionReader in advance to get function name and target features
+    prescan_custom_sections(data, environment)?;
+
     // Setup wasker_main and wasker_init
     setup(environment)?;
 
-    // Parse CustomSectionReader in advance to get function name
-
     // Parse Wasm binary and generate LLVM IR
     let mut code_section_data: Option<&[u8]> = None;
+    let mut code_section_start: usize = 0;
+    let mut globals_section: Option<GlobalSectionReader<'_>> = None;
+    let mut data_section: Option<DataSectionReader<'_>> = None;
     let mut elements_section: Option<SectionLimited<'_, Element<'_>>> = None;
 
     let mut parser = Parser::new(0);
@@ -61,10 +81,14 @@ pub fn translate_module(mut data: &[u8], environment: &mut Environment<'_, '_>)
                 parse_memory_section(memories, environment)?;
             }
             Payload::TableSection(tables) => {
-                parse_table_section(tables)?;
+                parse_table_section(tables, environment)?;
+            }
+            Payload::TagSection(tags) => {
+                parse_tag_section(tags, environment)?;
             }
             Payload::GlobalSection(globals) => {
-                parse_global_section(globals, environment)?;
+                // parse later, as initializers may refer to functions by ref.func
+                globals_section = Some(globals);
             }
             Payload::ExportSection(exports) => {
                 parse_export_section(exports, environment)?;
@@ -74,7 +98,8 @@ pub fn translate_module(mut data: &[u8], environment: &mut Environment<'_, '_>)
                 elements_section = Some(elements);
             }
             Payload::DataSection(datas) => {
-                parse_data_section(datas, environment)?;
+                // parse later, as offsets may refer to globals
+                data_section = Some(datas);
             }
             Payload::CodeSectionEntry(_) => {
                 // parse later
@@ -91,6 +116,7 @@ pub fn translate_module(mut data: &[u8], environment: &mut Environment<'_, '_>)
             }
             Payload::CodeSectionStart { count, range, size } => {
                 log::trace!("CodeSectionStart: count:{count}, range:{range:?}, size:{size}",);
+                code_section_start = range.start;
                 parser.skip_section();
                 data = &data[size as usize..];
             }
@@ -100,7 +126,22 @@ pub fn translate_module(mut data: &[u8], environment: &mut Environment<'_, '_>)
         }
     }
 
+    if !environment.tag_list_signature.is_empty() {
+        setup_exception_handling(environment)?;
+    }
+    if let Some(element_section) = &elements_section {
+        collect_table_functions(element_section.clone(), environment)?;
+    }
+    if let Some(globals_section) = &globals_section {
+        collect_global_functions(globals_section.clone(), environment)?;
+    }
     define_functions(environment)?;
+    if let Some(globals_section) = globals_section {
+        parse_global_section(globals_section, environment)?;
+    }
+    if let Some(data_section) = data_section {
+        parse_data_section(data_section, environment)?;
+    }
     if let Some(element_section) = elements_section {
         parse_element_section(element_section, environment)?;
     }
@@ -112,7 +153,9 @@ pub fn translate_module(mut data: &[u8], environment: &mut Environment<'_, '_>)
             {
                 cs_data = &cs_data[consumed..];
                 match payload {
-                    Payload::CodeSectionStart { .. } => (),
+                    Payload::CodeSectionStart { range, .. } => {
+                        environment.code_offset_delta = range.start - code_section_start;
+                    }
                     Payload::CodeSectionEntry(f) => {
                         parse_code_section(f, environment)?;
                     }
@@ -129,6 +172,61 @@ pub fn translate_module(mut data: &[u8], environment: &mut Environment<'_, '_>)
 
     // complete wasker_main and wasker_init
     complete(environment)?;
+
+    if !environment.is_primary() {
+        externalize_module_state(environment);
+    }
+    Ok(())
+}
+
+// Leave module-level state to the primary partition.
+// Globals are defined there and only referenced here, and wasker_main is removed.
+// Internal globals and coverage records belong to the functions of this partition.
+fn externalize_module_state(environment: &mut Environment<'_, '_>) {
+    let mut global = environment.module.get_first_global();
+    while let Some(g) = global {
+        if g.get_initializer().is_some()
+            && g.get_linkage() != Linkage::Internal
+            && !coverage::is_record(g)
+        {
+            g.set_linkage(Linkage::AvailableExternally);
+        }
+        global = g.get_next_global();
+    }
+    if let Some(wasker_main) = environment.module.get_function("wasker_main") {
+        unsafe { wasker_main.delete() };
+    }
+}
+
+// Find custom sections needed before generating IR.
+// The name section usually follows the sections it names.
+fn prescan_custom_sections(mut data: &[u8], environment: &mut Environment<'_, '_>) -> Result<()> {
+    let mut parser = Parser::new(0);
+    loop {
+        let payload = match parser.parse(data, true)? {
+            Chunk::Parsed { consumed, payload } => {
+                data = &data[consumed..];
+                payload
+            }
+            // this state isn't possible with `eof = true`
+            Chunk::NeedMoreData(_) => unreachable!(),
+        };
+        match payload {
+            Payload::CustomSection(c) if c.name() == "name" => {
+                parse_name_section(c, environment)?;
+            }
+            Payload::CustomSection(c) if c.name() == "target_features" => {
+                check_target_features(c.data(), environment.args)
+                    .context("error check target_features")?;
+            }
+            Payload::CodeSectionStart { size, .. } => {
+                parser.skip_section();
+                data = &data[size as usize..];
+            }
+            Payload::End(..) => break,
+            _other => {}
+        }
+    }
     Ok(())
 }
 
@@ -142,10 +240,15 @@ pub fn wasmparser_to_inkwell<'a>(
         ValType::I64 => Ok(BasicTypeEnum::IntType(inkwell_types.i64_type)),
         ValType::F32 => Ok(BasicTypeEnum::FloatType(inkwell_types.f32_type)),
         ValType::F64 => Ok(BasicTypeEnum::FloatType(inkwell_types.f64_type)),
+        ValType::Ref(..) => Ok(BasicTypeEnum::PointerType(inkwell_types.i8_ptr_type)),
         _other => bail!("unimplemented ValType: {:?}", wasmparser_type),
     }
 }
 
+// LLVM calling convention `tailcc`.
+// Calls marked `tail` in tail position are guaranteed to be optimized between `tailcc` functions.
+pub(crate) const LLVM_TAILCC: u32 = 18;
+
 fn define_functions(environment: &mut Environment<'_, '_>) -> Result<()> {
     // assert
     if environment.function_list_name.len() != environment.function_list_signature.len() {
@@ -164,38 +267,150 @@ fn define_functions(environment: &mut Environment<'_, '_>) -> Result<()> {
         unreachable!();
     }
 
+    // update fname from the name section unless the function is already named by its export
+    for (&idx, name) in &environment.names.functions {
+        if idx >= environment.import_section_size
+            && (idx as usize) < environment.function_list_name.len()
+            && environment.function_list_name[idx as usize] == format!("func_{idx}")
+        {
+            environment.function_list_name[idx as usize] = format!("{idx}_{name}");
+        }
+    }
+
     // define functions
     let func_num = environment.function_list_name.len();
     for i in 0..func_num {
         let fname = &environment.function_list_name[i];
         let fsig = environment.function_list_signature[i];
+        let defined = i >= environment.import_section_size as usize;
+
+        // The export name is taken by the thunk called from the host
+        let body_name = if defined && environment.exported_functions.contains(&(i as u32)) {
+            format!("{fname}.tailcc")
+        } else {
+            fname.clone()
+        };
 
         // check if fname is already defined
-        let already_defined = environment.module.get_function(fname);
+        let already_defined = environment.module.get_function(&body_name);
         let fn_value = match already_defined {
             Some(v) => v,
             None => {
-                let f = environment.module.add_function(
-                    fname,
-                    environment.function_signature_list[fsig as usize],
-                    None,
-                );
-                // add attribute
-                // create noredzone attribute
-                let attr_noredzone = environment
-                    .context
-                    .create_enum_attribute(Attribute::get_named_enum_kind_id("noredzone"), 0);
+                let mut signature = environment.function_signature_list[fsig as usize];
+                if let Some(params) = environment.import_adapters.get(&(i as u32)) {
+                    // Through the table, the import would be called with the Wasm signature
+                    if environment.address_taken_functions.contains(&(i as u32)) {
+                        bail!("import adapter can't be applied to {fname} in the table");
+                    }
+                    signature = adapt_signature(environment, signature, params);
+                }
+                if environment.args.import_context && i < environment.import_section_size as usize {
+                    if environment.address_taken_functions.contains(&(i as u32)) {
+                        bail!("--import-context can't be applied to {fname} in the table");
+                    }
+                    signature = with_context_param(environment, signature);
+                }
+                let f = environment.module.add_function(&body_name, signature, None);
+                add_codegen_attributes(environment, f);
 
-                f.add_attribute(inkwell::attributes::AttributeLoc::Function, attr_noredzone);
+                // Defined functions use tailcc so that return_call doesn't grow the native stack
+                if defined {
+                    f.set_call_conventions(LLVM_TAILCC);
+                }
                 f
             }
         };
         environment.function_list.push(fn_value);
     }
 
+    // Thunks between tailcc and the C calling convention
+    let insert_block = environment.builder.get_insert_block();
+    for i in 0..func_num as u32 {
+        let f = environment.function_list[i as usize];
+        let fname = environment.function_list_name[i as usize].clone();
+        if i >= environment.import_section_size {
+            // Exports are called by the host with the C calling convention
+            if environment.exported_functions.contains(&i) && environment.compiles_function(i) {
+                let thunk = environment.module.add_function(&fname, f.get_type(), None);
+                build_thunk(environment, thunk, f);
+            }
+        } else if environment.address_taken_functions.contains(&i) {
+            // Imports in the table are called with tailcc by call_indirect.
+            // Imports of the same name share the thunk as well as the function.
+            let thunk_name = format!("{fname}.tailcc");
+            let thunk = match environment.module.get_function(&thunk_name) {
+                Some(thunk) => thunk,
+                None => {
+                    let thunk = environment
+                        .module
+                        .add_function(&thunk_name, f.get_type(), None);
+                    thunk.set_call_conventions(LLVM_TAILCC);
+                    if environment.is_primary() {
+                        build_thunk(environment, thunk, f);
+                    }
+                    thunk
+                }
+            };
+            environment.import_thunks.insert(i, thunk);
+        }
+    }
+    if let Some(block) = insert_block {
+        environment.builder.position_at_end(block);
+    }
+
     Ok(())
 }
 
+// Define `thunk` as a tail call to `callee`, converting the calling convention
+fn build_thunk<'a>(
+    environment: &Environment<'a, '_>,
+    thunk: FunctionValue<'a>,
+    callee: FunctionValue<'a>,
+) {
+    add_codegen_attributes(environment, thunk);
+    let block = environment.context.append_basic_block(thunk, "entry");
+    environment.builder.position_at_end(block);
+    let args: Vec<BasicMetadataValueEnum> = thunk.get_param_iter().map(Into::into).collect();
+    let call_site = environment.builder.build_call(callee, &args, "");
+    call_site.set_call_convention(callee.get_call_conventions());
+    call_site.set_tail_call(true);
+    match call_site.try_as_basic_value().left() {
+        Some(ret) => environment.builder.build_return(Some(&ret)),
+        None => environment.builder.build_return(None),
+    };
+}
+
+// Add attributes specified by codegen options to a generated function
+fn add_codegen_attributes(environment: &Environment<'_, '_>, f: FunctionValue<'_>) {
+    let context = environment.context;
+    let mut attrs =
+        vec![context.create_enum_attribute(Attribute::get_named_enum_kind_id("noredzone"), 0)];
+
+    match environment.args.frame_pointer {
+        FramePointer::Default => {}
+        FramePointer::None => attrs.push(context.create_string_attribute("frame-pointer", "none")),
+        FramePointer::NonLeaf => {
+            attrs.push(context.create_string_attribute("frame-pointer", "non-leaf"))
+        }
+        FramePointer::All => attrs.push(context.create_string_attribute("frame-pointer", "all")),
+    }
+
+    match environment.args.stack_probe {
+        StackProbe::Default => {}
+        StackProbe::Inline => {
+            attrs.push(context.create_string_attribute("probe-stack", "inline-asm"))
+        }
+        // Frames never reach the probe size, whichever probing the target does by default
+        StackProbe::None => {
+            attrs.push(context.create_string_attribute("stack-probe-size", &u32::MAX.to_string()))
+        }
+    }
+
+    for attr in attrs {
+        f.add_attribute(AttributeLoc::Function, attr);
+    }
+}
+
 // Setup wasker_main and wasker_init
 // Create these block then call memory_base()
 fn setup(environment: &mut Environment<'_, '_>) -> Result<()> {
@@ -204,6 +419,7 @@ fn setup(environment: &mut Environment<'_, '_>) -> Result<()> {
     let wasker_main_fn = environment
         .module
         .add_function("wasker_main", wasker_main_fn_type, None);
+    add_codegen_attributes(environment, wasker_main_fn);
 
     // Define init, enrty, return block
     let wasker_init_block = environment
@@ -288,9 +504,12 @@ fn complete(environment: &mut Environment<'_, '_>) -> Result<()> {
     );
     match environment.start_function_idx {
         Some(idx) => {
-            environment
-                .builder
-                .build_call(environment.function_list[idx as usize], &[], "");
+            let start = environment.function_list[idx as usize];
+            let call_site = environment.builder.build_call(start, &[], "");
+            call_site.set_call_convention(start.get_call_conventions());
+            if environment.exception_handling {
+                exception::gen_uncaught_check(environment)?;
+            }
         }
         None => {
             log::warn!("_start is not defined");
@@ -304,9 +523,20 @@ fn parse_type_section(
     types: TypeSectionReader,
     environment: &mut Environment<'_, '_>,
 ) -> Result<()> {
+    let mut canonical_types: Vec<wasmparser::FuncType> = Vec::new();
     for entry in types {
         log::trace!("Type Section: {entry:?}");
         if let anyhow::Result::Ok(wasmparser::Type::Func(functype)) = entry {
+            // Signatures which are structurally equal share the same id
+            let id = match canonical_types.iter().position(|ty| *ty == functype) {
+                Some(id) => id,
+                None => {
+                    canonical_types.push(functype.clone());
+                    canonical_types.len() - 1
+                }
+            };
+            environment.function_signature_ids.push(id as u32);
+
             let params = functype.params();
             let returns = functype.results();
 
@@ -344,14 +574,69 @@ fn parse_import_section(
     imports: ImportSectionReader,
     environment: &mut Environment<'_, '_>,
 ) -> Result<()> {
+    let adapters = resolve_import_adapters(environment.args)?;
     for import in imports {
         let import = import?;
         match import.ty {
             TypeRef::Func(ty) => {
+                if let Some(params) =
+                    adapters.get(&(import.module.to_string(), import.name.to_string()))
+                {
+                    let num_params = environment.function_signature_list[ty as usize]
+                        .count_param_types() as usize;
+                    if params.len() != num_params {
+                        bail!(
+                            "import adapter of {}.{} has {} params, but the import has {num_params}",
+                            import.module,
+                            import.name,
+                            params.len()
+                        );
+                    }
+                    environment
+                        .import_adapters
+                        .insert(environment.import_section_size, params.clone());
+                }
                 environment.function_list_signature.push(ty);
                 environment.function_list_name.push(import.name.to_string());
                 environment.import_section_size += 1;
             }
+            TypeRef::Tag(tag) => {
+                environment.tag_list_name.push(import.name.to_string());
+                environment.tag_list_signature.push(tag.func_type_idx);
+            }
+            TypeRef::Global(global) => {
+                let ty = wasmparser_to_inkwell(&global.content_type, &environment.inkwell_types)?;
+
+                // The table is private to the object, so a dynamic-linking module places
+                // its elements from the beginning of the table
+                if environment.dylink.is_some() && import.name == "__table_base" {
+                    environment.global.push(Global::Const {
+                        value: ty.into_int_type().const_zero().into(),
+                    });
+                    continue;
+                }
+
+                // Imported globals are defined by the host and always read from memory
+                let global_value =
+                    environment
+                        .module
+                        .add_global(ty, Some(AddressSpace::default()), import.name);
+                environment.global.push(Global::Mut {
+                    ptr_to_value: global_value,
+                    ty,
+                });
+            }
+            TypeRef::Memory(memory) => {
+                // Imported memory is owned by the host, which allocates its initial pages
+                declare_memory(environment, memory.initial as u32, false);
+            }
+            TypeRef::Table(table) => {
+                let size = match &environment.dylink {
+                    Some(dylink) => table.initial.max(dylink.table_size),
+                    None => table.initial,
+                };
+                declare_table(environment, size)?;
+            }
             _other => {}
         }
     }
@@ -380,13 +665,18 @@ fn parse_memory_section(
     memories: MemorySectionReader,
     environment: &mut Environment<'_, '_>,
 ) -> Result<()> {
-    // Declare memory size as a global value
     let mut size: u32 = 0;
     for (i, memory) in memories.into_iter().enumerate() {
         let memory = memory?;
         size += memory.initial as u32;
         log::trace!("- memory[{i}] = {memory:?}");
     }
+    declare_memory(environment, size, true);
+    Ok(())
+}
+
+fn declare_memory(environment: &mut Environment<'_, '_>, size: u32, allocate: bool) {
+    // Declare memory size as a global value
     let global = environment.module.add_global(
         environment.inkwell_types.i32_type,
         Some(AddressSpace::default()),
@@ -400,6 +690,10 @@ fn parse_memory_section(
     );
     environment.global_memory_size = Some(global);
 
+    if !allocate {
+        return;
+    }
+
     // malloc memory from OS
     environment.builder.build_call(
         environment
@@ -412,13 +706,98 @@ fn parse_memory_section(
             .into()],
         "linear_memory_offset",
     );
-    Ok(())
 }
 
-fn parse_table_section(tables: TableSectionReader) -> Result<()> {
+fn parse_table_section(
+    tables: TableSectionReader,
+    environment: &mut Environment<'_, '_>,
+) -> Result<()> {
     for (i, table) in tables.into_iter().enumerate() {
         let table = table?;
         log::trace!("- table[{}] size={:?}", i, table.ty.initial);
+        declare_table(environment, table.ty.initial)?;
+    }
+    Ok(())
+}
+
+fn declare_table(environment: &mut Environment<'_, '_>, size: u32) -> Result<()> {
+    // TODO: support multiple tables
+    if environment.global_table.is_some() {
+        bail!("TableSection: multiple tables unsupported");
+    }
+
+    // Declare table as global. Elements are initialized in ElementSection
+    let table_type = environment.table_entry_type().array_type(size);
+    let global_table =
+        environment
+            .module
+            .add_global(table_type, Some(AddressSpace::default()), "global_table");
+    global_table.set_initializer(&table_type.const_zero());
+    environment.global_table = Some(global_table);
+    environment.table_size = size;
+    Ok(())
+}
+
+fn parse_tag_section(tags: TagSectionReader, environment: &mut Environment<'_, '_>) -> Result<()> {
+    for tag in tags {
+        let tag = tag?;
+        let tname = format!("{}", environment.tag_list_name.len());
+        environment.tag_list_signature.push(tag.func_type_idx);
+        environment.tag_list_name.push(tname);
+    }
+    log::trace!("- declare {} tags", environment.tag_list_signature.len());
+    Ok(())
+}
+
+// Declare globals holding the in-flight exception and symbols of tags
+fn setup_exception_handling(environment: &mut Environment<'_, '_>) -> Result<()> {
+    environment.exception_handling = true;
+
+    let exception_tag = environment.module.add_global(
+        environment.inkwell_types.i32_type,
+        Some(AddressSpace::default()),
+        "wasker_exception_tag",
+    );
+    exception_tag.set_initializer(
+        &environment
+            .inkwell_types
+            .i32_type
+            .const_int(exception::NO_EXCEPTION, false),
+    );
+    environment.exception_tag = Some(exception_tag);
+
+    // Each value carried by an exception occupies one i64 slot
+    let payload_len = environment
+        .tag_list_signature
+        .iter()
+        .map(|sig| environment.function_signature_list[*sig as usize].count_param_types())
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let payload_type = environment.inkwell_types.i64_type.array_type(payload_len);
+    let exception_payload = environment.module.add_global(
+        payload_type,
+        Some(AddressSpace::default()),
+        "wasker_exception_payload",
+    );
+    exception_payload.set_initializer(&payload_type.const_zero());
+    environment.exception_payload = Some(exception_payload);
+    environment.exception_payload_len = payload_len;
+
+    // Tags are identified by their index
+    for (i, tname) in environment.tag_list_name.iter().enumerate() {
+        let tag_global = environment.module.add_global(
+            environment.inkwell_types.i32_type,
+            Some(AddressSpace::default()),
+            &format!("wasker_tag_{tname}"),
+        );
+        tag_global.set_initializer(
+            &environment
+                .inkwell_types
+                .i32_type
+                .const_int(i as u64, false),
+        );
+        tag_global.set_constant(true);
     }
     Ok(())
 }
@@ -429,61 +808,41 @@ fn parse_global_section(
 ) -> Result<()> {
     // Hold function signature
     // These functions will be registerd in ExportSection
-    for (i, global) in globals.into_iter().enumerate() {
+    for global in globals {
         let global = global?;
-        let gname = format!("global_{i}");
+        // Qualify names by index, so that they collide neither with each other
+        // nor with globals of the runtime such as `global_table`
+        let idx = environment.global.len();
+        let gname = match environment.names.globals.get(&(idx as u32)) {
+            Some(name) => format!("global_{idx}_{name}"),
+            None => format!("global_{idx}"),
+        };
         let ty = wasmparser_to_inkwell(&global.ty.content_type, &environment.inkwell_types)?;
 
         // Get initial value
-        let init_expr_binary_reader = &mut global.init_expr.get_binary_reader();
-        let init_val: BasicValueEnum = match init_expr_binary_reader
-            .read_operator()
-            .expect("fail read_operator")
-        {
-            Operator::I32Const { value } => ty
-                .into_int_type()
-                .const_int(value as u64, false)
-                .as_basic_value_enum(),
-            Operator::I64Const { value } => ty
-                .into_int_type()
-                .const_int(value as u64, false)
-                .as_basic_value_enum(),
-            Operator::F32Const { value } => ty
-                .into_float_type()
-                .const_float(f32::from_bits(value.bits()).into())
-                .as_basic_value_enum(),
-            Operator::F64Const { value } => ty
-                .into_float_type()
-                .const_float(f64::from_bits(value.bits()))
-                .as_basic_value_enum(),
-            _other => {
-                bail!("Unsupposed Global const value");
-            }
-        };
+        let init_val = eval_const_expr(environment, &global.init_expr)
+            .context("error eval global initializer")?;
 
         // declare
-        if global.ty.mutable {
+        if global.ty.mutable || !is_const(&init_val) {
             // Declare GlobalValue
             let global_value =
                 environment
                     .module
                     .add_global(ty, Some(AddressSpace::default()), &gname);
-            match init_val.get_type() {
-                BasicTypeEnum::IntType(..) => {
-                    global_value.set_initializer(&init_val.into_int_value());
-                }
-                BasicTypeEnum::FloatType(..) => {
-                    global_value.set_initializer(&init_val.into_float_value());
-                }
-                _other => {
-                    bail!("Unsupposed Global mutable value");
-                }
+            if is_const(&init_val) {
+                global_value.set_initializer(&init_val);
+            } else {
+                // Initialize in wasker_init
+                global_value.set_initializer(&ty.const_zero());
+                environment
+                    .builder
+                    .build_store(global_value.as_pointer_value(), init_val);
             }
             environment.global.push(Global::Mut {
                 ptr_to_value: global_value,
                 ty,
             });
-            global_value.set_initializer(&init_val);
         } else {
             // declare as BasicValueEnum
             environment.global.push(Global::Const { value: init_val });
@@ -504,6 +863,7 @@ fn parse_export_section(
         match export.kind {
             wasmparser::ExternalKind::Func => {
                 log::trace!("Export func[{}] = {}", export.name, export.index);
+                environment.exported_functions.insert(export.index);
                 environment.function_list_name[export.index as usize] = export.name.to_string();
                 if export.name == "_start" {
                     environment.function_list_name[export.index as usize] =
@@ -511,6 +871,10 @@ fn parse_export_section(
                     environment.start_function_idx = Some(export.index);
                 }
             }
+            wasmparser::ExternalKind::Tag => {
+                log::trace!("Export tag[{}] = {}", export.name, export.index);
+                environment.tag_list_name[export.index as usize] = export.name.to_string();
+            }
             _other => {
                 log::trace!("ExportSection: not support other than Memory");
             }
@@ -519,10 +883,44 @@ fn parse_export_section(
     Ok(())
 }
 
+// Collect functions referenced by the table before defining functions.
+fn collect_table_functions(
+    elements: ElementSectionReader,
+    environment: &mut Environment<'_, '_>,
+) -> Result<()> {
+    for element in elements {
+        let element = element?;
+        for item in read_element_items(element.items)?.into_iter().flatten() {
+            environment.address_taken_functions.insert(item);
+        }
+    }
+    Ok(())
+}
+
+// Collect functions referenced by global initializers before defining functions.
+fn collect_global_functions(
+    globals: GlobalSectionReader,
+    environment: &mut Environment<'_, '_>,
+) -> Result<()> {
+    for global in globals {
+        let global = global?;
+        for op in global.init_expr.get_operators_reader() {
+            if let Operator::RefFunc { function_index } = op? {
+                environment.address_taken_functions.insert(function_index);
+            }
+        }
+    }
+    Ok(())
+}
+
 fn parse_element_section(
     elements: ElementSectionReader,
     environment: &mut Environment<'_, '_>,
 ) -> Result<()> {
+    // Function index of each table element
+    let mut table_elements: Vec<Option<u32>> = vec![None; environment.table_size as usize];
+    // Segments whose offset is known only at runtime
+    let mut runtime_segments = Vec::new();
     for element in elements {
         let element = element?;
         match element.kind {
@@ -534,49 +932,26 @@ fn parse_element_section(
                 // TODO: support multiple tables
                 assert_eq!(table_index, 0);
 
-                let offset_op = offset_expr
-                    .get_binary_reader()
-                    .read_operator()
-                    .expect("failed to get data section offset");
-                let offset = match offset_op {
-                    Operator::I32Const { value } => value,
-                    _other => unreachable!("unsupported offset type"),
+                let items = read_element_items(element.items)?;
+                let offset = eval_const_expr(environment, &offset_expr)
+                    .context("error eval element offset")?
+                    .into_int_value();
+                let Some(offset) = offset.get_zero_extended_constant() else {
+                    runtime_segments.push((offset, items));
+                    continue;
                 };
-                match element.items {
-                    ElementItems::Functions(elems) => {
-                        // Declare function pointer array as global
-                        let count = elems.count();
-                        let array_fpointer = environment
-                            .inkwell_types
-                            .i8_ptr_type
-                            .array_type(count + offset as u32);
-                        let global_table = environment.module.add_global(
-                            array_fpointer,
-                            Some(AddressSpace::default()),
-                            "global_table",
-                        );
-                        environment.global_table = Some(global_table);
-
-                        // Initialize function pointer array
-                        let mut fpointers: Vec<PointerValue> = Vec::new();
-                        for _ in 0..offset {
-                            fpointers.push(environment.inkwell_types.i8_ptr_type.const_null());
-                        }
-                        for (i, elem) in elems.into_iter().enumerate() {
-                            let elem = elem?;
-                            let func = environment.function_list[elem as usize];
-                            fpointers.push(func.as_global_value().as_pointer_value());
-                            log::trace!("- elem[{}] = Function[{}]", i + offset as usize, elem);
-                        }
-                        let initializer = environment
-                            .inkwell_types
-                            .i8_ptr_type
-                            .const_array(&fpointers);
-                        global_table.set_initializer(&initializer);
-                    }
-                    ElementItems::Expressions { .. } => {
-                        bail!("ElementSection: Expressions item Unsupported");
-                    }
+                let offset = offset as usize;
+                if offset + items.len() > table_elements.len() {
+                    bail!(
+                        "ElementSection: elements [{}, {}) out of table size {}",
+                        offset,
+                        offset + items.len(),
+                        table_elements.len()
+                    );
+                }
+                for (i, item) in items.into_iter().enumerate() {
+                    table_elements[offset + i] = item;
+                    log::trace!("- elem[{}] = Function[{:?}]", offset + i, item);
                 }
             }
             ElementKind::Declared => {
@@ -587,9 +962,103 @@ fn parse_element_section(
             }
         }
     }
+
+    // Initialize table with pairs of function pointer and signature id
+    let entries: Vec<_> = table_elements
+        .iter()
+        .map(|item| table_entry(environment, *item))
+        .collect();
+    let global_table = environment
+        .global_table
+        .expect("should define global_table");
+    global_table.set_initializer(&environment.table_entry_type().const_array(&entries));
+
+    // Store the rest in wasker_init
+    for (offset, items) in runtime_segments {
+        environment.builder.position_at_end(
+            environment
+                .wasker_init_block
+                .expect("should define wasker_init_block"),
+        );
+        if items.len() > environment.table_size as usize {
+            bail!("ElementSection: elements out of table size");
+        }
+        let limit = environment.inkwell_types.i32_type.const_int(
+            (environment.table_size as usize - items.len()) as u64,
+            false,
+        );
+        let out_of_bounds = environment.builder.build_int_compare(
+            inkwell::IntPredicate::UGT,
+            offset,
+            limit,
+            "out_of_bounds",
+        );
+        trap::gen_trap_if(environment, out_of_bounds, TrapKind::TableOutOfBounds)?;
+        environment.wasker_init_block = environment.builder.get_insert_block();
+
+        for (i, item) in items.into_iter().enumerate() {
+            let idx = environment.builder.build_int_add(
+                offset,
+                environment
+                    .inkwell_types
+                    .i32_type
+                    .const_int(i as u64, false),
+                "elem_idx",
+            );
+            let entry_addr = unsafe {
+                environment.builder.build_gep(
+                    environment.table_entry_type(),
+                    global_table.as_pointer_value(),
+                    &[idx],
+                    "entry_addr",
+                )
+            };
+            environment
+                .builder
+                .build_store(entry_addr, table_entry(environment, item));
+        }
+    }
     Ok(())
 }
 
+// Function index of each item, or None for ref.null
+fn read_element_items(items: ElementItems) -> Result<Vec<Option<u32>>> {
+    match items {
+        ElementItems::Functions(elems) => elems.into_iter().map(|elem| Ok(Some(elem?))).collect(),
+        ElementItems::Expressions(exprs) => exprs
+            .into_iter()
+            .map(|expr| match expr?.get_binary_reader().read_operator()? {
+                Operator::RefFunc { function_index } => Ok(Some(function_index)),
+                Operator::RefNull { .. } => Ok(None),
+                other => bail!("ElementSection: unsupported element expression {other:?}"),
+            })
+            .collect(),
+    }
+}
+
+// Table element, a pair of function pointer and signature id
+fn table_entry<'a>(environment: &Environment<'a, '_>, item: Option<u32>) -> StructValue<'a> {
+    let entry_type = environment.table_entry_type();
+    match item {
+        Some(idx) => {
+            let func = environment.tailcc_function(idx);
+            let sig = environment.function_list_signature[idx as usize];
+            entry_type.const_named_struct(&[
+                func.as_global_value().as_pointer_value().into(),
+                environment
+                    .inkwell_types
+                    .i32_type
+                    .const_int(
+                        environment.function_signature_ids[sig as usize] as u64,
+                        false,
+                    )
+                    .into(),
+            ])
+        }
+        None => entry_type.const_zero(),
+    }
+}
+
 fn parse_data_section(
     datas: DataSectionReader,
     environment: &mut Environment<'_, '_>,
@@ -601,7 +1070,7 @@ fn parse_data_section(
             .expect("should define wasker_init_block"),
     );
 
-    for data in datas {
+    for (i, data) in datas.into_iter().enumerate() {
         let data = data?;
         log::trace!(
             "DataSection　DDDataKind:{:?},  range:{}-{}",
@@ -621,11 +1090,14 @@ fn parse_data_section(
                 let size = data.data.len();
                 log::trace!("- data size = {size}");
                 let array_ty = environment.inkwell_types.i8_type.array_type(size as u32);
-                let global_mem_initializer = environment.module.add_global(
-                    array_ty,
-                    Some(AddressSpace::default()),
-                    "global_mem_initializer",
-                );
+                let dname = match environment.names.data.get(&(i as u32)) {
+                    Some(name) => format!("global_mem_initializer_{i}_{name}"),
+                    None => "global_mem_initializer".to_string(),
+                };
+                let global_mem_initializer =
+                    environment
+                        .module
+                        .add_global(array_ty, Some(AddressSpace::default()), &dname);
 
                 // Initialize array
                 let mut data_intvalue = Vec::new();
@@ -644,19 +1116,55 @@ fn parse_data_section(
                 global_mem_initializer.set_initializer(&initializer);
 
                 // Get offset from the base of the Linear Memory
-                let offset_op = offset_expr
-                    .get_binary_reader()
-                    .read_operator()
-                    .expect("failed to get data section offset");
-                let offset = match offset_op {
-                    Operator::I32Const { value } => value,
-                    _other => unreachable!("unsupported offset type"),
-                };
-                log::trace!("- offset = 0x{offset:x}");
-                let offset_int = environment
-                    .inkwell_types
-                    .i64_type
-                    .const_int(offset as u64, false);
+                let offset = eval_const_expr(environment, &offset_expr)
+                    .context("error eval data offset")?
+                    .into_int_value();
+                log::trace!("- offset = {offset:?}");
+                let offset_int = environment.builder.build_int_z_extend(
+                    offset,
+                    environment.inkwell_types.i64_type,
+                    "offset_int",
+                );
+
+                // Offsets given by imported globals are only known at runtime
+                let mem_size = environment
+                    .builder
+                    .build_load(
+                        environment.inkwell_types.i32_type,
+                        environment
+                            .global_memory_size
+                            .expect("should define global_memory_size")
+                            .as_pointer_value(),
+                        "mem_size",
+                    )
+                    .into_int_value();
+                let mem_size = environment.builder.build_int_z_extend(
+                    mem_size,
+                    environment.inkwell_types.i64_type,
+                    "mem_size_i64",
+                );
+                let mem_bytes = environment.builder.build_int_mul(
+                    mem_size,
+                    environment.inkwell_types.i64_type.const_int(65536, false),
+                    "mem_bytes",
+                );
+                let end = environment.builder.build_int_add(
+                    offset_int,
+                    environment
+                        .inkwell_types
+                        .i64_type
+                        .const_int(size as u64, false),
+                    "end",
+                );
+                let out_of_bounds = environment.builder.build_int_compare(
+                    inkwell::IntPredicate::UGT,
+                    end,
+                    mem_bytes,
+                    "out_of_bounds",
+                );
+                trap::gen_trap_if(environment, out_of_bounds, TrapKind::MemoryOutOfBounds)?;
+                environment.wasker_init_block = environment.builder.get_insert_block();
+
                 let dest_int = environment.builder.build_int_add(
                     environment
                         .linear_memory_offset_int
@@ -696,29 +1204,173 @@ fn parse_custom_section(
     environment: &mut Environment<'_, '_>,
 ) -> Result<()> {
     //println!("{}", pretty_hex(&customs.data()));
-    if customs.name() != "name" {
-        log::trace!("CustomSection other than `name` is not supported");
-        return Ok(());
+    if environment.is_primary()
+        && environment
+            .args
+            .custom_sections
+            .iter()
+            .any(|name| name == customs.name())
+    {
+        emit_custom_section(environment, customs.name(), customs.data())?;
     }
+
+    match customs.name() {
+        "name" | "target_features" => {
+            log::trace!("CustomSection `{}` is parsed in advance", customs.name());
+            Ok(())
+        }
+        "dylink.0" => parse_dylink_section(customs, environment),
+        name if name.starts_with(".debug_") && environment.debug_info.is_some() => {
+            log::warn!("CustomSection `{name}`: embedded DWARF is not translated");
+            Ok(())
+        }
+        _ => {
+            log::trace!("CustomSection `{}` is not supported", customs.name());
+            Ok(())
+        }
+    }
+}
+
+// Copy a custom section into `.wasm.custom.<name>`.
+// The host finds it by `wasker_custom_<symbol>_start` and `wasker_custom_<symbol>_end`,
+// where `symbol` is `name` with characters other than ASCII letters and digits replaced by `_`.
+// Sections mapped to the same symbol are rejected, as the host couldn't tell them apart.
+fn emit_custom_section(
+    environment: &mut Environment<'_, '_>,
+    name: &str,
+    data: &[u8],
+) -> Result<()> {
+    log::trace!("- custom section `{name}` size={}", data.len());
+    let symbol: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let start_name = format!("wasker_custom_{symbol}_start");
+    if environment.module.get_global(&start_name).is_some() {
+        bail!(
+            "custom section `{name}`: symbol `{start_name}` is already defined by a custom section"
+        );
+    }
+
+    let bytes: Vec<_> = data
+        .iter()
+        .map(|&b| environment.inkwell_types.i8_type.const_int(b as u64, false))
+        .collect();
+    let content = environment.inkwell_types.i8_type.const_array(&bytes);
+    let start = environment.module.add_global(
+        content.get_type(),
+        Some(AddressSpace::default()),
+        &start_name,
+    );
+    start.set_initializer(&content);
+    start.set_constant(true);
+    start.set_alignment(1);
+    start.set_section(&format!(".wasm.custom.{name}"));
+
+    // The end symbol is an alias just past the content
+    let end_address = unsafe {
+        start.as_pointer_value().const_gep(
+            content.get_type(),
+            &[environment.inkwell_types.i32_type.const_int(1, false)],
+        )
+    };
+    let end_name =
+        CString::new(format!("wasker_custom_{symbol}_end")).expect("symbol should not contain NUL");
+    unsafe {
+        llvm_sys::core::LLVMAddAlias2(
+            environment.module.as_mut_ptr(),
+            environment.inkwell_types.i8_type.as_type_ref(),
+            0,
+            end_address.as_value_ref(),
+            end_name.as_ptr(),
+        );
+    }
+    Ok(())
+}
+
+// Subsections of `dylink.0`
+const WASM_DYLINK_MEM_INFO: u8 = 1;
+const WASM_DYLINK_NEEDED: u8 = 2;
+
+/// Read `dylink.0` of a dynamic-linking module emitted by Emscripten or wasm-ld
+pub fn read_dylink_section(customs: &CustomSectionReader) -> Result<DylinkInfo> {
+    let mut dylink = DylinkInfo::default();
+    let mut reader = BinaryReader::new_with_offset(customs.data(), customs.data_offset());
+    while !reader.eof() {
+        let kind = reader.read_u8()?;
+        let size = reader.read_var_u32()?;
+        let mut subsection = BinaryReader::new(reader.read_bytes(size as usize)?);
+        match kind {
+            WASM_DYLINK_MEM_INFO => {
+                dylink.memory_size = subsection.read_var_u32()?;
+                dylink.memory_alignment = subsection.read_var_u32()?;
+                dylink.table_size = subsection.read_var_u32()?;
+                dylink.table_alignment = subsection.read_var_u32()?;
+            }
+            WASM_DYLINK_NEEDED => {
+                for _ in 0..subsection.read_var_u32()? {
+                    dylink.needed.push(subsection.read_string()?.to_string());
+                }
+            }
+            _other => {
+                log::trace!("dylink.0: skip subsection {kind}");
+            }
+        }
+    }
+    Ok(dylink)
+}
+
+fn parse_dylink_section(
+    customs: CustomSectionReader,
+    environment: &mut Environment<'_, '_>,
+) -> Result<()> {
+    let dylink = read_dylink_section(&customs)?;
+    log::trace!("- dylink {dylink:?}");
+
+    // Tell the host how much memory to reserve at `__memory_base`
+    for (name, value) in [
+        ("wasker_dylink_memory_size", dylink.memory_size),
+        ("wasker_dylink_memory_alignment", dylink.memory_alignment),
+    ] {
+        let global = environment.module.add_global(
+            environment.inkwell_types.i32_type,
+            Some(AddressSpace::default()),
+            name,
+        );
+        global.set_constant(true);
+        global.set_initializer(
+            &environment
+                .inkwell_types
+                .i32_type
+                .const_int(value as u64, false),
+        );
+    }
+    environment.dylink = Some(dylink);
+    Ok(())
+}
+
+fn parse_name_section(
+    customs: CustomSectionReader,
+    environment: &mut Environment<'_, '_>,
+) -> Result<()> {
     let name_section_reader = NameSectionReader::new(customs.data(), customs.data_offset());
     for name_reader in name_section_reader {
         match name_reader {
             std::result::Result::Ok(entry) => match entry {
                 Name::Function(fnames) => {
-                    for n in fnames {
-                        let n = n.expect("Error get Naming");
-                        // assert
-                        if environment.function_list_name.len() <= n.index as usize {
-                            log::error!("function_list_name length too short");
-                            unreachable!();
-                        }
-
-                        // update fname
-                        if n.name != "_start" && n.index >= environment.import_section_size {
-                            let fname = format!("{}_{}", n.index, n.name);
-                            environment.function_list_name[n.index as usize] = fname;
-                        };
-                    }
+                    collect_name_map(fnames, &mut environment.names.functions)?;
+                }
+                Name::Local(lnames) => {
+                    collect_indirect_name_map(lnames, &mut environment.names.locals)?;
+                }
+                Name::Label(lnames) => {
+                    collect_indirect_name_map(lnames, &mut environment.names.labels)?;
+                }
+                Name::Global(gnames) => {
+                    collect_name_map(gnames, &mut environment.names.globals)?;
+                }
+                Name::Data(dnames) => {
+                    collect_name_map(dnames, &mut environment.names.data)?;
                 }
                 _ => {
                     log::trace!("CustomSection: unsupported Name");
@@ -732,6 +1384,25 @@ fn parse_custom_section(
     Ok(())
 }
 
+fn collect_name_map(names: NameMap, map: &mut HashMap<u32, String>) -> Result<()> {
+    for n in names {
+        let n = n?;
+        map.insert(n.index, n.name.to_string());
+    }
+    Ok(())
+}
+
+fn collect_indirect_name_map(
+    names: IndirectNameMap,
+    map: &mut HashMap<u32, HashMap<u32, String>>,
+) -> Result<()> {
+    for indirect in names {
+        let indirect = indirect?;
+        collect_name_map(indirect.names, map.entry(indirect.index).or_default())?;
+    }
+    Ok(())
+}
+
 fn parse_code_section(f: FunctionBody, environment: &mut Environment<'_, '_>) -> Result<()> {
     // Move to function
     environment.current_function_idx = if environment.current_function_idx == u32::MAX {
@@ -741,12 +1412,25 @@ fn parse_code_section(f: FunctionBody, environment: &mut Environment<'_, '_>) ->
         environment.current_function_idx + 1
     };
     log::trace!("### function idx = {}", environment.current_function_idx);
+    if !environment.compiles_function(environment.current_function_idx) {
+        return Ok(());
+    }
+    environment.exception_unwind_block = None;
 
     // Create block
     let current_fn = environment.function_list[environment.current_function_idx as usize];
     let current_func_block = environment.context.append_basic_block(current_fn, "entry");
     let current_ret_block = environment.context.append_basic_block(current_fn, "ret");
 
+    if let Some(debug_info) = &mut environment.debug_info {
+        debug_info.enter_function(
+            &environment.builder,
+            current_fn,
+            &environment.function_list_name[environment.current_function_idx as usize],
+            f.range().start - environment.code_offset_delta,
+        );
+    }
+
     // Phi
     environment.builder.position_at_end(current_ret_block);
     let ret = current_fn.get_type().get_return_type();
@@ -768,13 +1452,20 @@ fn parse_code_section(f: FunctionBody, environment: &mut Environment<'_, '_>) ->
         });
 
     // params
+    let local_names = environment
+        .names
+        .locals
+        .get(&environment.current_function_idx)
+        .cloned()
+        .unwrap_or_default();
     let mut locals = vec![];
     for idx in 0..current_fn.count_params() {
         let v = current_fn
             .get_nth_param(idx)
             .expect("fail to get_nth_param");
         let ty = current_fn.get_type().get_param_types()[idx as usize];
-        let alloca = environment.builder.build_alloca(ty, "param");
+        let name = local_names.get(&idx).map_or("param", |name| name.as_str());
+        let alloca = environment.builder.build_alloca(ty, name);
         environment.builder.build_store(alloca, v);
         locals.push((alloca, ty));
     }
@@ -786,22 +1477,89 @@ fn parse_code_section(f: FunctionBody, environment: &mut Environment<'_, '_>) ->
         let (count, ty) = local_reader.read()?;
         let ty = wasmparser_to_inkwell(&ty, &environment.inkwell_types)?;
         for _ in 0..count {
-            let alloca = environment.builder.build_alloca(ty, "local");
+            let name = local_names
+                .get(&(locals.len() as u32))
+                .map_or("local", |name| name.as_str());
+            let alloca = environment.builder.build_alloca(ty, name);
             environment.builder.build_store(alloca, ty.const_zero());
             locals.push((alloca, ty));
         }
     }
 
+    if let Some(symbol) = environment.args.stack_limit.clone() {
+        stack_limit::gen_stack_check(environment, &symbol).context("error gen stack check")?;
+    }
+    if environment.args.epoch_interruption {
+        epoch::gen_epoch_check(environment).context("error gen epoch check")?;
+    }
+    if environment.args.trace {
+        trace::gen_enter(environment);
+    }
+    if environment.args.coverage.is_some() {
+        coverage::gen_counter(environment, f.range().start - environment.code_offset_delta);
+    }
+
     // parse instructions
+    let label_names = environment
+        .names
+        .labels
+        .get(&environment.current_function_idx)
+        .cloned()
+        .unwrap_or_default();
+    let mut num_label = 0;
     let mut op_reader = f.get_operators_reader()?.get_binary_reader();
     let mut num_op = 0;
+    let mut fuel_meter = environment
+        .args
+        .fuel
+        .then(|| FuelMeter::new(&environment.args.fuel_costs));
     while !op_reader.eof() {
+        if let Some(debug_info) = &environment.debug_info {
+            debug_info.set_location(
+                &environment.builder,
+                op_reader.original_position() - environment.code_offset_delta,
+            );
+        }
         let op = op_reader.read_operator()?;
 
         log::trace!("CodeSection: op[{num_op}] = {op:?}");
         num_op += 1;
 
+        if let Some(fuel_meter) = &mut fuel_meter {
+            fuel_meter.charge(environment, &op)?;
+        }
+        let num_frames = environment.control_frames.len();
         parse_instruction(environment, &op, &current_fn, &locals)?;
+
+        // Name the branch destination after the label
+        if let Operator::Block { .. }
+        | Operator::Loop { .. }
+        | Operator::If { .. }
+        | Operator::Try { .. } = op
+        {
+            if let (Some(name), Some(frame)) = (
+                label_names.get(&num_label),
+                environment.control_frames.get(num_frames),
+            ) {
+                frame.br_dest().set_name(name);
+            }
+            num_label += 1;
+        }
+
+        if environment.args.coverage == Some(Coverage::Block)
+            && environment.unreachable_depth == 0
+            && !environment.control_frames.is_empty()
+            && coverage::starts_block(&op)
+        {
+            coverage::gen_counter(
+                environment,
+                op_reader.original_position() - environment.code_offset_delta,
+            );
+        }
+    }
+
+    if let Some(debug_info) = &mut environment.debug_info {
+        debug_info.leave_function(&environment.builder);
     }
     Ok(())
 }
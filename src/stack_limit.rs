@@ -0,0 +1,47 @@
+//! `stack_limit` bounds the native stack used by guest recursion.
+//!
+//! Wasm calls compile to native calls, so deep recursion in the guest would overrun the host stack.
+//! With `--stack-limit`, each function compares the stack pointer with a host-defined limit after
+//! its prologue, and traps with `TrapKind::StackExhausted` if the stack pointer is below the limit.
+//!
+//! ```c
+//! uintptr_t wasker_stack_limit; // lowest usable address of the stack
+//! ```
+//!
+//! The symbol can be renamed with `--stack-limit=SYMBOL`, e.g. to a per-CPU variable of a kernel.
+//! The limit should leave room for imports and `wasker_trap`, which run on the same stack unchecked.
+
+use anyhow::Result;
+use inkwell::{module::Linkage, IntPredicate};
+
+use crate::environment::Environment;
+use crate::insts::trap::{self, TrapKind};
+
+/// Trap if the stack pointer is below the limit `symbol`, then continue in a new block.
+pub(crate) fn gen_stack_check(environment: &mut Environment<'_, '_>, symbol: &str) -> Result<()> {
+    let i64_type = environment.inkwell_types.i64_type;
+    let sp = environment
+        .builder
+        .build_call(environment.inkwell_insts.stacksave, &[], "")
+        .try_as_basic_value()
+        .left()
+        .expect("fail build_call stacksave")
+        .into_pointer_value();
+    let sp = environment.builder.build_ptr_to_int(sp, i64_type, "sp");
+
+    let limit = environment.module.get_global(symbol).unwrap_or_else(|| {
+        let global = environment.module.add_global(i64_type, None, symbol);
+        global.set_linkage(Linkage::External);
+        global
+    });
+    let limit = environment
+        .builder
+        .build_load(i64_type, limit.as_pointer_value(), "stack_limit")
+        .into_int_value();
+
+    let exhausted =
+        environment
+            .builder
+            .build_int_compare(IntPredicate::ULT, sp, limit, "stack_exhausted");
+    trap::gen_trap_if(environment, exhausted, TrapKind::StackExhausted)
+}
@@ -0,0 +1,84 @@
+//! `target_features` checks the proposals listed in the `target_features` custom section.
+//!
+//! The section is read before generating any IR, so that a module using an unsupported proposal
+//! is rejected up front instead of failing in the middle of codegen.
+
+use anyhow::{bail, Result};
+use wasmparser::BinaryReader;
+
+use crate::compiler::Args;
+
+// Prefixes of each entry
+const FEATURE_USED: u8 = b'+';
+const FEATURE_REQUIRED: u8 = b'=';
+const FEATURE_DISALLOWED: u8 = b'-';
+
+enum Support {
+    Full,
+    // Supported except for the described part
+    Partial(&'static str),
+    None,
+}
+
+fn support(feature: &str) -> Option<Support> {
+    let support = match feature {
+        "mutable-globals"
+        | "sign-ext"
+        | "nontrapping-fptoint"
+        | "exception-handling"
+        | "tail-call"
+        | "extended-const" => Support::Full,
+        "bulk-memory" => Support::Partial("passive data segments are not supported"),
+        "multivalue" => Support::Partial("functions with multiple results are not supported"),
+        "reference-types" => Support::Partial("only a single funcref table is supported"),
+        "simd128"
+        | "relaxed-simd"
+        | "atomics"
+        | "shared-mem"
+        | "multimemory"
+        | "memory64"
+        | "gc"
+        | "function-references" => Support::None,
+        _other => return None,
+    };
+    Some(support)
+}
+
+/// Parse `target_features` and check each used feature against wasker and the compile options.
+pub fn check_target_features(data: &[u8], args: &Args) -> Result<()> {
+    let mut reader = BinaryReader::new(data);
+    let count = reader.read_var_u32()?;
+    for _ in 0..count {
+        let prefix = reader.read_u8()?;
+        let feature = reader.read_string()?;
+        log::trace!("- target feature {}{feature}", prefix as char);
+        match prefix {
+            FEATURE_USED | FEATURE_REQUIRED => {}
+            FEATURE_DISALLOWED => continue,
+            _other => bail!("target_features: invalid prefix {prefix:#x} of `{feature}`"),
+        }
+
+        if args.disabled_features.iter().any(|f| f == feature) {
+            bail!("module uses `{feature}`, which is disabled by --disable-feature");
+        }
+        if args.enabled_features.iter().any(|f| f == feature) {
+            continue;
+        }
+        match support(feature) {
+            Some(Support::Full) => {}
+            Some(Support::Partial(limitation)) => {
+                log::warn!("module uses `{feature}`: {limitation}");
+            }
+            Some(Support::None) => {
+                bail!(
+                    "module uses `{feature}`, which is not supported. \
+                     Pass --enable-feature={feature} to compile it anyway"
+                );
+            }
+            None => {
+                log::warn!("module uses unknown feature `{feature}`");
+            }
+        }
+    }
+    Ok(())
+}
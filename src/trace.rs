@@ -0,0 +1,63 @@
+//! `trace` calls host hooks on function entry and exit, for profilers and tracers.
+//!
+//! With `--trace`, every function compiled from the code section calls the unresolved hooks below
+//! with its Wasm function index, and every direct call to an import is surrounded by the import hooks
+//! with the Wasm function index of the import.
+//!
+//! ```c
+//! void wasker_trace_enter(uint32_t func_idx);
+//! void wasker_trace_exit(uint32_t func_idx);
+//! void wasker_trace_import(uint32_t import_idx);      // before calling the import
+//! void wasker_trace_import_exit(uint32_t import_idx); // after the import returned
+//! ```
+//!
+//! The exit hook is called before a tail call, as the callee replaces the current function.
+//! Tail calls to imports are compiled as a call followed by a return, so that the import hooks pair up.
+//! Hooks aren't called when the guest traps.
+
+use inkwell::module::Linkage;
+
+use crate::environment::Environment;
+
+/// Call `wasker_trace_enter` with the current function.
+pub(crate) fn gen_enter(environment: &mut Environment<'_, '_>) {
+    let func_idx = environment.current_function_idx;
+    gen_hook(environment, "wasker_trace_enter", func_idx);
+}
+
+/// Call `wasker_trace_exit` with the current function.
+pub(crate) fn gen_exit(environment: &mut Environment<'_, '_>) {
+    let func_idx = environment.current_function_idx;
+    gen_hook(environment, "wasker_trace_exit", func_idx);
+}
+
+/// Call `wasker_trace_import` before calling the import `import_idx`.
+pub(crate) fn gen_import(environment: &mut Environment<'_, '_>, import_idx: u32) {
+    gen_hook(environment, "wasker_trace_import", import_idx);
+}
+
+/// Call `wasker_trace_import_exit` after the import `import_idx` returned.
+pub(crate) fn gen_import_exit(environment: &mut Environment<'_, '_>, import_idx: u32) {
+    gen_hook(environment, "wasker_trace_import_exit", import_idx);
+}
+
+fn gen_hook(environment: &mut Environment<'_, '_>, name: &str, idx: u32) {
+    let hook = environment.module.get_function(name).unwrap_or_else(|| {
+        let fn_type = environment
+            .inkwell_types
+            .void_type
+            .fn_type(&[environment.inkwell_types.i32_type.into()], false);
+        environment
+            .module
+            .add_function(name, fn_type, Some(Linkage::External))
+    });
+    environment.builder.build_call(
+        hook,
+        &[environment
+            .inkwell_types
+            .i32_type
+            .const_int(idx as u64, false)
+            .into()],
+        "",
+    );
+}
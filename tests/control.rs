@@ -6,6 +6,7 @@ fn example() {
     let args = compiler::Args {
         input_file: wat.into(),
         output_file: "/tmp/wasm.o".into(),
+        ..Default::default()
     };
     compiler::compile_wasm_from_file(&args).expect("fail compile");
 }
@@ -16,6 +17,7 @@ fn block() {
     let args = compiler::Args {
         input_file: wat.into(),
         output_file: "/tmp/wasm.o".into(),
+        ..Default::default()
     };
     compiler::compile_wasm_from_file(&args).expect("fail compile");
 }
@@ -26,6 +28,7 @@ fn ret() {
     let args = compiler::Args {
         input_file: wat.into(),
         output_file: "/tmp/wasm.o".into(),
+        ..Default::default()
     };
     compiler::compile_wasm_from_file(&args).expect("fail compile");
 }
@@ -36,6 +39,7 @@ fn call() {
     let args = compiler::Args {
         input_file: wat.into(),
         output_file: "/tmp/wasm.o".into(),
+        ..Default::default()
     };
     compiler::compile_wasm_from_file(&args).expect("fail compile");
 }
@@ -46,6 +50,7 @@ fn call_indirect() {
     let args = compiler::Args {
         input_file: wat.into(),
         output_file: "/tmp/wasm.o".into(),
+        ..Default::default()
     };
     compiler::compile_wasm_from_file(&args).expect("fail compile");
 }
@@ -56,6 +61,7 @@ fn select() {
     let args = compiler::Args {
         input_file: wat.into(),
         output_file: "/tmp/wasm.o".into(),
+        ..Default::default()
     };
     compiler::compile_wasm_from_file(&args).expect("fail compile");
 }
@@ -66,6 +72,7 @@ fn br() {
     let args = compiler::Args {
         input_file: wat.into(),
         output_file: "/tmp/wasm.o".into(),
+        ..Default::default()
     };
     compiler::compile_wasm_from_file(&args).expect("fail compile");
 }
@@ -76,6 +83,7 @@ fn br_table() {
     let args = compiler::Args {
         input_file: wat.into(),
         output_file: "/tmp/wasm.o".into(),
+        ..Default::default()
     };
     compiler::compile_wasm_from_file(&args).expect("fail compile");
 }
@@ -86,6 +94,7 @@ fn br_if() {
     let args = compiler::Args {
         input_file: wat.into(),
         output_file: "/tmp/wasm.o".into(),
+        ..Default::default()
     };
     compiler::compile_wasm_from_file(&args).expect("fail compile");
 }
@@ -96,6 +105,7 @@ fn iff() {
     let args = compiler::Args {
         input_file: wat.into(),
         output_file: "/tmp/wasm.o".into(),
+        ..Default::default()
     };
     compiler::compile_wasm_from_file(&args).expect("fail compile");
 }
@@ -106,6 +116,7 @@ fn loopp() {
     let args = compiler::Args {
         input_file: wat.into(),
         output_file: "/tmp/wasm.o".into(),
+        ..Default::default()
     };
     compiler::compile_wasm_from_file(&args).expect("fail compile");
 }
@@ -116,6 +127,7 @@ fn switch() {
     let args = compiler::Args {
         input_file: wat.into(),
         output_file: "/tmp/wasm.o".into(),
+        ..Default::default()
     };
     compiler::compile_wasm_from_file(&args).expect("fail compile");
 }
@@ -126,6 +138,228 @@ fn bulk() {
     let args = compiler::Args {
         input_file: wat.into(),
         output_file: "/tmp/wasm.o".into(),
+        ..Default::default()
     };
     compiler::compile_wasm_from_file(&args).expect("fail compile");
 }
+
+#[test]
+fn return_call() {
+    let wat = "./tests/wat/return_call.wat";
+    let args = compiler::Args {
+        input_file: wat.into(),
+        output_file: "/tmp/wasm.o".into(),
+        ..Default::default()
+    };
+    compiler::compile_wasm_from_file(&args).expect("fail compile");
+}
+
+#[test]
+fn exception() {
+    let wat = "./tests/wat/exception.wat";
+    let args = compiler::Args {
+        input_file: wat.into(),
+        output_file: "/tmp/wasm.o".into(),
+        ..Default::default()
+    };
+    compiler::compile_wasm_from_file(&args).expect("fail compile");
+}
+
+#[test]
+fn debug_info() {
+    let wat = "./tests/wat/call.wat";
+    let obj = "/tmp/wasm_debug_info.o";
+    let args = compiler::Args {
+        input_file: wat.into(),
+        output_file: obj.into(),
+        debug_info: true,
+        ..Default::default()
+    };
+    compiler::compile_wasm_from_file(&args).expect("fail compile");
+    let elf = std::fs::read(obj).expect("fail read object");
+    assert!(elf.windows(11).any(|w| w == b".debug_info"));
+    assert!(elf.windows(11).any(|w| w == b".debug_line"));
+}
+
+#[test]
+fn parallel_deterministic() {
+    let wat = "./tests/wat/call.wat";
+    let compile = |obj: &str| {
+        let args = compiler::Args {
+            input_file: wat.into(),
+            output_file: obj.into(),
+            jobs: 4,
+            ..Default::default()
+        };
+        compiler::compile_wasm_from_file(&args).expect("fail compile");
+        std::fs::read(obj).expect("fail read object")
+    };
+    assert_eq!(
+        compile("/tmp/wasm_parallel_1.o"),
+        compile("/tmp/wasm_parallel_2.o")
+    );
+}
+
+#[test]
+fn archive_output() {
+    let wat = "./tests/wat/call.wat";
+    let archive = "/tmp/libwasm_archive.a";
+    let args = compiler::Args {
+        input_file: wat.into(),
+        output_file: archive.into(),
+        functions_per_object: Some(2),
+        jobs: 2,
+        emit: compiler::Emit::Archive,
+        ..Default::default()
+    };
+    compiler::compile_wasm_from_file(&args).expect("fail compile");
+    let bytes = std::fs::read(archive).expect("fail read archive");
+    assert!(bytes.starts_with(b"!<arch>\n"));
+    // The symbol index is the first member
+    assert!(bytes[8..].starts_with(b"/ "));
+
+    let members = std::process::Command::new("ar")
+        .arg("t")
+        .arg(archive)
+        .output()
+        .expect("fail run ar");
+    let members = String::from_utf8_lossy(&members.stdout);
+    assert!(members.lines().count() > 1, "{members}");
+    assert!(members
+        .lines()
+        .all(|member| member.starts_with("libwasm_archive.part")));
+    // Partition objects and their IR are removed once archived
+    assert!(!std::path::Path::new("/tmp/libwasm_archive.part0.o").exists());
+    assert!(!std::path::Path::new("/tmp/libwasm_archive.part0.ll").exists());
+}
+
+#[test]
+fn functions_per_object_requires_archive() {
+    let wat = "./tests/wat/call.wat";
+    let args = compiler::Args {
+        input_file: wat.into(),
+        output_file: "/tmp/wasm.o".into(),
+        functions_per_object: Some(2),
+        ..Default::default()
+    };
+    let err = compiler::compile_wasm_from_file(&args).expect_err("should require --emit archive");
+    assert!(format!("{err:#}").contains("--emit archive"));
+}
+
+#[test]
+fn cache() {
+    let wat = "./tests/wat/call.wat";
+    let cache_dir = "/tmp/wasker_test_cache";
+    let obj = "/tmp/wasm_cache.o";
+    let _ = std::fs::remove_dir_all(cache_dir);
+    let args = compiler::Args {
+        input_file: wat.into(),
+        output_file: obj.into(),
+        cache_dir: Some(cache_dir.into()),
+        ..Default::default()
+    };
+
+    // miss
+    compiler::compile_wasm_from_file(&args).expect("fail compile");
+    let compiled = std::fs::read(obj).expect("fail read object");
+    assert_eq!(std::fs::read_dir(cache_dir).unwrap().count(), 1);
+
+    // hit
+    std::fs::remove_file(obj).unwrap();
+    compiler::compile_wasm_from_file(&args).expect("fail compile");
+    assert_eq!(std::fs::read(obj).expect("fail read object"), compiled);
+
+    // different options miss
+    let args = compiler::Args {
+        canonicalize_nans: true,
+        ..args
+    };
+    compiler::compile_wasm_from_file(&args).expect("fail compile");
+    assert_eq!(std::fs::read_dir(cache_dir).unwrap().count(), 2);
+}
+
+#[test]
+fn import_adapter_arity() {
+    let wat = "./tests/wat/import_adapter.wat";
+    let args = compiler::Args {
+        input_file: wat.into(),
+        output_file: "/tmp/wasm.o".into(),
+        import_adapters: vec!["host print ptr".parse().unwrap()],
+        ..Default::default()
+    };
+    let err = compiler::compile_wasm_from_file(&args).expect_err("arity should mismatch");
+    assert!(format!("{err:#}").contains("host.print"));
+}
+
+#[test]
+fn fuel() {
+    let wat = "./tests/wat/loop.wat";
+    let args = compiler::Args {
+        input_file: wat.into(),
+        output_file: "/tmp/wasm_fuel.o".into(),
+        fuel: true,
+        fuel_costs: vec!["memory=5".parse().unwrap(), "Call=0".parse().unwrap()],
+        ..Default::default()
+    };
+    compiler::compile_wasm_from_file(&args).expect("fail compile");
+    let ll = std::fs::read_to_string("/tmp/wasm_fuel.ll").expect("fail read ll");
+    assert!(ll.contains("@wasker_fuel = external global i64"));
+    assert!(ll.contains("declare void @wasker_out_of_fuel()"));
+
+    assert!("stack=1".parse::<wasker::fuel::FuelCost>().is_err());
+    assert!("numeric=-1".parse::<wasker::fuel::FuelCost>().is_err());
+}
+
+#[test]
+fn stack_probe() {
+    let wat = "./tests/wat/call.wat";
+    let compile = |stack_probe, obj: &str| {
+        let args = compiler::Args {
+            input_file: wat.into(),
+            output_file: obj.into(),
+            stack_probe,
+            ..Default::default()
+        };
+        compiler::compile_wasm_from_file(&args).expect("fail compile");
+        std::fs::read_to_string(std::path::Path::new(obj).with_extension("ll"))
+            .expect("fail read ll")
+    };
+    let ll = compile(compiler::StackProbe::Inline, "/tmp/wasm_probe_inline.o");
+    assert!(ll.contains("\"probe-stack\"=\"inline-asm\""), "{ll}");
+    let ll = compile(compiler::StackProbe::None, "/tmp/wasm_probe_none.o");
+    assert!(ll.contains("\"stack-probe-size\"=\"4294967295\""), "{ll}");
+    assert!(!ll.contains("\"probe-stack\""), "{ll}");
+}
+
+#[test]
+fn coverage_partitioned() {
+    let wat = "./tests/wat/call.wat";
+    let object = "/tmp/wasm_coverage.o";
+    let args = compiler::Args {
+        input_file: wat.into(),
+        output_file: object.into(),
+        coverage: Some(wasker::coverage::Coverage::Function),
+        jobs: 2,
+        ..Default::default()
+    };
+    compiler::compile_wasm_from_file(&args).expect("fail compile");
+
+    // Every partition keeps the records of its functions as global symbols
+    let symbols = std::process::Command::new("nm")
+        .arg(object)
+        .output()
+        .expect("fail run nm");
+    let symbols = String::from_utf8_lossy(&symbols.stdout);
+    let records: Vec<&str> = symbols
+        .lines()
+        .filter(|line| line.contains("wasker_coverage."))
+        .collect();
+    assert!(records.iter().all(|line| line.contains(" D ")), "{symbols}");
+    let records = records.len();
+    let wasm = wat::parse_file(wat).expect("fail parse wat");
+    let functions = wasmparser::Parser::new(0)
+        .parse_all(&wasm)
+        .filter(|payload| matches!(payload, Ok(wasmparser::Payload::CodeSectionEntry(_))))
+        .count();
+    assert_eq!(records, functions, "{symbols}");
+}
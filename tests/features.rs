@@ -0,0 +1,40 @@
+use wasker::compiler;
+
+#[test]
+fn unsupported_feature() {
+    let wat = "./tests/wat/target_features.wat";
+    let args = compiler::Args {
+        input_file: wat.into(),
+        output_file: "/tmp/wasm.o".into(),
+        ..Default::default()
+    };
+    let err = compiler::compile_wasm_from_file(&args).expect_err("simd128 should be rejected");
+    assert!(format!("{err:#}").contains("simd128"));
+}
+
+#[test]
+fn enable_feature() {
+    let wat = "./tests/wat/target_features.wat";
+    let args = compiler::Args {
+        input_file: wat.into(),
+        output_file: "/tmp/wasm.o".into(),
+        enabled_features: vec!["simd128".to_string()],
+        ..Default::default()
+    };
+    compiler::compile_wasm_from_file(&args).expect("fail compile");
+}
+
+#[test]
+fn disable_feature() {
+    let wat = "./tests/wat/target_features.wat";
+    let args = compiler::Args {
+        input_file: wat.into(),
+        output_file: "/tmp/wasm.o".into(),
+        enabled_features: vec!["simd128".to_string()],
+        disabled_features: vec!["mutable-globals".to_string()],
+        ..Default::default()
+    };
+    let err =
+        compiler::compile_wasm_from_file(&args).expect_err("mutable-globals should be rejected");
+    assert!(format!("{err:#}").contains("mutable-globals"));
+}
@@ -6,6 +6,7 @@ fn local_get() {
     let args = compiler::Args {
         input_file: wat.into(),
         output_file: "/tmp/wasm.o".into(),
+        ..Default::default()
     };
     compiler::compile_wasm_from_file(&args).expect("fail compile");
 }
@@ -16,6 +17,53 @@ fn global() {
     let args = compiler::Args {
         input_file: wat.into(),
         output_file: "/tmp/wasm.o".into(),
+        ..Default::default()
     };
     compiler::compile_wasm_from_file(&args).expect("fail compile");
 }
+
+#[test]
+fn global_ref_func() {
+    let wat = "./tests/wat/global_ref_func.wat";
+    let obj = "/tmp/wasm_global_ref_func.o";
+    let args = compiler::Args {
+        input_file: wat.into(),
+        output_file: obj.into(),
+        ..Default::default()
+    };
+    compiler::compile_wasm_from_file(&args).expect("fail compile");
+    let ll = std::fs::read_to_string("/tmp/wasm_global_ref_func.ll").expect("fail read ll");
+    assert!(
+        ll.lines()
+            .any(|l| l.starts_with("@global_0_ref =") && l.contains("ptr @\"0_f\"")),
+        "global_0_ref isn't initialized with $f in {ll}"
+    );
+}
+
+#[test]
+fn names() {
+    let wat = "./tests/wat/names.wat";
+    let obj = "/tmp/wasm_names.o";
+    let args = compiler::Args {
+        input_file: wat.into(),
+        output_file: obj.into(),
+        ..Default::default()
+    };
+    compiler::compile_wasm_from_file(&args).expect("fail compile");
+    let ll = std::fs::read_to_string("/tmp/wasm_names.ll").expect("fail read ll");
+    for name in [
+        "@global_1_counter",
+        "@global_2_table",
+        "@global_3_mem_size",
+        "@global_4 =",
+        "@global_mem_initializer_0_greeting",
+        "@global_table =",
+        "@global_mem_size =",
+    ] {
+        assert!(ll.contains(name), "{name} not found in {ll}");
+    }
+    // Names of the module don't rename globals of the runtime
+    for name in ["@global_table.", "@global_mem_size."] {
+        assert!(!ll.contains(name), "{name} found in {ll}");
+    }
+}
@@ -1,6 +1,8 @@
 mod control;
+mod features;
 mod local;
 mod memory;
 mod numeric;
 mod rust;
 mod spec;
+mod wast_spec;
@@ -1,4 +1,4 @@
-use wasker::compiler;
+use wasker::{compiler, section};
 
 #[test]
 fn memory_size() {
@@ -6,6 +6,7 @@ fn memory_size() {
     let args = compiler::Args {
         input_file: wat.into(),
         output_file: "/tmp/wasm.o".into(),
+        ..Default::default()
     };
     compiler::compile_wasm_from_file(&args).expect("fail compile");
 }
@@ -16,6 +17,7 @@ fn memory_copy() {
     let args = compiler::Args {
         input_file: wat.into(),
         output_file: "/tmp/wasm.o".into(),
+        ..Default::default()
     };
     compiler::compile_wasm_from_file(&args).expect("fail compile");
 }
@@ -26,6 +28,7 @@ fn memory_fill() {
     let args = compiler::Args {
         input_file: wat.into(),
         output_file: "/tmp/wasm.o".into(),
+        ..Default::default()
     };
     compiler::compile_wasm_from_file(&args).expect("fail compile");
 }
@@ -36,6 +39,7 @@ fn endianness() {
     let args = compiler::Args {
         input_file: wat.into(),
         output_file: "/tmp/wasm.o".into(),
+        ..Default::default()
     };
     compiler::compile_wasm_from_file(&args).expect("fail compile");
 }
@@ -46,6 +50,7 @@ fn address32() {
     let args = compiler::Args {
         input_file: wat.into(),
         output_file: "/tmp/wasm.o".into(),
+        ..Default::default()
     };
     compiler::compile_wasm_from_file(&args).expect("fail compile");
 }
@@ -56,6 +61,7 @@ fn address64() {
     let args = compiler::Args {
         input_file: wat.into(),
         output_file: "/tmp/wasm.o".into(),
+        ..Default::default()
     };
     compiler::compile_wasm_from_file(&args).expect("fail compile");
 }
@@ -66,6 +72,71 @@ fn align() {
     let args = compiler::Args {
         input_file: wat.into(),
         output_file: "/tmp/wasm.o".into(),
+        ..Default::default()
     };
     compiler::compile_wasm_from_file(&args).expect("fail compile");
 }
+
+#[test]
+fn side_module() {
+    let wat = "./tests/wat/side_module.wat";
+    let args = compiler::Args {
+        input_file: wat.into(),
+        output_file: "/tmp/wasm.o".into(),
+        pic: true,
+        ..Default::default()
+    };
+    compiler::compile_wasm_from_file(&args).expect("fail compile");
+
+    let wasm = wat::parse_file(wat).expect("fail parse wat");
+    let dylink = wasmparser::Parser::new(0)
+        .parse_all(&wasm)
+        .find_map(|payload| match payload.expect("fail parse wasm") {
+            wasmparser::Payload::CustomSection(customs) if customs.name() == "dylink.0" => {
+                Some(section::read_dylink_section(&customs).expect("fail read dylink.0"))
+            }
+            _ => None,
+        })
+        .expect("dylink.0 not found");
+    assert_eq!(dylink.memory_size, 16);
+    assert_eq!(dylink.memory_alignment, 2);
+    assert_eq!(dylink.table_size, 2);
+    assert_eq!(dylink.table_alignment, 0);
+    assert_eq!(dylink.needed, ["libc.so"]);
+}
+
+#[test]
+fn custom_section() {
+    let wat = "./tests/wat/custom_section.wat";
+    let obj = "/tmp/wasm_custom_section.o";
+    let args = compiler::Args {
+        input_file: wat.into(),
+        output_file: obj.into(),
+        custom_sections: vec!["app.meta".to_string()],
+        ..Default::default()
+    };
+    compiler::compile_wasm_from_file(&args).expect("fail compile");
+    let elf = std::fs::read(obj).expect("fail read object");
+    let contains = |needle: &[u8]| elf.windows(needle.len()).any(|w| w == needle);
+    assert!(contains(b".wasm.custom.app.meta"));
+    assert!(contains(b"wasker_custom_app_meta_start"));
+    assert!(contains(b"wasker_custom_app_meta_end"));
+    assert!(contains(b"name=hello;version=1"));
+    assert!(!contains(b"not copied"));
+}
+
+#[test]
+fn custom_section_collision() {
+    // `app.meta` and `app-meta` are both copied as `wasker_custom_app_meta`
+    let args = compiler::Args {
+        input_file: "./tests/wat/custom_section_collision.wat".into(),
+        output_file: "/tmp/wasm_custom_section_collision.o".into(),
+        custom_sections: vec!["app.meta".to_string(), "app-meta".to_string()],
+        ..Default::default()
+    };
+    let err = compiler::compile_wasm_from_file(&args).expect_err("should reject the collision");
+    assert!(
+        format!("{err:#}").contains("wasker_custom_app_meta_start"),
+        "{err:#}"
+    );
+}
@@ -6,6 +6,7 @@ fn i64() {
     let args = compiler::Args {
         input_file: wat.into(),
         output_file: "/tmp/wasm.o".into(),
+        ..Default::default()
     };
     compiler::compile_wasm_from_file(&args).expect("fail compile");
 }
@@ -16,6 +17,7 @@ fn convert() {
     let args = compiler::Args {
         input_file: wat.into(),
         output_file: "/tmp/wasm.o".into(),
+        ..Default::default()
     };
     compiler::compile_wasm_from_file(&args).expect("fail compile");
 }
@@ -26,6 +28,7 @@ fn f64() {
     let args = compiler::Args {
         input_file: wat.into(),
         output_file: "/tmp/wasm.o".into(),
+        ..Default::default()
     };
     compiler::compile_wasm_from_file(&args).expect("fail compile");
 }
@@ -36,6 +39,7 @@ fn f64_cmp() {
     let args = compiler::Args {
         input_file: wat.into(),
         output_file: "/tmp/wasm.o".into(),
+        ..Default::default()
     };
     compiler::compile_wasm_from_file(&args).expect("fail compile");
 }
@@ -46,6 +50,41 @@ fn f64_bitwise() {
     let args = compiler::Args {
         input_file: wat.into(),
         output_file: "/tmp/wasm.o".into(),
+        ..Default::default()
+    };
+    compiler::compile_wasm_from_file(&args).expect("fail compile");
+}
+
+#[test]
+fn trunc_sat() {
+    let wat = "./tests/wat/trunc_sat.wat";
+    let args = compiler::Args {
+        input_file: wat.into(),
+        output_file: "/tmp/wasm.o".into(),
+        ..Default::default()
+    };
+    compiler::compile_wasm_from_file(&args).expect("fail compile");
+}
+
+#[test]
+fn float_minmax() {
+    let wat = "./tests/wat/float_minmax.wat";
+    let args = compiler::Args {
+        input_file: wat.into(),
+        output_file: "/tmp/wasm.o".into(),
+        ..Default::default()
+    };
+    compiler::compile_wasm_from_file(&args).expect("fail compile");
+}
+
+#[test]
+fn deterministic() {
+    let wat = "./tests/wat/float_nan.wat";
+    let args = compiler::Args {
+        input_file: wat.into(),
+        output_file: "/tmp/wasm.o".into(),
+        deterministic: true,
+        ..Default::default()
     };
     compiler::compile_wasm_from_file(&args).expect("fail compile");
 }
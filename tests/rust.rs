@@ -6,6 +6,7 @@ fn rust() {
     let args = compiler::Args {
         input_file: wat.into(),
         output_file: "/tmp/wasm.o".into(),
+        ..Default::default()
     };
     compiler::compile_wasm_from_file(&args).expect("fail compile");
 }
@@ -1,5 +1,6 @@
 use std::process::Command;
-use wasker::compiler;
+use wasker::insts::trap::TrapKind;
+use wasker::{compiler, link};
 
 fn ensure_log_dir(log_dir: &str) {
     if !std::path::Path::new(log_dir).exists() {
@@ -7,17 +8,46 @@ fn ensure_log_dir(log_dir: &str) {
     }
 }
 
-fn compile_for_executable(output_path: &str, wasm_path: &str, wasi_wrapper_path: &str) {
-    let compile_status = Command::new("gcc")
-        .args(["-o", output_path, wasm_path, wasi_wrapper_path, "-no-pie"])
-        .status()
-        .expect("Failed to compile with GCC");
+fn compile_for_executable(output_path: &str, wasm_path: &str, wasi_wrapper_path: &str, pie: bool) {
+    let mut gcc = Command::new("gcc");
+    gcc.args(["-o", output_path, wasm_path, wasi_wrapper_path]);
+    if !pie {
+        gcc.arg("-no-pie");
+    }
+    let compile_status = gcc.status().expect("Failed to compile with GCC");
     if !compile_status.success() {
         panic!("GCC compilation failed");
     }
 }
 
 fn run_test(testcase: &str) {
+    run_test_with_options(testcase, compiler::Args::default());
+}
+
+fn run_test_with_options(testcase: &str, options: compiler::Args) {
+    let executable_path = build_test_executable(testcase, options);
+    run_executable(&executable_path);
+}
+
+// Run a test which ends with a trap of `kind`, reported as Pass by the WASI wrapper
+fn run_trap_test(testcase: &str, options: compiler::Args, kind: TrapKind) {
+    let executable_path = build_test_executable(testcase, options);
+    let mut command = Command::new(&executable_path);
+    command.env("WASKER_TEST_EXPECTED_TRAP", (kind as u32).to_string());
+    check_output(command);
+}
+
+// The debug print of the tests takes a string in the linear memory
+fn with_print_adapter(options: compiler::Args) -> compiler::Args {
+    let mut import_adapters = vec!["myenv print ptr len".parse().unwrap()];
+    import_adapters.extend(options.import_adapters);
+    compiler::Args {
+        import_adapters,
+        ..options
+    }
+}
+
+fn build_test_executable(testcase: &str, options: compiler::Args) -> String {
     let project_root = env!("CARGO_MANIFEST_DIR");
     let log_dir = format!("{project_root}/target/test_logs");
     ensure_log_dir(&log_dir);
@@ -27,19 +57,54 @@ fn run_test(testcase: &str) {
     let executable_path = format!("{log_dir}/test_{testcase}.out");
     let wasi_wrapper_path = format!("{project_root}/tests/wasi-wrapper-for-test.c");
 
+    let pie = options.pic || options.reloc_model == compiler::RelocModel::Pic;
     let args = compiler::Args {
         input_file: wat_path.into(),
         output_file: wasker_output_path.clone().into(),
+        ..with_print_adapter(options)
     };
 
     // Compile Wasm to ELF file
     compiler::compile_wasm_from_file(&args).expect("fail compile");
 
     // Compile ELF with WASI wrapper
-    compile_for_executable(&executable_path, &wasker_output_path, &wasi_wrapper_path);
+    compile_for_executable(
+        &executable_path,
+        &wasker_output_path,
+        &wasi_wrapper_path,
+        pie,
+    );
+    executable_path
+}
+
+fn run_build_test(testcase: &str, options: link::BuildArgs) {
+    let project_root = env!("CARGO_MANIFEST_DIR");
+    let log_dir = format!("{project_root}/target/test_logs");
+    ensure_log_dir(&log_dir);
+
+    let args = link::BuildArgs {
+        compile: compiler::Args {
+            input_file: format!("{project_root}/tests/wat/{testcase}.wat").into(),
+            output_file: format!("{log_dir}/build_{testcase}.out").into(),
+            ..with_print_adapter(options.compile)
+        },
+        linker: "cc".to_string(),
+        ..options
+    };
 
+    // Compile and link in one step
+    link::build_executable(&args).expect("fail build");
+
+    run_executable(&args.compile.output_file);
+}
+
+fn run_executable(executable_path: impl AsRef<std::path::Path>) {
+    check_output(Command::new(executable_path.as_ref()));
+}
+
+fn check_output(mut command: Command) {
     // Run the executable and check output
-    let output = Command::new(&executable_path)
+    let output = command
         .output()
         .expect("Failed to execute the compiled program");
     let stdout = String::from_utf8_lossy(&output.stdout);
@@ -89,3 +154,266 @@ fn spec_br_table() {
 fn spec_block() {
     run_test("block");
 }
+
+#[test]
+fn spec_return_call() {
+    run_test("return_call");
+}
+
+#[test]
+fn spec_exception() {
+    run_test("exception");
+}
+
+#[test]
+fn spec_exception_uncaught() {
+    run_trap_test(
+        "exception_uncaught",
+        compiler::Args::default(),
+        TrapKind::UncaughtException,
+    );
+}
+
+#[test]
+fn spec_trunc_sat() {
+    run_test("trunc_sat");
+}
+
+#[test]
+fn spec_float_minmax() {
+    run_test("float_minmax");
+}
+
+#[test]
+fn spec_float_nan() {
+    run_test_with_options(
+        "float_nan",
+        compiler::Args {
+            canonicalize_nans: true,
+            ..Default::default()
+        },
+    );
+}
+
+#[test]
+fn spec_pic() {
+    run_test_with_options(
+        "call_indirect",
+        compiler::Args {
+            pic: true,
+            ..Default::default()
+        },
+    );
+}
+
+#[test]
+fn spec_codegen_options() {
+    run_test_with_options(
+        "call",
+        compiler::Args {
+            code_model: compiler::CodeModel::Large,
+            frame_pointer: compiler::FramePointer::All,
+            stack_probe: compiler::StackProbe::Inline,
+            ..Default::default()
+        },
+    );
+}
+
+#[test]
+fn spec_debug_info() {
+    run_test_with_options(
+        "call_indirect",
+        compiler::Args {
+            debug_info: true,
+            ..Default::default()
+        },
+    );
+}
+
+#[test]
+fn spec_parallel() {
+    run_test_with_options(
+        "call_indirect",
+        compiler::Args {
+            jobs: 4,
+            ..Default::default()
+        },
+    );
+}
+
+#[test]
+fn spec_import_adapter() {
+    run_test_with_options(
+        "import_adapter",
+        compiler::Args {
+            import_adapters: vec!["host print ptr len".parse().unwrap()],
+            ..Default::default()
+        },
+    );
+}
+
+#[test]
+fn spec_data_out_of_bounds() {
+    run_trap_test(
+        "data_out_of_bounds",
+        Default::default(),
+        TrapKind::MemoryOutOfBounds,
+    );
+}
+
+#[test]
+fn spec_import_adapter_overflow() {
+    run_trap_test(
+        "import_adapter_overflow",
+        compiler::Args {
+            import_adapters: vec!["host print64 ptr len".parse().unwrap()],
+            ..Default::default()
+        },
+        TrapKind::MemoryOutOfBounds,
+    );
+}
+
+#[test]
+fn spec_import_context() {
+    run_test_with_options(
+        "import_context",
+        compiler::Args {
+            import_context: true,
+            ..Default::default()
+        },
+    );
+}
+
+#[test]
+fn spec_fuel() {
+    run_test_with_options(
+        "fuel",
+        compiler::Args {
+            fuel: true,
+            ..Default::default()
+        },
+    );
+}
+
+#[test]
+fn spec_epoch() {
+    run_test_with_options(
+        "epoch",
+        compiler::Args {
+            epoch_interruption: true,
+            ..Default::default()
+        },
+    );
+}
+
+#[test]
+fn spec_stack_limit() {
+    run_trap_test(
+        "stack_limit",
+        compiler::Args {
+            stack_limit: Some("wasker_stack_limit".to_string()),
+            ..Default::default()
+        },
+        TrapKind::StackExhausted,
+    );
+}
+
+#[test]
+fn spec_trace() {
+    run_test_with_options(
+        "trace",
+        compiler::Args {
+            trace: true,
+            ..Default::default()
+        },
+    );
+}
+
+#[test]
+fn spec_coverage() {
+    run_test_with_options(
+        "coverage",
+        compiler::Args {
+            coverage: Some(wasker::coverage::Coverage::Block),
+            ..Default::default()
+        },
+    );
+}
+
+#[test]
+fn build_with_wrapper() {
+    run_build_test(
+        "call",
+        link::BuildArgs {
+            host: Some(
+                concat!(env!("CARGO_MANIFEST_DIR"), "/tests/wasi-wrapper-for-test.c").into(),
+            ),
+            ..Default::default()
+        },
+    );
+}
+
+#[test]
+fn build_static() {
+    run_build_test(
+        "call",
+        link::BuildArgs {
+            host: Some(
+                concat!(env!("CARGO_MANIFEST_DIR"), "/tests/wasi-wrapper-for-test.c").into(),
+            ),
+            static_link: true,
+            ..Default::default()
+        },
+    );
+}
+
+#[test]
+fn build_archive() {
+    run_build_test(
+        "call_indirect",
+        link::BuildArgs {
+            compile: compiler::Args {
+                functions_per_object: Some(2),
+                jobs: 2,
+                emit: compiler::Emit::Archive,
+                ..Default::default()
+            },
+            host: Some(
+                concat!(env!("CARGO_MANIFEST_DIR"), "/tests/wasi-wrapper-for-test.c").into(),
+            ),
+            ..Default::default()
+        },
+    );
+}
+
+#[test]
+fn build_with_wasker_wasi() {
+    let project_root = env!("CARGO_MANIFEST_DIR");
+    let log_dir = format!("{project_root}/target/test_logs");
+    ensure_log_dir(&log_dir);
+
+    // A target dir of its own, so that the build doesn't wait for or invalidate this test run
+    let target_dir = format!("{project_root}/target/wasker-wasi");
+    let status = Command::new(env!("CARGO"))
+        .args(["build", "-p", "wasker-wasi", "--target-dir", &target_dir])
+        .current_dir(project_root)
+        .status()
+        .expect("Failed to run cargo");
+    assert!(status.success(), "Failed to build wasker-wasi");
+
+    // Build with the CLI, which finds wasker-wasi through WASKER_WASI_LIB
+    let executable_path = format!("{log_dir}/build_wasi_args.out");
+    let status = Command::new(env!("CARGO_BIN_EXE_wasker"))
+        .env(
+            "WASKER_WASI_LIB",
+            format!("{target_dir}/debug/libwasker_wasi.a"),
+        )
+        .arg("build")
+        .arg(format!("{project_root}/tests/wat/wasi_args.wat"))
+        .args(["-o", &executable_path])
+        .status()
+        .expect("Failed to run wasker build");
+    assert!(status.success(), "wasker build failed");
+
+    run_executable(&executable_path);
+}
@@ -1,3 +1,4 @@
+#include <stdint.h>
 #include <stdio.h>
 #include <stdlib.h>
 
@@ -43,6 +44,196 @@ void print(char *ptr, int len)
   }
 }
 
+void print64(char *ptr, uint64_t len)
+{
+  fwrite(ptr, 1, len, stdout);
+}
+
+//////////////////////////////////////////////
+/// Imports called with --import-context
+//////////////////////////////////////////////
+
+struct wasker_import_context
+{
+  uint8_t *memory_base;
+  uint64_t memory_size;
+};
+
+void print_guest(struct wasker_import_context *ctx, uint32_t offset, uint32_t len)
+{
+  if ((uint64_t)offset + len > ctx->memory_size)
+  {
+    printf("Fail: out of linear memory\n");
+    return;
+  }
+  print((char *)ctx->memory_base + offset, len);
+}
+
+void check_memory_size(struct wasker_import_context *ctx, uint32_t pages)
+{
+  if (ctx->memory_size == (uint64_t)pages * 65536)
+    printf("Pass\n");
+  else
+    printf("Fail: memory size %lu\n", (unsigned long)ctx->memory_size);
+}
+
+//////////////////////////////////////////////
+/// Fuel of code compiled with --fuel
+//////////////////////////////////////////////
+
+int64_t wasker_fuel = 100;
+int32_t fuel_refills = 0;
+
+void wasker_out_of_fuel(void)
+{
+  fuel_refills++;
+  wasker_fuel += 100;
+}
+
+int32_t out_of_fuel_count(void)
+{
+  return fuel_refills;
+}
+
+//////////////////////////////////////////////
+/// Epoch of code compiled with --epoch-interruption
+//////////////////////////////////////////////
+
+volatile uint64_t wasker_epoch = 0;
+uint64_t wasker_epoch_deadline = UINT64_MAX;
+int32_t interrupts = 0;
+
+void wasker_interrupt(void)
+{
+  interrupts++;
+  wasker_epoch_deadline = wasker_epoch + 1;
+}
+
+void expire_epoch(void)
+{
+  wasker_epoch_deadline = wasker_epoch;
+}
+
+int32_t interrupt_count(void)
+{
+  return interrupts;
+}
+
+//////////////////////////////////////////////
+/// Offset of data_out_of_bounds.wat
+//////////////////////////////////////////////
+
+int32_t data_offset = 65534;
+
+//////////////////////////////////////////////
+/// Stack limit of code compiled with --stack-limit
+//////////////////////////////////////////////
+
+uintptr_t wasker_stack_limit = 0;
+
+void limit_stack(uint32_t size)
+{
+  wasker_stack_limit = (uintptr_t)__builtin_frame_address(0) - size;
+}
+
+//////////////////////////////////////////////
+/// Traps
+//////////////////////////////////////////////
+
+// A test expecting a trap sets WASKER_TEST_EXPECTED_TRAP to its kind.
+// Any other trap is reported as a failure.
+void wasker_trap(uint32_t kind)
+{
+  const char *expected = getenv("WASKER_TEST_EXPECTED_TRAP");
+  if (expected != NULL && strtoul(expected, NULL, 10) == kind)
+  {
+    printf("Pass\n");
+    exit(0);
+  }
+  printf("Fail: trap %u\n", kind);
+  fflush(stdout);
+}
+
+//////////////////////////////////////////////
+/// Hooks of code compiled with --trace
+//////////////////////////////////////////////
+
+int32_t traced_depth = 0;
+int32_t traced_last_enter = -1;
+int in_import = 0;
+
+void wasker_trace_enter(uint32_t func_idx)
+{
+  traced_depth++;
+  traced_last_enter = func_idx;
+}
+
+void wasker_trace_exit(uint32_t func_idx)
+{
+  traced_depth--;
+}
+
+void wasker_trace_import(uint32_t import_idx)
+{
+  in_import = 1;
+}
+
+void wasker_trace_import_exit(uint32_t import_idx)
+{
+  in_import = 0;
+}
+
+int32_t trace_depth(void)
+{
+  return in_import ? traced_depth : -1;
+}
+
+int32_t trace_last_enter(void)
+{
+  return traced_last_enter;
+}
+
+//////////////////////////////////////////////
+/// Counters of code compiled with --coverage
+//////////////////////////////////////////////
+
+struct wasker_coverage_record
+{
+  uint64_t count;
+  uint32_t func_idx;
+  uint32_t offset;
+};
+
+// Weak, as the section is missing without --coverage
+extern struct wasker_coverage_record __start_wasker_coverage[] __attribute__((weak));
+extern struct wasker_coverage_record __stop_wasker_coverage[] __attribute__((weak));
+
+int32_t coverage_records(uint32_t func_idx)
+{
+  int32_t records = 0;
+  for (struct wasker_coverage_record *r = __start_wasker_coverage; r < __stop_wasker_coverage; r++)
+  {
+    if (r->func_idx == func_idx)
+    {
+      records++;
+    }
+  }
+  return records;
+}
+
+int32_t coverage_total(uint32_t func_idx)
+{
+  int32_t total = 0;
+  for (struct wasker_coverage_record *r = __start_wasker_coverage; r < __stop_wasker_coverage; r++)
+  {
+    if (r->func_idx == func_idx)
+    {
+      total += r->count;
+    }
+  }
+  return total;
+}
+
 int main()
 {
   // Entrypoint of ELF generated by Wasker
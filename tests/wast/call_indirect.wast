@@ -0,0 +1,38 @@
+;; call_indirect traps on out-of-range, null and signature-mismatch elements
+
+(module
+  (type $i32-i32 (func (param i32) (result i32)))
+  (type $i32-i32-dup (func (param i32) (result i32)))
+  (type $i64-i64 (func (param i64) (result i64)))
+
+  (table 6 funcref)
+  (elem (i32.const 0) $inc $dec)
+  (elem (i32.const 3) $wide)
+
+  (func $inc (type $i32-i32) (i32.add (local.get 0) (i32.const 1)))
+  (func $dec (type $i32-i32-dup) (i32.sub (local.get 0) (i32.const 1)))
+  (func $wide (type $i64-i64) (i64.mul (local.get 0) (i64.const 2)))
+
+  (func (export "call-i32") (param i32 i32) (result i32)
+    (call_indirect (type $i32-i32) (local.get 1) (local.get 0))
+  )
+
+  (func (export "call-i32-dup") (param i32 i32) (result i32)
+    (call_indirect (type $i32-i32-dup) (local.get 1) (local.get 0))
+  )
+
+  (func (export "call-i64") (param i32 i64) (result i64)
+    (call_indirect (type $i64-i64) (local.get 1) (local.get 0))
+  )
+)
+
+(assert_return (invoke "call-i32" (i32.const 0) (i32.const 41)) (i32.const 42))
+(assert_return (invoke "call-i32" (i32.const 1) (i32.const 41)) (i32.const 40))
+(assert_return (invoke "call-i32-dup" (i32.const 0) (i32.const 1)) (i32.const 2))
+(assert_return (invoke "call-i64" (i32.const 3) (i64.const 21)) (i64.const 42))
+(assert_trap (invoke "call-i32" (i32.const 2) (i32.const 0)) "uninitialized element")
+(assert_trap (invoke "call-i32" (i32.const 5) (i32.const 0)) "uninitialized element")
+(assert_trap (invoke "call-i32" (i32.const 3) (i32.const 0)) "indirect call type mismatch")
+(assert_trap (invoke "call-i64" (i32.const 0) (i64.const 0)) "indirect call type mismatch")
+(assert_trap (invoke "call-i32" (i32.const 6) (i32.const 0)) "undefined element")
+(assert_trap (invoke "call-i32" (i32.const -1) (i32.const 0)) "undefined element")
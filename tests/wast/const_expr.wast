@@ -0,0 +1,48 @@
+;; Constant expressions in global initializers and segment offsets
+
+(module
+  (import "spectest" "global_i32" (global $base i32))
+
+  (type $out-i32 (func (result i32)))
+
+  (global $folded i32 (i32.add (i32.const 40) (i32.const 2)))
+  (global $folded-i64 i64 (i64.mul (i64.const 6) (i64.const 7)))
+  (global $folded-f64 f64 (f64.const -0x1.8p+1))
+  (global $from-import i32 (i32.sub (global.get $base) (i32.const 600)))
+  (global $mut (mut i32) (global.get $base))
+
+  (memory 1)
+  (data (offset (i32.add (i32.const 60) (i32.const 4))) "\2a")
+  (data (offset (i32.sub (global.get $base) (i32.const 616))) "\07")
+
+  (table 8 funcref)
+  (elem (offset (i32.mul (i32.const 2) (i32.const 2))) func $f)
+  (elem (offset (i32.sub (global.get $base) (i32.const 661))) func $g)
+
+  (func $f (type $out-i32) (i32.const 100))
+  (func $g (type $out-i32) (i32.const 200))
+
+  (func (export "folded") (result i32) (global.get $folded))
+  (func (export "folded-i64") (result i64) (global.get $folded-i64))
+  (func (export "folded-f64") (result f64) (global.get $folded-f64))
+  (func (export "from-import") (result i32) (global.get $from-import))
+  (func (export "mut") (result i32)
+    (global.set $mut (i32.add (global.get $mut) (i32.const 1)))
+    (global.get $mut)
+  )
+  (func (export "load8") (param i32) (result i32) (i32.load8_u (local.get 0)))
+  (func (export "call") (param i32) (result i32)
+    (call_indirect (type $out-i32) (local.get 0))
+  )
+)
+
+(assert_return (invoke "folded") (i32.const 42))
+(assert_return (invoke "folded-i64") (i64.const 42))
+(assert_return (invoke "folded-f64") (f64.const -3.0))
+(assert_return (invoke "from-import") (i32.const 66))
+(assert_return (invoke "mut") (i32.const 667))
+(assert_return (invoke "load8" (i32.const 64)) (i32.const 42))
+(assert_return (invoke "load8" (i32.const 50)) (i32.const 7))
+(assert_return (invoke "call" (i32.const 4)) (i32.const 100))
+(assert_return (invoke "call" (i32.const 5)) (i32.const 200))
+(assert_trap (invoke "call" (i32.const 3)) "uninitialized element")
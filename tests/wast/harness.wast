@@ -0,0 +1,54 @@
+;; Exercise the directives supported by the wast harness
+
+(module
+  (import "spectest" "print" (func $print))
+
+  (global $counter (mut i32) (i32.const 0))
+
+  (func (export "add") (param i32 i32) (result i32)
+    (i32.add (local.get 0) (local.get 1))
+  )
+
+  (func (export "mul64") (param i64 i64) (result i64)
+    (i64.mul (local.get 0) (local.get 1))
+  )
+
+  (func (export "div_s") (param i32 i32) (result i32)
+    (i32.div_s (local.get 0) (local.get 1))
+  )
+
+  (func (export "fadd") (param f32 f32) (result f32)
+    (f32.add (local.get 0) (local.get 1))
+  )
+
+  (func (export "fneg") (param f64) (result f64)
+    (f64.neg (local.get 0))
+  )
+
+  (func (export "bump")
+    (call $print)
+    (global.set $counter (i32.add (global.get $counter) (i32.const 1)))
+  )
+
+  (func (export "counter") (result i32)
+    (global.get $counter)
+  )
+)
+
+(assert_return (invoke "add" (i32.const 1) (i32.const 2)) (i32.const 3))
+(assert_return (invoke "add" (i32.const 0x7fffffff) (i32.const 1)) (i32.const 0x80000000))
+(assert_return (invoke "mul64" (i64.const -3) (i64.const 0x4000000000000000)) (i64.const 0x4000000000000000))
+(assert_return (invoke "div_s" (i32.const -7) (i32.const 2)) (i32.const -3))
+(assert_trap (invoke "div_s" (i32.const 1) (i32.const 0)) "integer divide by zero")
+(assert_return (invoke "fadd" (f32.const 1.5) (f32.const 0x1p-1)) (f32.const 2.0))
+(assert_return (invoke "fadd" (f32.const nan) (f32.const 1.0)) (f32.const nan:canonical))
+(assert_return (invoke "fneg" (f64.const -0.0)) (f64.const 0.0))
+(assert_return (invoke "fneg" (f64.const nan:0x4)) (f64.const -nan:0x4))
+(invoke "bump")
+(invoke "bump")
+(assert_return (invoke "counter") (i32.const 2))
+
+(assert_malformed
+  (module quote "(func (result i32) (i32.const))")
+  "unexpected token"
+)
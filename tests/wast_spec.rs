@@ -0,0 +1,654 @@
+//! Run `.wast` scripts of the WebAssembly spec testsuite through Wasker.
+//!
+//! Every `module` directive is compiled by Wasker and linked with a generated C driver which
+//! replays the `invoke`, `assert_return` and `assert_trap` directives that follow it.
+//! `assert_invalid` and `assert_malformed` modules are expected to be rejected by Wasker.
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use wasker::compiler;
+use wasmparser::{ExternalKind, Payload, TypeRef, ValType};
+use wast::core::{NanPattern, WastArgCore, WastRetCore};
+use wast::parser::{self, ParseBuffer};
+use wast::{QuoteWat, Wast, WastArg, WastDirective, WastExecute, WastInvoke, WastRet, Wat};
+
+// Functions of the `spectest` module provided by the generated driver
+const SPECTEST_FUNCTIONS: [&str; 7] = [
+    "print",
+    "print_i32",
+    "print_i64",
+    "print_f32",
+    "print_f64",
+    "print_i32_f32",
+    "print_f64_f64",
+];
+
+// Globals of the `spectest` module provided by the generated driver
+const SPECTEST_GLOBALS: [&str; 4] = ["global_i32", "global_i64", "global_f32", "global_f64"];
+
+const DRIVER_PRELUDE: &str = r#"#include <signal.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+extern void wasker_main();
+
+const int LINEAR_MEMORY_BLOCK_SIZE = 64 * 1024;
+const int LINEAR_MEMORY_BLOCK_NUM_MAX = 32;
+int linear_memory_block_num = 0;
+
+long memory_base()
+{
+  return (long)calloc(LINEAR_MEMORY_BLOCK_NUM_MAX, LINEAR_MEMORY_BLOCK_SIZE);
+}
+
+long memory_grow(long num)
+{
+  int old = linear_memory_block_num;
+  if (LINEAR_MEMORY_BLOCK_NUM_MAX < linear_memory_block_num + num)
+    return -1;
+  linear_memory_block_num += num;
+  return old;
+}
+
+void print(void) {}
+void print_i32(int32_t a) {}
+void print_i64(int64_t a) {}
+void print_f32(float a) {}
+void print_f64(double a) {}
+void print_i32_f32(int32_t a, float b) {}
+void print_f64_f64(double a, double b) {}
+
+int32_t global_i32 = 666;
+int64_t global_i64 = 666;
+float global_f32 = 666.6f;
+double global_f64 = 666.6;
+
+static void report(int idx, int ok) { printf(ok ? "Pass %d\n" : "Fail %d\n", idx); }
+
+static uint32_t f32_bits(float f) { uint32_t b; memcpy(&b, &f, 4); return b; }
+static uint64_t f64_bits(double f) { uint64_t b; memcpy(&b, &f, 8); return b; }
+static float f32_from_bits(uint32_t b) { float f; memcpy(&f, &b, 4); return f; }
+static double f64_from_bits(uint64_t b) { double f; memcpy(&f, &b, 8); return f; }
+
+static int f32_canonical_nan(uint32_t b) { return (b & 0x7fffffffu) == 0x7fc00000u; }
+static int f32_arithmetic_nan(uint32_t b) { return (b & 0x7fc00000u) == 0x7fc00000u; }
+static int f64_canonical_nan(uint64_t b) { return (b & 0x7fffffffffffffffull) == 0x7ff8000000000000ull; }
+static int f64_arithmetic_nan(uint64_t b) { return (b & 0x7ff8000000000000ull) == 0x7ff8000000000000ull; }
+
+// Run `f` in a child process and check that it is killed by a trap
+static void expect_trap(int idx, void (*f)(void))
+{
+  fflush(stdout);
+  pid_t pid = fork();
+  if (pid == 0)
+  {
+    alarm(10);
+    f();
+    _exit(0);
+  }
+  int status;
+  waitpid(pid, &status, 0);
+  report(idx, WIFSIGNALED(status) && WTERMSIG(status) != SIGALRM);
+}
+"#;
+
+fn ensure_log_dir(log_dir: &str) {
+    if !std::path::Path::new(log_dir).exists() {
+        std::fs::create_dir_all(log_dir).expect("Failed to create log directory");
+    }
+}
+
+/// Number of passed, failed and skipped directives.
+#[derive(Debug, Default, Clone, Copy)]
+struct Tally {
+    passed: usize,
+    failed: usize,
+    skipped: usize,
+}
+
+impl Tally {
+    fn record(&mut self, ok: bool) {
+        if ok {
+            self.passed += 1;
+        } else {
+            self.failed += 1;
+        }
+    }
+
+    fn add(&mut self, other: Tally) {
+        self.passed += other.passed;
+        self.failed += other.failed;
+        self.skipped += other.skipped;
+    }
+}
+
+/// Result of running a single `.wast` script.
+#[derive(Debug, Default)]
+struct WastReport {
+    /// `invoke`, `assert_return` and `assert_trap`
+    execution: Tally,
+    /// `assert_invalid` and `assert_malformed`
+    validation: Tally,
+    /// Line numbers of failed directives
+    failed_lines: Vec<usize>,
+}
+
+enum Expect<'a> {
+    Nothing,
+    Return(Vec<WastRet<'a>>),
+    Trap,
+}
+
+struct Directive<'a> {
+    line: usize,
+    invoke: WastInvoke<'a>,
+    expect: Expect<'a>,
+}
+
+// Parameters and results of a function type
+type Signature = (Vec<ValType>, Vec<ValType>);
+
+// Exported functions of a module and their signatures
+struct ModuleInfo {
+    types: Vec<Signature>,
+    function_types: Vec<u32>,
+    exports: HashMap<String, u32>,
+    symbols: HashMap<u32, String>,
+    supported_imports: bool,
+}
+
+impl ModuleInfo {
+    fn parse(wasm: &[u8]) -> wasmparser::Result<Self> {
+        let mut info = ModuleInfo {
+            types: Vec::new(),
+            function_types: Vec::new(),
+            exports: HashMap::new(),
+            symbols: HashMap::new(),
+            supported_imports: true,
+        };
+        let mut import_size = 0;
+        for payload in wasmparser::Parser::new(0).parse_all(wasm) {
+            match payload? {
+                Payload::TypeSection(types) => {
+                    for ty in types {
+                        let wasmparser::Type::Func(ty) = ty?;
+                        info.types
+                            .push((ty.params().to_vec(), ty.results().to_vec()));
+                    }
+                }
+                Payload::ImportSection(imports) => {
+                    for import in imports {
+                        let import = import?;
+                        match import.ty {
+                            TypeRef::Func(ty) => {
+                                info.function_types.push(ty);
+                                import_size += 1;
+                                info.supported_imports &= import.module == "spectest"
+                                    && SPECTEST_FUNCTIONS.contains(&import.name);
+                            }
+                            TypeRef::Global(_) => {
+                                info.supported_imports &= import.module == "spectest"
+                                    && SPECTEST_GLOBALS.contains(&import.name);
+                            }
+                            _ => info.supported_imports = false,
+                        }
+                    }
+                }
+                Payload::FunctionSection(functions) => {
+                    for ty in functions {
+                        info.function_types.push(ty?);
+                    }
+                }
+                Payload::ExportSection(exports) => {
+                    for export in exports {
+                        let export = export?;
+                        // `objcopy --redefine-sym` can't rename symbols containing `=`
+                        if export.kind != ExternalKind::Func
+                            || export.index < import_size
+                            || export.name.contains('=')
+                        {
+                            continue;
+                        }
+                        // Wasker names a function after its last export
+                        let symbol = match export.name {
+                            "_start" => "wasker_start",
+                            name => name,
+                        };
+                        info.exports.insert(export.name.to_string(), export.index);
+                        info.symbols.insert(export.index, symbol.to_string());
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(info)
+    }
+
+    fn signature(&self, export: &str) -> Option<(u32, &Signature)> {
+        let index = *self.exports.get(export)?;
+        let ty = self.function_types.get(index as usize)?;
+        Some((index, self.types.get(*ty as usize)?))
+    }
+}
+
+struct ModuleRun<'a> {
+    name: String,
+    id: Option<&'a str>,
+    wasm: Option<Vec<u8>>,
+    directives: Vec<Directive<'a>>,
+}
+
+impl<'a> ModuleRun<'a> {
+    fn new(name: String, mut module: QuoteWat<'a>) -> Self {
+        let id = match &module {
+            QuoteWat::Wat(Wat::Module(m)) => m.id.map(|id| id.name()),
+            _ => None,
+        };
+        ModuleRun {
+            name,
+            id,
+            wasm: module.encode().ok(),
+            directives: Vec::new(),
+        }
+    }
+
+    fn finish(self, log_dir: &str, report: &mut WastReport) {
+        let lines: Vec<usize> = self.directives.iter().map(|d| d.line).collect();
+        let fail_all = |report: &mut WastReport| {
+            report.execution.failed += lines.len();
+            report.failed_lines.extend(&lines);
+        };
+        let Some(wasm) = self.wasm else {
+            fail_all(report);
+            return;
+        };
+        let info = match ModuleInfo::parse(&wasm) {
+            Ok(info) => info,
+            Err(_) => {
+                fail_all(report);
+                return;
+            }
+        };
+        if !info.supported_imports {
+            report.execution.skipped += lines.len();
+            return;
+        }
+
+        // Compile the module with Wasker
+        let object_path = format!("{log_dir}/{}.o", self.name);
+        let args = compiler::Args {
+            output_file: object_path.clone().into(),
+            ..Default::default()
+        };
+        let compiled = std::panic::catch_unwind(|| compiler::compile_wasm(&wasm, &args));
+        if !matches!(compiled, Ok(Ok(()))) {
+            fail_all(report);
+            return;
+        }
+
+        // Give exported functions C identifiers
+        let mut objcopy = Command::new("objcopy");
+        for (index, symbol) in &info.symbols {
+            objcopy.arg(format!("--redefine-sym={symbol}=wast_func_{index}"));
+        }
+        if !objcopy
+            .arg(&object_path)
+            .status()
+            .is_ok_and(|s| s.success())
+        {
+            fail_all(report);
+            return;
+        }
+
+        // Generate the driver
+        let mut declarations = String::new();
+        let mut commands = String::new();
+        let mut main = String::new();
+        let mut declared = Vec::new();
+        let mut expected = Vec::new();
+        for (idx, directive) in self.directives.iter().enumerate() {
+            if directive
+                .invoke
+                .module
+                .is_some_and(|m| Some(m.name()) != self.id)
+            {
+                report.execution.skipped += 1;
+                continue;
+            }
+            let Some((index, (params, results))) = info.signature(directive.invoke.name) else {
+                report.execution.skipped += 1;
+                continue;
+            };
+            let Some(body) = gen_command(idx, index, params, results, directive) else {
+                report.execution.skipped += 1;
+                continue;
+            };
+            if !declared.contains(&index) {
+                declared.push(index);
+                let params: Vec<&str> = params.iter().map(|p| c_type(p).unwrap()).collect();
+                let _ = writeln!(
+                    declarations,
+                    "extern {} wast_func_{index}({});",
+                    results.first().map_or(Some("void"), c_type).unwrap(),
+                    if params.is_empty() {
+                        "void".to_string()
+                    } else {
+                        params.join(", ")
+                    },
+                );
+            }
+            let _ = writeln!(commands, "static void cmd_{idx}(void)\n{{\n{body}}}");
+            let _ = match directive.expect {
+                Expect::Trap => writeln!(main, "  expect_trap({idx}, cmd_{idx});"),
+                _ => writeln!(main, "  cmd_{idx}();"),
+            };
+            expected.push(idx);
+        }
+        let driver = format!(
+            "{DRIVER_PRELUDE}\n{declarations}\n{commands}\nint main()\n{{\n  alarm(60);\n  wasker_main();\n{main}  return 0;\n}}\n"
+        );
+        let driver_path = format!("{log_dir}/{}.c", self.name);
+        let executable_path = format!("{log_dir}/{}.out", self.name);
+        std::fs::write(&driver_path, driver).expect("Failed to write driver");
+
+        let linked = Command::new("gcc")
+            .args([
+                "-o",
+                &executable_path,
+                &object_path,
+                &driver_path,
+                "-no-pie",
+            ])
+            .status()
+            .is_ok_and(|s| s.success());
+        let stdout = if linked {
+            Command::new(&executable_path)
+                .output()
+                .map(|o| String::from_utf8_lossy(&o.stdout).into_owned())
+                .unwrap_or_default()
+        } else {
+            String::new()
+        };
+
+        // Directives not reported by the driver crashed or were never reached
+        let passed: Vec<usize> = stdout
+            .lines()
+            .filter_map(|l| l.strip_prefix("Pass ")?.parse().ok())
+            .collect();
+        for idx in expected {
+            let ok = passed.contains(&idx);
+            report.execution.record(ok);
+            if !ok {
+                report.failed_lines.push(self.directives[idx].line);
+            }
+        }
+    }
+}
+
+fn c_type(ty: &ValType) -> Option<&'static str> {
+    match ty {
+        ValType::I32 => Some("int32_t"),
+        ValType::I64 => Some("int64_t"),
+        ValType::F32 => Some("float"),
+        ValType::F64 => Some("double"),
+        _ => None,
+    }
+}
+
+fn c_arg(arg: &WastArg) -> Option<String> {
+    match arg {
+        WastArg::Core(WastArgCore::I32(v)) => Some(format!("(int32_t)UINT32_C({:#x})", *v as u32)),
+        WastArg::Core(WastArgCore::I64(v)) => Some(format!("(int64_t)UINT64_C({:#x})", *v as u64)),
+        WastArg::Core(WastArgCore::F32(v)) => {
+            Some(format!("f32_from_bits(UINT32_C({:#x}))", v.bits))
+        }
+        WastArg::Core(WastArgCore::F64(v)) => {
+            Some(format!("f64_from_bits(UINT64_C({:#x}))", v.bits))
+        }
+        _ => None,
+    }
+}
+
+fn c_check(ret: &WastRet) -> Option<String> {
+    let check = match ret {
+        WastRet::Core(WastRetCore::I32(v)) => format!("(uint32_t)r == UINT32_C({:#x})", *v as u32),
+        WastRet::Core(WastRetCore::I64(v)) => format!("(uint64_t)r == UINT64_C({:#x})", *v as u64),
+        WastRet::Core(WastRetCore::F32(p)) => match p {
+            NanPattern::CanonicalNan => "f32_canonical_nan(f32_bits(r))".to_string(),
+            NanPattern::ArithmeticNan => "f32_arithmetic_nan(f32_bits(r))".to_string(),
+            NanPattern::Value(v) => format!("f32_bits(r) == UINT32_C({:#x})", v.bits),
+        },
+        WastRet::Core(WastRetCore::F64(p)) => match p {
+            NanPattern::CanonicalNan => "f64_canonical_nan(f64_bits(r))".to_string(),
+            NanPattern::ArithmeticNan => "f64_arithmetic_nan(f64_bits(r))".to_string(),
+            NanPattern::Value(v) => format!("f64_bits(r) == UINT64_C({:#x})", v.bits),
+        },
+        _ => return None,
+    };
+    Some(check)
+}
+
+// Generate the body of a C function which runs a directive
+fn gen_command(
+    idx: usize,
+    index: u32,
+    params: &[ValType],
+    results: &[ValType],
+    directive: &Directive,
+) -> Option<String> {
+    if params.len() != directive.invoke.args.len()
+        || results.len() > 1
+        || params.iter().chain(results).any(|ty| c_type(ty).is_none())
+    {
+        return None;
+    }
+    let args = directive
+        .invoke
+        .args
+        .iter()
+        .map(c_arg)
+        .collect::<Option<Vec<_>>>()?
+        .join(", ");
+    let call = format!("wast_func_{index}({args})");
+    let body = match &directive.expect {
+        Expect::Trap => format!("  {call};\n"),
+        Expect::Nothing => format!("  {call};\n  report({idx}, 1);\n"),
+        Expect::Return(rets) => match (rets.as_slice(), results.first()) {
+            ([], None) => format!("  {call};\n  report({idx}, 1);\n"),
+            ([ret], Some(ty)) => format!(
+                "  {} r = {call};\n  report({idx}, {});\n",
+                c_type(ty)?,
+                c_check(ret)?
+            ),
+            _ => return None,
+        },
+    };
+    Some(body)
+}
+
+// Wasker should refuse to compile the module
+fn rejects(mut module: QuoteWat, log_dir: &str) -> bool {
+    let Ok(wasm) = module.encode() else {
+        return true;
+    };
+    let args = compiler::Args {
+        output_file: format!("{log_dir}/rejected.o").into(),
+        ..Default::default()
+    };
+    !matches!(
+        std::panic::catch_unwind(|| compiler::compile_wasm(&wasm, &args)),
+        Ok(Ok(()))
+    )
+}
+
+/// Run a `.wast` script and count the results of its directives.
+fn run_wast(path: &Path) -> WastReport {
+    let project_root = env!("CARGO_MANIFEST_DIR");
+    let stem = path
+        .file_stem()
+        .expect("wast file name")
+        .to_string_lossy()
+        .into_owned();
+    let log_dir = format!("{project_root}/target/test_logs/wast/{stem}");
+    ensure_log_dir(&log_dir);
+
+    let contents = std::fs::read_to_string(path).expect("Failed to read wast file");
+    let mut report = WastReport::default();
+    let buf = match ParseBuffer::new(&contents) {
+        Ok(buf) => buf,
+        Err(e) => panic!("Failed to lex {}: {e}", path.display()),
+    };
+    let wast = match parser::parse::<Wast>(&buf) {
+        Ok(wast) => wast,
+        Err(e) => panic!("Failed to parse {}: {e}", path.display()),
+    };
+
+    let mut current: Option<ModuleRun> = None;
+    for directive in wast.directives {
+        let line = directive.span().linecol_in(&contents).0 + 1;
+        let (invoke, expect) = match directive {
+            WastDirective::Module(module) => {
+                if let Some(run) = current.take() {
+                    run.finish(&log_dir, &mut report);
+                }
+                current = Some(ModuleRun::new(format!("{stem}_{line}"), module));
+                continue;
+            }
+            WastDirective::AssertInvalid { module, .. }
+            | WastDirective::AssertMalformed { module, .. } => {
+                let ok = rejects(module, &log_dir);
+                report.validation.record(ok);
+                if !ok {
+                    report.failed_lines.push(line);
+                }
+                continue;
+            }
+            WastDirective::Invoke(invoke) => (invoke, Expect::Nothing),
+            WastDirective::AssertReturn {
+                exec: WastExecute::Invoke(invoke),
+                results,
+                ..
+            } => (invoke, Expect::Return(results)),
+            WastDirective::AssertTrap {
+                exec: WastExecute::Invoke(invoke),
+                ..
+            }
+            | WastDirective::AssertExhaustion { call: invoke, .. } => (invoke, Expect::Trap),
+            _ => {
+                report.execution.skipped += 1;
+                continue;
+            }
+        };
+        match current.as_mut() {
+            Some(run) => run.directives.push(Directive {
+                line,
+                invoke,
+                expect,
+            }),
+            None => report.execution.skipped += 1,
+        }
+    }
+    if let Some(run) = current.take() {
+        run.finish(&log_dir, &mut report);
+    }
+    report
+}
+
+fn assert_wast_passes(path: &Path) {
+    let report = run_wast(path);
+    assert!(
+        report.execution.failed == 0 && report.validation.failed == 0,
+        "{}: {report:?}",
+        path.display()
+    );
+    assert!(
+        report.execution.passed > 0,
+        "{}: {report:?}",
+        path.display()
+    );
+}
+
+fn run_local_wast(name: &str) {
+    let project_root = env!("CARGO_MANIFEST_DIR");
+    assert_wast_passes(&PathBuf::from(format!(
+        "{project_root}/tests/wast/{name}.wast"
+    )));
+}
+
+#[test]
+fn wast_harness() {
+    run_local_wast("harness");
+}
+
+#[test]
+fn wast_call_indirect() {
+    run_local_wast("call_indirect");
+}
+
+#[test]
+fn wast_const_expr() {
+    run_local_wast("const_expr");
+}
+
+/// Run every script of the spec testsuite and print the conformance of each proposal.
+/// The scripts at the top level of `tests/spec/test/core` are counted as `core`.
+#[test]
+#[ignore = "run with `cargo test spec_testsuite -- --ignored` after checking out tests/spec"]
+fn spec_testsuite() {
+    let project_root = env!("CARGO_MANIFEST_DIR");
+    let core = PathBuf::from(format!("{project_root}/tests/spec/test/core"));
+    let Ok(entries) = std::fs::read_dir(&core) else {
+        eprintln!("tests/spec is not checked out. Run `git submodule update --init`");
+        return;
+    };
+
+    let mut scripts: Vec<(String, PathBuf)> = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            let proposal = entry.file_name().to_string_lossy().into_owned();
+            for script in std::fs::read_dir(&path).into_iter().flatten().flatten() {
+                scripts.push((proposal.clone(), script.path()));
+            }
+        } else {
+            scripts.push(("core".to_string(), path));
+        }
+    }
+    scripts.retain(|(_, path)| path.extension().is_some_and(|e| e == "wast"));
+    scripts.sort();
+
+    let mut proposals: Vec<(String, Tally, Tally)> = Vec::new();
+    for (proposal, path) in scripts {
+        let report = run_wast(&path);
+        println!(
+            "{proposal}/{}: execution {:?}, validation {:?}",
+            path.file_name().unwrap_or_default().to_string_lossy(),
+            report.execution,
+            report.validation
+        );
+        if proposals.last().map(|p| &p.0) != Some(&proposal) {
+            proposals.push((proposal, Tally::default(), Tally::default()));
+        }
+        let last = proposals.last_mut().expect("proposal");
+        last.1.add(report.execution);
+        last.2.add(report.validation);
+    }
+
+    println!("\nproposal: execution passed/failed/skipped, validation passed/failed");
+    for (proposal, execution, validation) in proposals {
+        println!(
+            "{proposal}: {}/{}/{}, {}/{}",
+            execution.passed,
+            execution.failed,
+            execution.skipped,
+            validation.passed,
+            validation.failed
+        );
+    }
+}
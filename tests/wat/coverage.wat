@@ -0,0 +1,36 @@
+;; Test coverage counters, run with --coverage=block
+(module
+  (import "myenv" "print" (func $print (param i32 i32)))
+  ;; Number of records and sum of counts of a function
+  (import "host" "coverage_records" (func $coverage_records (param i32) (result i32)))
+  (import "host" "coverage_total" (func $coverage_total (param i32) (result i32)))
+  (memory 1)
+  (data (i32.const 16) "Pass\n")
+  (data (i32.const 32) "Fail\n")
+
+  (func $check (param $ok i32)
+    (if (local.get $ok)
+      (then (call $print (i32.const 16) (i32.const 5)))
+      (else (call $print (i32.const 32) (i32.const 5)))))
+
+  ;; Function index 4, only the entry is counted
+  (func $straight)
+
+  ;; Function index 5, counted at the entry, the loop body, after br_if and after the loop
+  (func $loop
+    (local $i i32)
+    (loop $continue
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (br_if $continue (i32.lt_u (local.get $i) (i32.const 5)))))
+
+  (func (export "_start")
+    (call $check (i32.eqz (call $coverage_total (i32.const 4))))
+    (call $straight)
+    (call $straight)
+    (call $straight)
+    (call $check (i32.eq (call $coverage_records (i32.const 4)) (i32.const 1)))
+    (call $check (i32.eq (call $coverage_total (i32.const 4)) (i32.const 3)))
+
+    (call $loop)
+    (call $check (i32.eq (call $coverage_records (i32.const 5)) (i32.const 4)))
+    (call $check (i32.eq (call $coverage_total (i32.const 5)) (i32.const 8)))))
@@ -0,0 +1,4 @@
+(module
+  (@custom "app.meta" "name=hello;version=1")
+  (@custom "ignored" "not copied")
+  (func (export "_start")))
@@ -0,0 +1,4 @@
+(module
+  (@custom "app.meta" "name=hello")
+  (@custom "app-meta" "name=world")
+  (func (export "_start")))
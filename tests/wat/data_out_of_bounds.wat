@@ -0,0 +1,11 @@
+;; Test that a data segment at an offset known only at runtime traps out of the memory
+;; Pass is printed by wasker_trap of the host, as the test expects the MemoryOutOfBounds trap
+(module
+  (import "host" "data_offset" (global $offset i32))
+  (import "myenv" "print" (func $print (param i32 i32)))
+  (memory 1)
+  (data (i32.const 0) "Fail\n")
+  ;; 65534 + 4 is past the end of the memory
+  (data (global.get $offset) "oops")
+  (func (export "_start")
+    (call $print (i32.const 0) (i32.const 5))))
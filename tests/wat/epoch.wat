@@ -0,0 +1,32 @@
+;; Test epoch interruption, run with --epoch-interruption
+(module
+  (import "myenv" "print" (func $print (param i32 i32)))
+  ;; Move the deadline to the current epoch, and count calls to wasker_interrupt
+  (import "host" "expire_epoch" (func $expire_epoch))
+  (import "host" "interrupt_count" (func $interrupt_count (result i32)))
+  (memory 1)
+  (data (i32.const 16) "Pass\n")
+  (data (i32.const 32) "Fail\n")
+
+  (func $check (param $ok i32)
+    (if (local.get $ok)
+      (then (call $print (i32.const 16) (i32.const 5)))
+      (else (call $print (i32.const 32) (i32.const 5)))))
+
+  (func $nop)
+
+  (func (export "_start")
+    (local $i i32)
+    (call $check (i32.eqz (call $interrupt_count)))
+
+    ;; Interrupted at the entry of a function
+    (call $expire_epoch)
+    (call $nop)
+    (call $check (i32.eq (call $interrupt_count) (i32.const 1)))
+
+    ;; Interrupted at the loop header, only once as the hook moves the deadline
+    (call $expire_epoch)
+    (loop $continue
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (br_if $continue (i32.lt_u (local.get $i) (i32.const 10))))
+    (call $check (i32.eq (call $interrupt_count) (i32.const 2)))))
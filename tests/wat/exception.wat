@@ -0,0 +1,173 @@
+;; Test exception handling operators
+(module
+
+  ;; Import our myprint function
+  (import "myenv" "print" (func $print (param i64 i32)))
+
+  ;; Define a single page memory of 64KB.
+  (memory $0 1)
+
+  (data (i32.const 40) "Test Passed\n")
+  (data (i32.const 52) "#Test Failed\n")
+
+  (func $printSuccess
+    i64.const 40
+    i32.const 12
+    (call $print)
+  )
+
+  (func $printFail
+    i64.const 52
+    i32.const 13
+    (call $print)
+  )
+
+  (func $assert_test_i32 (param $expected i32) (param $result i32)
+    local.get $expected
+    local.get $result
+    i32.eq
+    (if
+      (then
+        (call $printSuccess)
+      )
+      (else
+        (call $printFail)
+      )
+    )
+  )
+
+  (tag $e0)
+  (tag $e-i32 (param i32))
+  (tag $e-i64-f64 (param i64 f64))
+  (tag $e-unused (param f32))
+  (tag $e-ref-i32 (param funcref i32))
+  (global $ref (mut funcref) (ref.func $throw-if))
+  (export "e-i32" (tag $e-i32))
+
+  (func $throw-if (param i32) (result i32)
+    (local.get 0)
+    (if (then (throw $e-i32 (local.get 0))))
+    (i32.const 0)
+  )
+
+  (func $throw-nested (param i32) (result i32)
+    (call $throw-if (local.get 0))
+  )
+
+  (func $catch-local (result i32)
+    try (result i32)
+      (throw $e-i32 (i32.const 7))
+    catch $e-i32
+    end
+  )
+
+  (func $catch-from-callee (param i32) (result i32)
+    try (result i32)
+      (call $throw-nested (local.get 0))
+    catch $e-i32
+      (i32.add (i32.const 100))
+    end
+  )
+
+  (func $catch-multi (param i32) (result i32)
+    (local $f f64)
+    try (result i32)
+      (local.get 0)
+      (if (then (throw $e0)))
+      (throw $e-i64-f64 (i64.const 3) (f64.const 4.5))
+    catch $e0
+      (i32.const 1)
+    catch $e-i64-f64
+      (local.set $f)
+      (i32.wrap_i64)
+      (i32.trunc_f64_s (local.get $f))
+      (i32.add)
+    end
+  )
+
+  (func $catch-all (result i32)
+    try (result i32)
+      (throw $e-unused (f32.const 1.0))
+    catch $e-i32
+    catch_all
+      (i32.const 11)
+    end
+  )
+
+  (func $rethrow (result i32)
+    try (result i32)
+      try
+        (throw $e-i32 (i32.const 21))
+      catch $e-i32
+        (drop)
+        (rethrow 0)
+      end
+      (i32.const 0)
+    catch $e-i32
+    end
+  )
+
+  (func $delegate (result i32)
+    try (result i32)
+      try
+        (throw $e-i32 (i32.const 31))
+      delegate 0
+      (i32.const 0)
+    catch $e-i32
+    end
+  )
+
+  (func $catch-in-loop (result i32)
+    (local $i i32)
+    (local $sum i32)
+    (loop $continue
+      try
+        (call $throw-if (local.get $i))
+        (drop)
+      catch $e-i32
+        (local.get $sum)
+        (i32.add)
+        (local.set $sum)
+      end
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (br_if $continue (i32.lt_u (local.get $i) (i32.const 5)))
+    )
+    (local.get $sum)
+  )
+
+  ;; References are carried through the payload too
+  (func $catch-ref (result i32)
+    (local $i i32)
+    try (result i32)
+      (throw $e-ref-i32 (global.get $ref) (i32.const 41))
+    catch $e-ref-i32
+      (local.set $i)
+      (global.set $ref)
+      (local.get $i)
+    end
+  )
+
+  (func $no-exception (result i32)
+    try (result i32)
+      (call $throw-nested (i32.const 0))
+      (i32.const 5)
+      (i32.add)
+    catch_all
+      (i32.const 99)
+    end
+  )
+
+  (func (export "_start")
+    (call $assert_test_i32 (call $catch-local) (i32.const 7))
+    (call $assert_test_i32 (call $catch-from-callee (i32.const 3)) (i32.const 103))
+    (call $assert_test_i32 (call $catch-from-callee (i32.const 0)) (i32.const 0))
+    (call $assert_test_i32 (call $catch-multi (i32.const 1)) (i32.const 1))
+    (call $assert_test_i32 (call $catch-multi (i32.const 0)) (i32.const 7))
+    (call $assert_test_i32 (call $catch-all) (i32.const 11))
+    (call $assert_test_i32 (call $rethrow) (i32.const 21))
+    (call $assert_test_i32 (call $delegate) (i32.const 31))
+    (call $assert_test_i32 (call $catch-in-loop) (i32.const 10))
+    (call $assert_test_i32 (call $catch-ref) (i32.const 41))
+    (call $assert_test_i32 (call $no-exception) (i32.const 5))
+  )
+)
@@ -0,0 +1,37 @@
+;; Test an exception which is not caught by the guest
+(module
+
+  ;; Import our myprint function
+  (import "myenv" "print" (func $print (param i64 i32)))
+
+  ;; Define a single page memory of 64KB.
+  (memory $0 1)
+
+  (data (i32.const 52) "#Test Failed\n")
+
+  (func $printFail
+    i64.const 52
+    i32.const 13
+    (call $print)
+  )
+
+  (tag $e-i32 (param i32))
+
+  (func $throw (param i32)
+    (throw $e-i32 (local.get 0))
+  )
+
+  (func $catch-other (param i32)
+    try
+      (call $throw (local.get 0))
+    catch_all
+      (rethrow 0)
+    end
+  )
+
+  ;; wasker_main traps after _start returns with the exception
+  (func (export "_start")
+    (call $catch-other (i32.const 1))
+    (call $printFail)
+  )
+)
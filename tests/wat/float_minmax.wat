@@ -0,0 +1,111 @@
+;; Test float min, max and nearest
+(module
+
+  ;; Import our myprint function
+  (import "myenv" "print" (func $print (param i64 i32)))
+
+  ;; Define a single page memory of 64KB.
+  (memory $0 1)
+
+  (data (i32.const 40) "Test Passed\n")
+  (data (i32.const 52) "#Test Failed\n")
+
+  (func $printSuccess
+    i64.const 40
+    i32.const 12
+    (call $print)
+  )
+
+  (func $printFail
+    i64.const 52
+    i32.const 13
+    (call $print)
+  )
+
+  (func $assert_test_i32 (param $expected i32) (param $result i32)
+    local.get $expected
+    local.get $result
+    i32.eq
+    (if
+      (then
+        (call $printSuccess)
+      )
+      (else
+        (call $printFail)
+      )
+    )
+  )
+
+  (func $assert_test_i64 (param $expected i64) (param $result i64)
+    local.get $expected
+    local.get $result
+    i64.eq
+    (if
+      (then
+        (call $printSuccess)
+      )
+      (else
+        (call $printFail)
+      )
+    )
+  )
+
+  (func $f32.min (param f32 f32) (result i32)
+    (i32.reinterpret_f32 (f32.min (local.get 0) (local.get 1)))
+  )
+
+  (func $f32.max (param f32 f32) (result i32)
+    (i32.reinterpret_f32 (f32.max (local.get 0) (local.get 1)))
+  )
+
+  (func $f64.min (param f64 f64) (result i64)
+    (i64.reinterpret_f64 (f64.min (local.get 0) (local.get 1)))
+  )
+
+  (func $f64.max (param f64 f64) (result i64)
+    (i64.reinterpret_f64 (f64.max (local.get 0) (local.get 1)))
+  )
+
+  (func $f32.min-is-nan (param f32 f32) (result i32)
+    (local $r f32)
+    (local.set $r (f32.min (local.get 0) (local.get 1)))
+    (f32.ne (local.get $r) (local.get $r))
+  )
+
+  (func $f64.max-is-nan (param f64 f64) (result i32)
+    (local $r f64)
+    (local.set $r (f64.max (local.get 0) (local.get 1)))
+    (f64.ne (local.get $r) (local.get $r))
+  )
+
+  (func $f32.nearest (param f32) (result i32)
+    (i32.reinterpret_f32 (f32.nearest (local.get 0)))
+  )
+
+  (func $f64.nearest (param f64) (result i64)
+    (i64.reinterpret_f64 (f64.nearest (local.get 0)))
+  )
+
+  (func (export "_start")
+    (call $assert_test_i32 (call $f32.min (f32.const -0.0) (f32.const 0.0)) (i32.const 0x80000000))
+    (call $assert_test_i32 (call $f32.min (f32.const 0.0) (f32.const -0.0)) (i32.const 0x80000000))
+    (call $assert_test_i32 (call $f32.max (f32.const -0.0) (f32.const 0.0)) (i32.const 0))
+    (call $assert_test_i32 (call $f32.max (f32.const 0.0) (f32.const -0.0)) (i32.const 0))
+    (call $assert_test_i32 (call $f32.min (f32.const 1.5) (f32.const -2.5)) (i32.reinterpret_f32 (f32.const -2.5)))
+    (call $assert_test_i32 (call $f32.max (f32.const 1.5) (f32.const -2.5)) (i32.reinterpret_f32 (f32.const 1.5)))
+    (call $assert_test_i64 (call $f64.min (f64.const 0.0) (f64.const -0.0)) (i64.const 0x8000000000000000))
+    (call $assert_test_i64 (call $f64.max (f64.const -0.0) (f64.const 0.0)) (i64.const 0))
+    (call $assert_test_i64 (call $f64.min (f64.const -inf) (f64.const 3.0)) (i64.reinterpret_f64 (f64.const -inf)))
+    (call $assert_test_i64 (call $f64.max (f64.const -inf) (f64.const 3.0)) (i64.reinterpret_f64 (f64.const 3.0)))
+    (call $assert_test_i32 (call $f32.min-is-nan (f32.const nan) (f32.const 1.0)) (i32.const 1))
+    (call $assert_test_i32 (call $f32.min-is-nan (f32.const 1.0) (f32.const nan)) (i32.const 1))
+    (call $assert_test_i32 (call $f32.min-is-nan (f32.const 1.0) (f32.const 2.0)) (i32.const 0))
+    (call $assert_test_i32 (call $f64.max-is-nan (f64.const nan) (f64.const -inf)) (i32.const 1))
+    (call $assert_test_i32 (call $f64.max-is-nan (f64.const inf) (f64.const nan)) (i32.const 1))
+    (call $assert_test_i32 (call $f32.nearest (f32.const 2.5)) (i32.reinterpret_f32 (f32.const 2.0)))
+    (call $assert_test_i32 (call $f32.nearest (f32.const 3.5)) (i32.reinterpret_f32 (f32.const 4.0)))
+    (call $assert_test_i32 (call $f32.nearest (f32.const -0.5)) (i32.const 0x80000000))
+    (call $assert_test_i64 (call $f64.nearest (f64.const 4.5)) (i64.reinterpret_f64 (f64.const 4.0)))
+    (call $assert_test_i64 (call $f64.nearest (f64.const -1.5)) (i64.reinterpret_f64 (f64.const -2.0)))
+  )
+)
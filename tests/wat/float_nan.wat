@@ -0,0 +1,82 @@
+;; Test NaN canonicalization
+(module
+
+  ;; Import our myprint function
+  (import "myenv" "print" (func $print (param i64 i32)))
+
+  ;; Define a single page memory of 64KB.
+  (memory $0 1)
+
+  (data (i32.const 40) "Test Passed\n")
+  (data (i32.const 52) "#Test Failed\n")
+
+  (func $printSuccess
+    i64.const 40
+    i32.const 12
+    (call $print)
+  )
+
+  (func $printFail
+    i64.const 52
+    i32.const 13
+    (call $print)
+  )
+
+  (func $assert_test_i32 (param $expected i32) (param $result i32)
+    local.get $expected
+    local.get $result
+    i32.eq
+    (if
+      (then
+        (call $printSuccess)
+      )
+      (else
+        (call $printFail)
+      )
+    )
+  )
+
+  (func $assert_test_i64 (param $expected i64) (param $result i64)
+    local.get $expected
+    local.get $result
+    i64.eq
+    (if
+      (then
+        (call $printSuccess)
+      )
+      (else
+        (call $printFail)
+      )
+    )
+  )
+
+  (func $f32.add (param f32 f32) (result i32)
+    (i32.reinterpret_f32 (f32.add (local.get 0) (local.get 1)))
+  )
+
+  (func $f32.sqrt (param f32) (result i32)
+    (i32.reinterpret_f32 (f32.sqrt (local.get 0)))
+  )
+
+  (func $f64.div (param f64 f64) (result i64)
+    (i64.reinterpret_f64 (f64.div (local.get 0) (local.get 1)))
+  )
+
+  (func $f64.promote (param f32) (result i64)
+    (i64.reinterpret_f64 (f64.promote_f32 (local.get 0)))
+  )
+
+  (func $f32.neg (param f32) (result i32)
+    (i32.reinterpret_f32 (f32.neg (local.get 0)))
+  )
+
+  (func (export "_start")
+    (call $assert_test_i32 (call $f32.add (f32.const -nan:0x200000) (f32.const 1.0)) (i32.const 0x7fc00000))
+    (call $assert_test_i32 (call $f32.add (f32.const 1.0) (f32.const 2.0)) (i32.reinterpret_f32 (f32.const 3.0)))
+    (call $assert_test_i32 (call $f32.sqrt (f32.const -1.0)) (i32.const 0x7fc00000))
+    (call $assert_test_i64 (call $f64.div (f64.const 0.0) (f64.const 0.0)) (i64.const 0x7ff8000000000000))
+    (call $assert_test_i64 (call $f64.div (f64.const -nan:0x4) (f64.const 1.0)) (i64.const 0x7ff8000000000000))
+    (call $assert_test_i64 (call $f64.promote (f32.const -nan:0x1)) (i64.const 0x7ff8000000000000))
+    (call $assert_test_i32 (call $f32.neg (f32.const nan:0x1)) (i32.const 0xff800001))
+  )
+)
@@ -0,0 +1,30 @@
+;; Test fuel metering, run with --fuel
+(module
+  (import "myenv" "print" (func $print (param i32 i32)))
+  ;; Number of calls to wasker_out_of_fuel, counted by the host
+  (import "host" "out_of_fuel_count" (func $out_of_fuel_count (result i32)))
+  (memory 1)
+  (data (i32.const 16) "Pass\n")
+  (data (i32.const 32) "Fail\n")
+
+  (func $check (param $ok i32)
+    (if (local.get $ok)
+      (then (call $print (i32.const 16) (i32.const 5)))
+      (else (call $print (i32.const 32) (i32.const 5)))))
+
+  ;; Sum 0..n in a loop which doesn't call anything
+  (func $sum (param $n i32) (result i32)
+    (local $i i32)
+    (local $sum i32)
+    (loop $continue
+      (local.set $sum (i32.add (local.get $sum) (local.get $i)))
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (br_if $continue (i32.lt_u (local.get $i) (local.get $n))))
+    (local.get $sum))
+
+  (func (export "_start")
+    ;; The host starts with 100 fuel, and refills 100 on each call
+    (call $check (i32.eqz (call $out_of_fuel_count)))
+    (call $check (i32.eq (call $sum (i32.const 1000)) (i32.const 499500)))
+    ;; Each iteration costs 12 with the default costs
+    (call $check (i32.ge_u (call $out_of_fuel_count) (i32.const 100)))))
@@ -0,0 +1,12 @@
+;; Globals initialized with ref.func
+(module
+  (func $f (result i32) (i32.const 1))
+  (func $g (result i32) (i32.const 2))
+
+  (global $ref (mut funcref) (ref.func $f))
+  (global $const_ref funcref (ref.func $g))
+
+  (func (export "_start")
+    (global.set $ref (global.get $const_ref))
+  )
+)
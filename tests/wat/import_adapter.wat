@@ -0,0 +1,6 @@
+(module
+  (import "host" "print" (func $print (param i32 i32)))
+  (memory 1)
+  (data (i32.const 16) "Pass\n")
+  (func (export "_start")
+    (call $print (i32.const 16) (i32.const 5))))
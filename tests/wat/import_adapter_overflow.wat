@@ -0,0 +1,10 @@
+;; Test that a range wrapping around the address space traps, run with `host print64 ptr len`
+;; Pass is printed by wasker_trap of the host, as the test expects the MemoryOutOfBounds trap
+(module
+  (import "host" "print64" (func $print64 (param i64 i64)))
+  (memory 1)
+  (data (i32.const 16) "Fail\n")
+  (func (export "_start")
+    ;; 16 + len wraps to 5, which would pass a check on the end of the range
+    (call $print64 (i64.const 16) (i64.const -11))
+    (call $print64 (i64.const 16) (i64.const 5))))
@@ -0,0 +1,12 @@
+(module
+  (import "host" "print_guest" (func $print_guest (param i32 i32)))
+  (import "host" "check_memory_size" (func $check_memory_size (param i32)))
+  (memory 1)
+  (data (i32.const 16) "Pass\n")
+  (func (export "_start")
+    (call $print_guest (i32.const 16) (i32.const 5))
+    (call $check_memory_size (i32.const 1))
+    ;; memory.grow is reflected in the context
+    (drop (memory.grow (i32.const 1)))
+    (call $print_guest (i32.const 16) (i32.const 5))
+    (call $check_memory_size (i32.const 2))))
@@ -0,0 +1,17 @@
+(module
+  (import "env" "base" (global $base i32))
+  (memory 1)
+  (table 1 funcref)
+  (global $counter (mut i32) (i32.const 0))
+  ;; Names of globals of the runtime
+  (global $table (mut i32) (i32.const 0))
+  (global $mem_size (mut i32) (i32.const 0))
+  (global (mut i32) (i32.const 0))
+  (func $add (export "add") (param $lhs i32) (param $rhs i32) (result i32)
+    (local $sum i32)
+    (block $done
+      (local.set $sum (i32.add (local.get $lhs) (local.get $rhs)))
+      (br_if $done (i32.eqz (local.get $sum)))
+      (global.set $counter (i32.add (global.get $counter) (i32.const 1))))
+    (local.get $sum))
+  (data $greeting (i32.const 0) "hello"))
@@ -0,0 +1,144 @@
+;; Test `return_call` and `return_call_indirect` operators
+(module
+
+  ;; Import our myprint function
+  (import "myenv" "print" (func $print (param i64 i32)))
+
+  ;; Define a single page memory of 64KB.
+  (memory $0 1)
+
+  (data (i32.const 40) "Test Passed\n")
+  (data (i32.const 52) "#Test Failed\n")
+
+  (func $printSuccess
+    i64.const 40
+    i32.const 12
+    (call $print)
+  )
+
+  (func $printFail
+    i64.const 52
+    i32.const 13
+    (call $print)
+  )
+
+  (func $assert_test_i32 (param $expected i32) (param $result i32)
+    local.get $expected
+    local.get $result
+    i32.eq
+    (if
+      (then
+        (call $printSuccess)
+      )
+      (else
+        (call $printFail)
+      )
+    )
+  )
+
+  (func $assert_test_i64 (param $expected i64) (param $result i64)
+    local.get $expected
+    local.get $result
+    i64.eq
+    (if
+      (then
+        (call $printSuccess)
+      )
+      (else
+        (call $printFail)
+      )
+    )
+  )
+
+  (type $over-i64 (func (param i64) (result i64)))
+  (table funcref (elem $id-i64 $fac-indirect $count-indirect))
+
+  (func $id-i64 (type $over-i64) (local.get 0))
+
+  ;; Mutual recursion deep enough to overflow the native stack without tail calls
+  (func $even (param i64) (result i32)
+    (if (result i32) (i64.eqz (local.get 0))
+      (then (i32.const 44))
+      (else (return_call $odd (i64.sub (local.get 0) (i64.const 1))))
+    )
+  )
+  (func $odd (param i64) (result i32)
+    (if (result i32) (i64.eqz (local.get 0))
+      (then (i32.const 99))
+      (else (return_call $even (i64.sub (local.get 0) (i64.const 1))))
+    )
+  )
+
+  (func $count (param i64) (result i64)
+    (if (result i64) (i64.eqz (local.get 0))
+      (then (local.get 0))
+      (else (return_call $count (i64.sub (local.get 0) (i64.const 1))))
+    )
+  )
+
+  (func $fac-acc (param i64 i64) (result i64)
+    (if (result i64) (i64.eqz (local.get 0))
+      (then (local.get 1))
+      (else
+        (return_call $fac-acc
+          (i64.sub (local.get 0) (i64.const 1))
+          (i64.mul (local.get 0) (local.get 1))
+        )
+      )
+    )
+  )
+
+  (func $fac-indirect (type $over-i64)
+    (if (result i64) (i64.eqz (local.get 0))
+      (then (i64.const 1))
+      (else
+        (i64.mul
+          (local.get 0)
+          (call_indirect (type $over-i64)
+            (i64.sub (local.get 0) (i64.const 1))
+            (i32.const 1)
+          )
+        )
+      )
+    )
+  )
+
+  ;; Recursion through the table
+  (func $count-indirect (type $over-i64)
+    (if (result i64) (i64.eqz (local.get 0))
+      (then (local.get 0))
+      (else
+        (return_call_indirect (type $over-i64)
+          (i64.sub (local.get 0) (i64.const 1))
+          (i32.const 2)
+        )
+      )
+    )
+  )
+
+  ;; Recursion of a function also called by the host
+  (func $count-exported (export "count_exported") (param i64) (result i64)
+    (if (result i64) (i64.eqz (local.get 0))
+      (then (local.get 0))
+      (else (return_call $count-exported (i64.sub (local.get 0) (i64.const 1))))
+    )
+  )
+
+  (func $dispatch (param i32 i64) (result i64)
+    (return_call_indirect (type $over-i64) (local.get 1) (local.get 0))
+  )
+
+  (func (export "_start")
+    (call $assert_test_i32 (call $even (i64.const 0)) (i32.const 44))
+    (call $assert_test_i32 (call $even (i64.const 1)) (i32.const 99))
+    (call $assert_test_i32 (call $even (i64.const 1000000)) (i32.const 44))
+    (call $assert_test_i32 (call $odd (i64.const 1000001)) (i32.const 44))
+    (call $assert_test_i64 (call $count (i64.const 1000000)) (i64.const 0))
+    (call $assert_test_i64 (call $fac-acc (i64.const 5) (i64.const 1)) (i64.const 120))
+    (call $assert_test_i64 (call $fac-acc (i64.const 20) (i64.const 1)) (i64.const 2432902008176640000))
+    (call $assert_test_i64 (call $dispatch (i32.const 0) (i64.const 7)) (i64.const 7))
+    (call $assert_test_i64 (call $dispatch (i32.const 1) (i64.const 5)) (i64.const 120))
+    (call $assert_test_i64 (call $dispatch (i32.const 2) (i64.const 1000000)) (i64.const 0))
+    (call $assert_test_i64 (call $count-exported (i64.const 1000000)) (i64.const 0))
+  )
+)
@@ -0,0 +1,19 @@
+(module
+  (@dylink.0
+    (mem-info (memory 16 2) (table 2 0))
+    (needed "libc.so"))
+  (import "env" "memory" (memory 1))
+  (import "env" "__indirect_function_table" (table 0 funcref))
+  (import "env" "__memory_base" (global $__memory_base i32))
+  (import "env" "__table_base" (global $__table_base i32))
+  (type $i32_i32 (func (param i32) (result i32)))
+  (func $double (type $i32_i32) (i32.mul (local.get 0) (i32.const 2)))
+  (func $square (type $i32_i32) (i32.mul (local.get 0) (local.get 0)))
+  (func $load (export "load") (param i32) (result i32)
+    (i32.load (i32.add (global.get $__memory_base) (local.get 0))))
+  (func $apply (export "apply") (param i32 i32) (result i32)
+    (call_indirect (type $i32_i32)
+      (local.get 1)
+      (i32.add (global.get $__table_base) (local.get 0))))
+  (elem (global.get $__table_base) func $double $square)
+  (data (global.get $__memory_base) "\2a\00\00\00\07\00\00\00"))
@@ -0,0 +1,26 @@
+;; Test stack limit, run with --stack-limit
+;; Pass is printed by wasker_trap of the host, as the test expects the StackExhausted trap
+(module
+  (import "myenv" "print" (func $print (param i32 i32)))
+  ;; Set wasker_stack_limit to the given number of bytes below the current stack pointer
+  (import "host" "limit_stack" (func $limit_stack (param i32)))
+  (memory 1)
+  (data (i32.const 32) "Fail\n")
+
+  (func $fail
+    (call $print (i32.const 32) (i32.const 5)))
+
+  ;; Recurse n times, not as a tail call
+  (func $depth (param $n i32) (result i32)
+    (if (result i32) (i32.eqz (local.get $n))
+      (then (i32.const 0))
+      (else (i32.add (call $depth (i32.sub (local.get $n) (i32.const 1))) (i32.const 1)))))
+
+  (func (export "_start")
+    ;; Shallow recursion runs within the limit
+    (call $limit_stack (i32.const 65536))
+    (if (i32.ne (call $depth (i32.const 10)) (i32.const 10))
+      (then (call $fail)))
+    ;; Deep recursion traps
+    (drop (call $depth (i32.const 1000000)))
+    (call $fail)))
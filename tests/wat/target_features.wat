@@ -0,0 +1,3 @@
+(module
+  (@custom "target_features" "\03\2b\0fmutable-globals\2b\07simd128\2d\07atomics")
+  (func (export "_start")))
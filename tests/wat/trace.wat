@@ -0,0 +1,46 @@
+;; Test tracing hooks, run with --trace
+(module
+  (import "myenv" "print" (func $print (param i32 i32)))
+  ;; Number of functions entered and not exited yet, or -1 outside of import hooks
+  (import "host" "trace_depth" (func $trace_depth (result i32)))
+  ;; Function index passed to the last wasker_trace_enter
+  (import "host" "trace_last_enter" (func $trace_last_enter (result i32)))
+  (memory 1)
+  (data (i32.const 16) "Pass\n")
+  (data (i32.const 32) "Fail\n")
+
+  (func $check (param $ok i32)
+    (if (local.get $ok)
+      (then (call $print (i32.const 16) (i32.const 5)))
+      (else (call $print (i32.const 32) (i32.const 5)))))
+
+  ;; Function index 4
+  (func $leaf (result i32)
+    (call $trace_depth))
+
+  (func $mid (result i32)
+    (call $leaf))
+
+  ;; The tail call replaces $tail
+  (func $tail (result i32)
+    (return_call $leaf))
+
+  ;; A tail call to an import returns to $tail_import
+  (func $tail_import (result i32)
+    (return_call $trace_depth))
+
+  (func $early (param $x i32) (result i32)
+    (if (local.get $x)
+      (then (return (call $trace_depth))))
+    (i32.const 0))
+
+  (func (export "_start")
+    (call $check (i32.eq (call $trace_depth) (i32.const 1)))
+    (call $check (i32.eq (call $mid) (i32.const 3)))
+    (drop (call $mid))
+    (call $check (i32.eq (call $trace_last_enter) (i32.const 4)))
+    (call $check (i32.eq (call $tail) (i32.const 2)))
+    (call $check (i32.eq (call $tail_import) (i32.const 2)))
+    (call $check (i32.eq (call $early (i32.const 1)) (i32.const 2)))
+    (drop (call $early (i32.const 0)))
+    (call $check (i32.eq (call $trace_depth) (i32.const 1)))))
@@ -0,0 +1,118 @@
+;; Test non-trapping float-to-int conversions
+(module
+
+  ;; Import our myprint function
+  (import "myenv" "print" (func $print (param i64 i32)))
+
+  ;; Define a single page memory of 64KB.
+  (memory $0 1)
+
+  (data (i32.const 40) "Test Passed\n")
+  (data (i32.const 52) "#Test Failed\n")
+
+  (func $printSuccess
+    i64.const 40
+    i32.const 12
+    (call $print)
+  )
+
+  (func $printFail
+    i64.const 52
+    i32.const 13
+    (call $print)
+  )
+
+  (func $assert_test_i32 (param $expected i32) (param $result i32)
+    local.get $expected
+    local.get $result
+    i32.eq
+    (if
+      (then
+        (call $printSuccess)
+      )
+      (else
+        (call $printFail)
+      )
+    )
+  )
+
+  (func $assert_test_i64 (param $expected i64) (param $result i64)
+    local.get $expected
+    local.get $result
+    i64.eq
+    (if
+      (then
+        (call $printSuccess)
+      )
+      (else
+        (call $printFail)
+      )
+    )
+  )
+
+  (func $i32.trunc_sat_f32_s (param f32) (result i32)
+    (i32.trunc_sat_f32_s (local.get 0))
+  )
+
+  (func $i32.trunc_sat_f32_u (param f32) (result i32)
+    (i32.trunc_sat_f32_u (local.get 0))
+  )
+
+  (func $i32.trunc_sat_f64_s (param f64) (result i32)
+    (i32.trunc_sat_f64_s (local.get 0))
+  )
+
+  (func $i32.trunc_sat_f64_u (param f64) (result i32)
+    (i32.trunc_sat_f64_u (local.get 0))
+  )
+
+  (func $i64.trunc_sat_f32_s (param f32) (result i64)
+    (i64.trunc_sat_f32_s (local.get 0))
+  )
+
+  (func $i64.trunc_sat_f32_u (param f32) (result i64)
+    (i64.trunc_sat_f32_u (local.get 0))
+  )
+
+  (func $i64.trunc_sat_f64_s (param f64) (result i64)
+    (i64.trunc_sat_f64_s (local.get 0))
+  )
+
+  (func $i64.trunc_sat_f64_u (param f64) (result i64)
+    (i64.trunc_sat_f64_u (local.get 0))
+  )
+
+  (func (export "_start")
+    (call $assert_test_i32 (call $i32.trunc_sat_f32_s (f32.const 1.9)) (i32.const 1))
+    (call $assert_test_i32 (call $i32.trunc_sat_f32_s (f32.const -1.9)) (i32.const -1))
+    (call $assert_test_i32 (call $i32.trunc_sat_f32_s (f32.const nan)) (i32.const 0))
+    (call $assert_test_i32 (call $i32.trunc_sat_f32_s (f32.const inf)) (i32.const 0x7fffffff))
+    (call $assert_test_i32 (call $i32.trunc_sat_f32_s (f32.const -inf)) (i32.const 0x80000000))
+    (call $assert_test_i32 (call $i32.trunc_sat_f32_s (f32.const 3e9)) (i32.const 0x7fffffff))
+    (call $assert_test_i32 (call $i32.trunc_sat_f32_u (f32.const 1.9)) (i32.const 1))
+    (call $assert_test_i32 (call $i32.trunc_sat_f32_u (f32.const -1.9)) (i32.const 0))
+    (call $assert_test_i32 (call $i32.trunc_sat_f32_u (f32.const nan)) (i32.const 0))
+    (call $assert_test_i32 (call $i32.trunc_sat_f32_u (f32.const 5e9)) (i32.const 0xffffffff))
+    (call $assert_test_i32 (call $i32.trunc_sat_f64_s (f64.const -2147483649.0)) (i32.const 0x80000000))
+    (call $assert_test_i32 (call $i32.trunc_sat_f64_s (f64.const 2147483647.9)) (i32.const 2147483647))
+    (call $assert_test_i32 (call $i32.trunc_sat_f64_s (f64.const -nan)) (i32.const 0))
+    (call $assert_test_i32 (call $i32.trunc_sat_f64_u (f64.const 4294967295.9)) (i32.const 0xffffffff))
+    (call $assert_test_i32 (call $i32.trunc_sat_f64_u (f64.const -inf)) (i32.const 0))
+    (call $assert_test_i32 (call $i32.trunc_sat_f64_u (f64.const 1e100)) (i32.const 0xffffffff))
+    (call $assert_test_i64 (call $i64.trunc_sat_f32_s (f32.const -1.5)) (i64.const -1))
+    (call $assert_test_i64 (call $i64.trunc_sat_f32_s (f32.const 1e20)) (i64.const 0x7fffffffffffffff))
+    (call $assert_test_i64 (call $i64.trunc_sat_f32_s (f32.const nan)) (i64.const 0))
+    (call $assert_test_i64 (call $i64.trunc_sat_f32_u (f32.const 4294967296)) (i64.const 4294967296))
+    (call $assert_test_i64 (call $i64.trunc_sat_f32_u (f32.const -1.5)) (i64.const 0))
+    (call $assert_test_i64 (call $i64.trunc_sat_f32_u (f32.const inf)) (i64.const 0xffffffffffffffff))
+    (call $assert_test_i64 (call $i64.trunc_sat_f32_u (f32.const nan)) (i64.const 0))
+    (call $assert_test_i64 (call $i64.trunc_sat_f64_s (f64.const -9223372036854775808.0)) (i64.const 0x8000000000000000))
+    (call $assert_test_i64 (call $i64.trunc_sat_f64_s (f64.const 1e19)) (i64.const 0x7fffffffffffffff))
+    (call $assert_test_i64 (call $i64.trunc_sat_f64_s (f64.const -inf)) (i64.const 0x8000000000000000))
+    (call $assert_test_i64 (call $i64.trunc_sat_f64_s (f64.const nan)) (i64.const 0))
+    (call $assert_test_i64 (call $i64.trunc_sat_f64_u (f64.const 18446744073709549568.0)) (i64.const 0xfffffffffffff800))
+    (call $assert_test_i64 (call $i64.trunc_sat_f64_u (f64.const 2e19)) (i64.const 0xffffffffffffffff))
+    (call $assert_test_i64 (call $i64.trunc_sat_f64_u (f64.const -0.9)) (i64.const 0))
+    (call $assert_test_i64 (call $i64.trunc_sat_f64_u (f64.const nan)) (i64.const 0))
+  )
+)
@@ -0,0 +1,24 @@
+(module
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "args_sizes_get" (func $args_sizes_get (param i32 i32) (result i32)))
+  (memory 1)
+  (export "memory" (memory 0))
+  (data (i32.const 32) "Pass: args_sizes_get\n")
+  (data (i32.const 64) "Fail: args_sizes_get\n")
+
+  ;; Write 21 bytes at $ptr to stdout
+  (func $print (param $ptr i32)
+    (i32.store (i32.const 0) (local.get $ptr))
+    (i32.store (i32.const 4) (i32.const 21))
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))
+  )
+
+  (func (export "_start")
+    ;; The program name is always passed
+    (if (i32.and
+          (i32.eqz (call $args_sizes_get (i32.const 16) (i32.const 20)))
+          (i32.ge_u (i32.load (i32.const 16)) (i32.const 1)))
+      (then (call $print (i32.const 32)))
+      (else (call $print (i32.const 64))))
+  )
+)
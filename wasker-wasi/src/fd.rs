@@ -0,0 +1,460 @@
+//! `fd_*` functions.
+
+use std::ffi::CStr;
+use std::mem::MaybeUninit;
+use std::os::fd::{AsRawFd, RawFd};
+
+use crate::errno::{cvt, cvt_size, wasi, Errno, Result};
+use crate::memory::{self, Layout};
+use crate::state::{host_fd, state};
+
+// filetype
+const FILETYPE_UNKNOWN: u8 = 0;
+const FILETYPE_BLOCK_DEVICE: u8 = 1;
+const FILETYPE_CHARACTER_DEVICE: u8 = 2;
+const FILETYPE_DIRECTORY: u8 = 3;
+const FILETYPE_REGULAR_FILE: u8 = 4;
+const FILETYPE_SOCKET_DGRAM: u8 = 5;
+const FILETYPE_SOCKET_STREAM: u8 = 6;
+const FILETYPE_SYMBOLIC_LINK: u8 = 7;
+
+// fdflags
+pub(crate) const FDFLAGS_APPEND: u32 = 1 << 0;
+pub(crate) const FDFLAGS_DSYNC: u32 = 1 << 1;
+pub(crate) const FDFLAGS_NONBLOCK: u32 = 1 << 2;
+pub(crate) const FDFLAGS_RSYNC: u32 = 1 << 3;
+pub(crate) const FDFLAGS_SYNC: u32 = 1 << 4;
+
+// fstflags
+const FSTFLAGS_ATIM: u32 = 1 << 0;
+const FSTFLAGS_ATIM_NOW: u32 = 1 << 1;
+const FSTFLAGS_MTIM: u32 = 1 << 2;
+const FSTFLAGS_MTIM_NOW: u32 = 1 << 3;
+
+// preopentype
+const PREOPENTYPE_DIR: u8 = 0;
+
+fn filetype(fd: RawFd, mode: libc::mode_t) -> u8 {
+    match mode & libc::S_IFMT {
+        libc::S_IFBLK => FILETYPE_BLOCK_DEVICE,
+        libc::S_IFCHR => FILETYPE_CHARACTER_DEVICE,
+        libc::S_IFDIR => FILETYPE_DIRECTORY,
+        libc::S_IFREG => FILETYPE_REGULAR_FILE,
+        libc::S_IFLNK => FILETYPE_SYMBOLIC_LINK,
+        libc::S_IFSOCK => {
+            let mut ty: libc::c_int = 0;
+            let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+            // SAFETY: ty and len are valid for getsockopt
+            let ret = unsafe {
+                libc::getsockopt(
+                    fd,
+                    libc::SOL_SOCKET,
+                    libc::SO_TYPE,
+                    &mut ty as *mut _ as *mut libc::c_void,
+                    &mut len,
+                )
+            };
+            match (ret, ty) {
+                (0, libc::SOCK_STREAM) => FILETYPE_SOCKET_STREAM,
+                (0, libc::SOCK_DGRAM) => FILETYPE_SOCKET_DGRAM,
+                _ => FILETYPE_UNKNOWN,
+            }
+        }
+        _ => FILETYPE_UNKNOWN,
+    }
+}
+
+pub(crate) fn fstat(fd: RawFd) -> Result<libc::stat> {
+    let mut stat = MaybeUninit::<libc::stat>::uninit();
+    // SAFETY: stat is written by fstat on success
+    cvt(unsafe { libc::fstat(fd, stat.as_mut_ptr()) })?;
+    Ok(unsafe { stat.assume_init() })
+}
+
+/// Write a `filestat` of the file `fd` to the guest.
+pub(crate) fn write_filestat(fd: RawFd, buf: u32) -> Result<()> {
+    let stat = fstat(fd)?;
+    let nanos = |sec: i64, nsec: i64| (sec as u64) * 1_000_000_000 + nsec as u64;
+    let mut filestat = Layout::<64>::default();
+    filestat
+        .u64(0, stat.st_dev)
+        .u64(8, stat.st_ino)
+        .u8(16, filetype(fd, stat.st_mode))
+        .u64(24, stat.st_nlink as u64)
+        .u64(32, stat.st_size as u64)
+        .u64(40, nanos(stat.st_atime, stat.st_atime_nsec))
+        .u64(48, nanos(stat.st_mtime, stat.st_mtime_nsec))
+        .u64(56, nanos(stat.st_ctime, stat.st_ctime_nsec));
+    memory::write_bytes(buf, &filestat.0)
+}
+
+/// `timespec` pair for `futimens` and `utimensat` from WASI timestamps.
+pub(crate) fn timespecs(atim: u64, mtim: u64, fst_flags: u32) -> Result<[libc::timespec; 2]> {
+    let timespec = |time: u64, set: bool, now: bool| match (set, now) {
+        (true, true) => Err(Errno::Inval),
+        (true, false) => Ok(libc::timespec {
+            tv_sec: (time / 1_000_000_000) as libc::time_t,
+            tv_nsec: (time % 1_000_000_000) as libc::c_long,
+        }),
+        (false, true) => Ok(libc::timespec {
+            tv_sec: 0,
+            tv_nsec: libc::UTIME_NOW,
+        }),
+        (false, false) => Ok(libc::timespec {
+            tv_sec: 0,
+            tv_nsec: libc::UTIME_OMIT,
+        }),
+    };
+    Ok([
+        timespec(
+            atim,
+            fst_flags & FSTFLAGS_ATIM != 0,
+            fst_flags & FSTFLAGS_ATIM_NOW != 0,
+        )?,
+        timespec(
+            mtim,
+            fst_flags & FSTFLAGS_MTIM != 0,
+            fst_flags & FSTFLAGS_MTIM_NOW != 0,
+        )?,
+    ])
+}
+
+/// Open flags of Linux from `fdflags`.
+pub(crate) fn fdflags_to_oflags(fdflags: u32) -> libc::c_int {
+    let mut flags = 0;
+    if fdflags & FDFLAGS_APPEND != 0 {
+        flags |= libc::O_APPEND;
+    }
+    if fdflags & FDFLAGS_DSYNC != 0 {
+        flags |= libc::O_DSYNC;
+    }
+    if fdflags & FDFLAGS_NONBLOCK != 0 {
+        flags |= libc::O_NONBLOCK;
+    }
+    if fdflags & FDFLAGS_RSYNC != 0 {
+        flags |= libc::O_RSYNC;
+    }
+    if fdflags & FDFLAGS_SYNC != 0 {
+        flags |= libc::O_SYNC;
+    }
+    flags
+}
+
+#[no_mangle]
+pub extern "C" fn fd_advise(fd: u32, offset: u64, len: u64, advice: u32) -> i32 {
+    wasi(|| {
+        let advice = match advice {
+            0 => libc::POSIX_FADV_NORMAL,
+            1 => libc::POSIX_FADV_SEQUENTIAL,
+            2 => libc::POSIX_FADV_RANDOM,
+            3 => libc::POSIX_FADV_WILLNEED,
+            4 => libc::POSIX_FADV_DONTNEED,
+            5 => libc::POSIX_FADV_NOREUSE,
+            _ => return Err(Errno::Inval),
+        };
+        let fd = host_fd(fd)?;
+        // SAFETY: plain syscall
+        let ret = unsafe { libc::posix_fadvise(fd, offset as i64, len as i64, advice) };
+        match ret {
+            0 => Ok(()),
+            errno => Err(Errno::from_raw_os_error(errno)),
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn fd_allocate(fd: u32, offset: u64, len: u64) -> i32 {
+    wasi(|| {
+        let fd = host_fd(fd)?;
+        // SAFETY: plain syscall
+        let ret = unsafe { libc::posix_fallocate(fd, offset as i64, len as i64) };
+        match ret {
+            0 => Ok(()),
+            errno => Err(Errno::from_raw_os_error(errno)),
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn fd_close(fd: u32) -> i32 {
+    // Dropping the entry closes the host fd
+    wasi(|| state().fds.remove(&fd).map(drop).ok_or(Errno::Badf))
+}
+
+#[no_mangle]
+pub extern "C" fn fd_datasync(fd: u32) -> i32 {
+    wasi(|| {
+        // SAFETY: plain syscall
+        cvt(unsafe { libc::fdatasync(host_fd(fd)?) })?;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn fd_fdstat_get(fd: u32, buf: u32) -> i32 {
+    wasi(|| {
+        let state = state();
+        let entry = state.get(fd)?;
+        let raw = entry.fd.as_raw_fd();
+        let stat = fstat(raw)?;
+        // SAFETY: plain syscall
+        let flags = cvt(unsafe { libc::fcntl(raw, libc::F_GETFL) })?;
+        let mut fdflags = 0;
+        if flags & libc::O_APPEND != 0 {
+            fdflags |= FDFLAGS_APPEND;
+        }
+        if flags & libc::O_NONBLOCK != 0 {
+            fdflags |= FDFLAGS_NONBLOCK;
+        }
+        // O_SYNC includes the bit of O_DSYNC
+        if flags & libc::O_SYNC == libc::O_SYNC {
+            fdflags |= FDFLAGS_SYNC;
+        } else if flags & libc::O_DSYNC != 0 {
+            fdflags |= FDFLAGS_DSYNC;
+        }
+        let mut fdstat = Layout::<24>::default();
+        fdstat
+            .u8(0, filetype(raw, stat.st_mode))
+            .u16(2, fdflags as u16)
+            .u64(8, entry.rights_base)
+            .u64(16, entry.rights_inheriting);
+        memory::write_bytes(buf, &fdstat.0)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn fd_fdstat_set_flags(fd: u32, fdflags: u32) -> i32 {
+    wasi(|| {
+        // Linux can't change the synchronization flags of an open file
+        if fdflags & (FDFLAGS_DSYNC | FDFLAGS_RSYNC | FDFLAGS_SYNC) != 0 {
+            return Err(Errno::Notsup);
+        }
+        let fd = host_fd(fd)?;
+        // SAFETY: plain syscall
+        cvt(unsafe { libc::fcntl(fd, libc::F_SETFL, fdflags_to_oflags(fdflags)) })?;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn fd_fdstat_set_rights(fd: u32, rights_base: u64, rights_inheriting: u64) -> i32 {
+    wasi(|| {
+        let mut state = state();
+        let entry = state.get_mut(fd)?;
+        // Rights can only be dropped
+        if rights_base & !entry.rights_base != 0
+            || rights_inheriting & !entry.rights_inheriting != 0
+        {
+            return Err(Errno::Notcapable);
+        }
+        entry.rights_base = rights_base;
+        entry.rights_inheriting = rights_inheriting;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn fd_filestat_get(fd: u32, buf: u32) -> i32 {
+    wasi(|| write_filestat(host_fd(fd)?, buf))
+}
+
+#[no_mangle]
+pub extern "C" fn fd_filestat_set_size(fd: u32, size: u64) -> i32 {
+    wasi(|| {
+        // SAFETY: plain syscall
+        cvt(unsafe { libc::ftruncate(host_fd(fd)?, size as i64) })?;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn fd_filestat_set_times(fd: u32, atim: u64, mtim: u64, fst_flags: u32) -> i32 {
+    wasi(|| {
+        let times = timespecs(atim, mtim, fst_flags)?;
+        // SAFETY: times has two elements
+        cvt(unsafe { libc::futimens(host_fd(fd)?, times.as_ptr()) })?;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn fd_pread(fd: u32, iovs: u32, iovs_len: u32, offset: u64, nread: u32) -> i32 {
+    wasi(|| {
+        let iovecs = memory::read_iovecs(iovs, iovs_len)?;
+        let fd = host_fd(fd)?;
+        // SAFETY: iovecs point into the checked linear memory
+        let n = cvt_size(unsafe {
+            libc::preadv(fd, iovecs.as_ptr(), iovecs.len() as i32, offset as i64)
+        })?;
+        memory::write_u32(nread, n as u32)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn fd_prestat_get(fd: u32, buf: u32) -> i32 {
+    wasi(|| {
+        let state = state();
+        let name = state.get(fd)?.preopen.as_ref().ok_or(Errno::Badf)?;
+        let mut prestat = Layout::<8>::default();
+        prestat.u8(0, PREOPENTYPE_DIR).u32(4, name.len() as u32);
+        memory::write_bytes(buf, &prestat.0)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn fd_prestat_dir_name(fd: u32, path: u32, path_len: u32) -> i32 {
+    wasi(|| {
+        let state = state();
+        let name = state.get(fd)?.preopen.as_ref().ok_or(Errno::Badf)?;
+        if (path_len as usize) < name.len() {
+            return Err(Errno::Nametoolong);
+        }
+        memory::write_bytes(path, name.as_bytes())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn fd_pwrite(fd: u32, iovs: u32, iovs_len: u32, offset: u64, nwritten: u32) -> i32 {
+    wasi(|| {
+        let iovecs = memory::read_iovecs(iovs, iovs_len)?;
+        let fd = host_fd(fd)?;
+        // SAFETY: iovecs point into the checked linear memory
+        let n = cvt_size(unsafe {
+            libc::pwritev(fd, iovecs.as_ptr(), iovecs.len() as i32, offset as i64)
+        })?;
+        memory::write_u32(nwritten, n as u32)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn fd_read(fd: u32, iovs: u32, iovs_len: u32, nread: u32) -> i32 {
+    wasi(|| {
+        let iovecs = memory::read_iovecs(iovs, iovs_len)?;
+        let fd = host_fd(fd)?;
+        // SAFETY: iovecs point into the checked linear memory
+        let n = cvt_size(unsafe { libc::readv(fd, iovecs.as_ptr(), iovecs.len() as i32) })?;
+        memory::write_u32(nread, n as u32)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn fd_readdir(fd: u32, buf: u32, buf_len: u32, cookie: u64, bufused: u32) -> i32 {
+    wasi(|| {
+        let entries = read_dir(host_fd(fd)?)?;
+        // Entries are written back to back, and the last one may be truncated
+        let mut out = Vec::new();
+        for (i, (ino, d_type, name)) in entries.iter().enumerate().skip(cookie as usize) {
+            if out.len() >= buf_len as usize {
+                break;
+            }
+            let mut dirent = Layout::<24>::default();
+            dirent
+                .u64(0, i as u64 + 1)
+                .u64(8, *ino)
+                .u32(16, name.len() as u32)
+                .u8(20, *d_type);
+            out.extend_from_slice(&dirent.0);
+            out.extend_from_slice(name);
+        }
+        out.truncate(buf_len as usize);
+        memory::write_bytes(buf, &out)?;
+        memory::write_u32(bufused, out.len() as u32)
+    })
+}
+
+/// Entries of the directory `fd` as (inode, filetype, name), in the order of the host.
+fn read_dir(fd: RawFd) -> Result<Vec<(u64, u8, Vec<u8>)>> {
+    // The stream owns a duplicate, so that closing it keeps fd open
+    // SAFETY: plain syscall
+    let dup = cvt(unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0) })?;
+    // SAFETY: dup is an open fd
+    let dir = unsafe { libc::fdopendir(dup) };
+    if dir.is_null() {
+        let errno = Errno::last_os_error();
+        // SAFETY: dup is not owned by a stream
+        unsafe { libc::close(dup) };
+        return Err(errno);
+    }
+    let mut entries = Vec::new();
+    // SAFETY: dir is a valid stream until closedir
+    unsafe {
+        libc::rewinddir(dir);
+        loop {
+            let entry = libc::readdir(dir);
+            if entry.is_null() {
+                break;
+            }
+            let d_type = match (*entry).d_type {
+                libc::DT_BLK => FILETYPE_BLOCK_DEVICE,
+                libc::DT_CHR => FILETYPE_CHARACTER_DEVICE,
+                libc::DT_DIR => FILETYPE_DIRECTORY,
+                libc::DT_REG => FILETYPE_REGULAR_FILE,
+                libc::DT_LNK => FILETYPE_SYMBOLIC_LINK,
+                libc::DT_SOCK => FILETYPE_SOCKET_STREAM,
+                _ => FILETYPE_UNKNOWN,
+            };
+            let name = CStr::from_ptr((*entry).d_name.as_ptr()).to_bytes().to_vec();
+            entries.push(((*entry).d_ino, d_type, name));
+        }
+        libc::closedir(dir);
+    }
+    Ok(entries)
+}
+
+#[no_mangle]
+pub extern "C" fn fd_renumber(fd: u32, to: u32) -> i32 {
+    wasi(|| {
+        let mut state = state();
+        state.get(fd)?;
+        state.get(to)?;
+        if fd != to {
+            // The entry previously at `to` is dropped and closed
+            let entry = state.fds.remove(&fd).unwrap();
+            state.fds.insert(to, entry);
+        }
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn fd_seek(fd: u32, offset: i64, whence: u32, newoffset: u32) -> i32 {
+    wasi(|| {
+        let whence = match whence {
+            0 => libc::SEEK_SET,
+            1 => libc::SEEK_CUR,
+            2 => libc::SEEK_END,
+            _ => return Err(Errno::Inval),
+        };
+        // SAFETY: plain syscall
+        let pos = unsafe { libc::lseek(host_fd(fd)?, offset, whence) };
+        if pos == -1 {
+            return Err(Errno::last_os_error());
+        }
+        memory::write_u64(newoffset, pos as u64)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn fd_sync(fd: u32) -> i32 {
+    wasi(|| {
+        // SAFETY: plain syscall
+        cvt(unsafe { libc::fsync(host_fd(fd)?) })?;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn fd_tell(fd: u32, offset: u32) -> i32 {
+    fd_seek(fd, 0, 1, offset)
+}
+
+#[no_mangle]
+pub extern "C" fn fd_write(fd: u32, iovs: u32, iovs_len: u32, nwritten: u32) -> i32 {
+    wasi(|| {
+        let iovecs = memory::read_iovecs(iovs, iovs_len)?;
+        let fd = host_fd(fd)?;
+        // SAFETY: iovecs point into the checked linear memory
+        let n = cvt_size(unsafe { libc::writev(fd, iovecs.as_ptr(), iovecs.len() as i32) })?;
+        memory::write_u32(nwritten, n as u32)
+    })
+}
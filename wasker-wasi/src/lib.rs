@@ -0,0 +1,47 @@
+//! `wasker-wasi` is a reference host of WASI preview1 for the output of Wasker on Linux.
+//!
+//! It defines every preview1 function with the symbol and signature Wasker imports,
+//! together with `memory_base` and `memory_grow` for the linear memory.
+//! Link it with the object generated by Wasker, and call `wasker_main`, or enable the `main` feature.
+//!
+//! Guest pointers are checked against the linear memory, and paths are confined to the preopened directories.
+
+mod errno;
+mod fd;
+mod memory;
+mod path;
+mod poll;
+mod proc;
+mod sock;
+mod state;
+
+use std::io;
+use std::path::Path;
+
+pub use errno::Errno;
+pub use fd::*;
+pub use memory::{memory_base, memory_grow, memory_size, MAX_PAGES, PAGE_SIZE};
+pub use path::*;
+pub use poll::poll_oneoff;
+pub use proc::*;
+pub use sock::*;
+pub use state::{wasker_wasi_preopen, RIGHTS_ALL};
+
+/// Preopen the host directory `host` as `guest` and return its fd.
+pub fn preopen(host: impl AsRef<Path>, guest: &str) -> io::Result<u32> {
+    state::state().preopen(host.as_ref(), guest)
+}
+
+#[cfg(feature = "main")]
+extern "C" {
+    fn wasker_main();
+}
+
+/// Entry point of the executable, running the Wasm module.
+#[cfg(feature = "main")]
+#[no_mangle]
+pub extern "C" fn main() -> i32 {
+    // SAFETY: wasker_main is defined by the object generated by Wasker
+    unsafe { wasker_main() };
+    0
+}
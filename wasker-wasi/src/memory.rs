@@ -0,0 +1,151 @@
+//! Linear memory of the guest.
+//!
+//! The whole 4GiB address space of a Wasm32 memory is reserved up front, and `memory_grow` makes pages accessible.
+//! So the base address never moves, and an access past the current size faults instead of touching other host data.
+//! Every pointer received from the guest is checked against the current size before use.
+
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+use crate::errno::{Errno, Result};
+
+pub const PAGE_SIZE: usize = 64 * 1024;
+pub const MAX_PAGES: usize = 64 * 1024;
+
+static BASE: AtomicPtr<u8> = AtomicPtr::new(ptr::null_mut());
+static RESERVATION: OnceLock<usize> = OnceLock::new();
+static PAGES: AtomicUsize = AtomicUsize::new(0);
+static GROW_LOCK: Mutex<()> = Mutex::new(());
+
+/// Base address of the linear memory. Called by `wasker_init`.
+#[no_mangle]
+pub extern "C" fn memory_base() -> *mut u8 {
+    let base = *RESERVATION.get_or_init(|| {
+        // SAFETY: a fresh anonymous mapping doesn't alias anything
+        let base = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                PAGE_SIZE * MAX_PAGES,
+                libc::PROT_NONE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE,
+                -1,
+                0,
+            )
+        };
+        assert_ne!(base, libc::MAP_FAILED, "failed to reserve linear memory");
+        base as usize
+    });
+    BASE.store(base as *mut u8, Ordering::Release);
+    base as *mut u8
+}
+
+/// Grow the linear memory by `delta` pages. Return the previous number of pages, or -1 on failure.
+#[no_mangle]
+pub extern "C" fn memory_grow(delta: u32) -> i32 {
+    let _guard = GROW_LOCK.lock().unwrap();
+    let base = memory_base();
+    let old = PAGES.load(Ordering::Acquire);
+    let Some(new) = old.checked_add(delta as usize).filter(|&n| n <= MAX_PAGES) else {
+        return -1;
+    };
+    if delta != 0 {
+        // SAFETY: the range is inside the reservation
+        let ret = unsafe {
+            libc::mprotect(
+                base.add(old * PAGE_SIZE) as *mut libc::c_void,
+                delta as usize * PAGE_SIZE,
+                libc::PROT_READ | libc::PROT_WRITE,
+            )
+        };
+        if ret != 0 {
+            return -1;
+        }
+    }
+    PAGES.store(new, Ordering::Release);
+    old as i32
+}
+
+/// Current size of the linear memory in bytes.
+pub fn memory_size() -> usize {
+    PAGES.load(Ordering::Acquire) * PAGE_SIZE
+}
+
+/// Host address of `len` bytes at `offset`, or `Errno::Fault` if they are out of the linear memory.
+pub(crate) fn guest_ptr(offset: u32, len: usize) -> Result<*mut u8> {
+    if offset as usize + len > memory_size() {
+        return Err(Errno::Fault);
+    }
+    // SAFETY: checked to be in the accessible part of the reservation
+    Ok(unsafe { BASE.load(Ordering::Acquire).add(offset as usize) })
+}
+
+pub(crate) fn read_bytes(offset: u32, len: u32) -> Result<Vec<u8>> {
+    let src = guest_ptr(offset, len as usize)?;
+    let mut bytes = vec![0; len as usize];
+    // SAFETY: src is checked by guest_ptr
+    unsafe { ptr::copy_nonoverlapping(src, bytes.as_mut_ptr(), bytes.len()) };
+    Ok(bytes)
+}
+
+pub(crate) fn write_bytes(offset: u32, bytes: &[u8]) -> Result<()> {
+    let dst = guest_ptr(offset, bytes.len())?;
+    // SAFETY: dst is checked by guest_ptr
+    unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), dst, bytes.len()) };
+    Ok(())
+}
+
+pub(crate) fn write_u32(offset: u32, value: u32) -> Result<()> {
+    write_bytes(offset, &value.to_le_bytes())
+}
+
+pub(crate) fn write_u64(offset: u32, value: u64) -> Result<()> {
+    write_bytes(offset, &value.to_le_bytes())
+}
+
+/// Translate an array of guest `iovec`/`ciovec` into host `iovec`.
+pub(crate) fn read_iovecs(iovs: u32, iovs_len: u32) -> Result<Vec<libc::iovec>> {
+    let array = read_bytes(iovs, iovs_len.checked_mul(8).ok_or(Errno::Fault)?)?;
+    array
+        .chunks_exact(8)
+        .map(|iov| {
+            let buf = u32::from_le_bytes(iov[0..4].try_into().unwrap());
+            let len = u32::from_le_bytes(iov[4..8].try_into().unwrap());
+            Ok(libc::iovec {
+                iov_base: guest_ptr(buf, len as usize)? as *mut libc::c_void,
+                iov_len: len as usize,
+            })
+        })
+        .collect()
+}
+
+/// Little-endian writer of a fixed-size WASI struct.
+pub(crate) struct Layout<const N: usize>(pub [u8; N]);
+
+impl<const N: usize> Default for Layout<N> {
+    fn default() -> Self {
+        Self([0; N])
+    }
+}
+
+impl<const N: usize> Layout<N> {
+    pub fn u8(&mut self, offset: usize, value: u8) -> &mut Self {
+        self.0[offset] = value;
+        self
+    }
+
+    pub fn u16(&mut self, offset: usize, value: u16) -> &mut Self {
+        self.0[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub fn u32(&mut self, offset: usize, value: u32) -> &mut Self {
+        self.0[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub fn u64(&mut self, offset: usize, value: u64) -> &mut Self {
+        self.0[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+        self
+    }
+}
@@ -0,0 +1,351 @@
+//! `path_*` functions.
+//!
+//! Paths are resolved with `openat2(RESOLVE_BENEATH)` relative to the directory fd,
+//! so that neither `..` nor symbolic links can escape a preopened directory.
+
+use std::ffi::CString;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+
+use crate::errno::{cvt, cvt_size, wasi, Errno, Result};
+use crate::fd::{fdflags_to_oflags, timespecs, write_filestat};
+use crate::memory;
+use crate::state::{host_fd, state, Entry};
+
+// lookupflags
+const LOOKUPFLAGS_SYMLINK_FOLLOW: u32 = 1 << 0;
+
+// oflags
+const OFLAGS_CREAT: u32 = 1 << 0;
+const OFLAGS_DIRECTORY: u32 = 1 << 1;
+const OFLAGS_EXCL: u32 = 1 << 2;
+const OFLAGS_TRUNC: u32 = 1 << 3;
+
+// rights
+const RIGHTS_FD_READ: u64 = 1 << 1;
+const RIGHTS_FD_WRITE: u64 = 1 << 6;
+const RIGHTS_FD_ALLOCATE: u64 = 1 << 8;
+const RIGHTS_FD_READDIR: u64 = 1 << 14;
+const RIGHTS_FD_FILESTAT_SET_SIZE: u64 = 1 << 22;
+
+// Not in libc yet
+#[repr(C)]
+struct OpenHow {
+    flags: u64,
+    mode: u64,
+    resolve: u64,
+}
+const RESOLVE_BENEATH: u64 = 0x08;
+
+/// Open `path` relative to `dir`, failing with `Errno::Notcapable` if it resolves outside `dir`.
+fn open_beneath(dir: RawFd, path: &CString, flags: libc::c_int, mode: u64) -> Result<OwnedFd> {
+    let how = OpenHow {
+        flags: (flags | libc::O_CLOEXEC) as u64,
+        // openat2 rejects a mode unless creating a file
+        mode: if flags & libc::O_CREAT != 0 { mode } else { 0 },
+        resolve: RESOLVE_BENEATH,
+    };
+    // SAFETY: path is NUL-terminated and how lives across the call
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_openat2,
+            dir,
+            path.as_ptr(),
+            &how as *const OpenHow,
+            std::mem::size_of::<OpenHow>(),
+        )
+    };
+    if ret == -1 {
+        return Err(match Errno::last_os_error() {
+            Errno::Xdev => Errno::Notcapable,
+            errno => errno,
+        });
+    }
+    // SAFETY: openat2 returned a new fd
+    Ok(unsafe { OwnedFd::from_raw_fd(ret as RawFd) })
+}
+
+/// Read a guest path.
+fn read_path(path: u32, path_len: u32) -> Result<Vec<u8>> {
+    let path = memory::read_bytes(path, path_len)?;
+    if path.is_empty() {
+        return Err(Errno::Noent);
+    }
+    if path.contains(&0) {
+        return Err(Errno::Ilseq);
+    }
+    Ok(path)
+}
+
+/// Split a guest path into its parent directory, opened beneath `fd`, and the last component.
+fn resolve_parent(fd: u32, path: u32, path_len: u32) -> Result<(OwnedFd, CString)> {
+    let dir = host_fd(fd)?;
+    let path = read_path(path, path_len)?;
+    let trimmed = match path.iter().rposition(|&b| b != b'/') {
+        Some(end) => &path[..=end],
+        // Only slashes, i.e. an absolute path
+        None => return Err(Errno::Notcapable),
+    };
+    let (parent, name) = match trimmed.iter().rposition(|&b| b == b'/') {
+        Some(i) => (&trimmed[..=i], &trimmed[i + 1..]),
+        None => (&b"."[..], trimmed),
+    };
+    // `..` can't be a name relative to the parent, so resolve the whole path as a directory
+    let (parent, name) = if name == b".." || name == b"." {
+        (trimmed, &b"."[..])
+    } else {
+        (parent, name)
+    };
+    let parent = CString::new(parent).unwrap();
+    let parent = open_beneath(dir, &parent, libc::O_PATH | libc::O_DIRECTORY, 0)?;
+    Ok((parent, CString::new(name).unwrap()))
+}
+
+/// Open a guest path beneath `fd` without access, to inspect or validate it.
+fn open_path(fd: u32, lookupflags: u32, path: u32, path_len: u32) -> Result<OwnedFd> {
+    let dir = host_fd(fd)?;
+    let path = CString::new(read_path(path, path_len)?).unwrap();
+    let mut flags = libc::O_PATH;
+    if lookupflags & LOOKUPFLAGS_SYMLINK_FOLLOW == 0 {
+        flags |= libc::O_NOFOLLOW;
+    }
+    open_beneath(dir, &path, flags, 0)
+}
+
+#[no_mangle]
+pub extern "C" fn path_create_directory(fd: u32, path: u32, path_len: u32) -> i32 {
+    wasi(|| {
+        let (parent, name) = resolve_parent(fd, path, path_len)?;
+        // SAFETY: name is NUL-terminated
+        cvt(unsafe { libc::mkdirat(parent.as_raw_fd(), name.as_ptr(), 0o777) })?;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn path_filestat_get(
+    fd: u32,
+    lookupflags: u32,
+    path: u32,
+    path_len: u32,
+    buf: u32,
+) -> i32 {
+    wasi(|| {
+        let file = open_path(fd, lookupflags, path, path_len)?;
+        write_filestat(file.as_raw_fd(), buf)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn path_filestat_set_times(
+    fd: u32,
+    lookupflags: u32,
+    path: u32,
+    path_len: u32,
+    atim: u64,
+    mtim: u64,
+    fst_flags: u32,
+) -> i32 {
+    wasi(|| {
+        let times = timespecs(atim, mtim, fst_flags)?;
+        // Check that the target, after following links, is inside the sandbox
+        open_path(fd, lookupflags, path, path_len)?;
+        let (parent, name) = resolve_parent(fd, path, path_len)?;
+        let flags = if lookupflags & LOOKUPFLAGS_SYMLINK_FOLLOW == 0 {
+            libc::AT_SYMLINK_NOFOLLOW
+        } else {
+            0
+        };
+        // SAFETY: name is NUL-terminated and times has two elements
+        cvt(unsafe { libc::utimensat(parent.as_raw_fd(), name.as_ptr(), times.as_ptr(), flags) })?;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn path_link(
+    old_fd: u32,
+    old_lookupflags: u32,
+    old_path: u32,
+    old_path_len: u32,
+    new_fd: u32,
+    new_path: u32,
+    new_path_len: u32,
+) -> i32 {
+    wasi(|| {
+        // Check that the target, after following links, is inside the sandbox
+        open_path(old_fd, old_lookupflags, old_path, old_path_len)?;
+        let (old_parent, old_name) = resolve_parent(old_fd, old_path, old_path_len)?;
+        let (new_parent, new_name) = resolve_parent(new_fd, new_path, new_path_len)?;
+        let flags = if old_lookupflags & LOOKUPFLAGS_SYMLINK_FOLLOW != 0 {
+            libc::AT_SYMLINK_FOLLOW
+        } else {
+            0
+        };
+        // SAFETY: names are NUL-terminated
+        cvt(unsafe {
+            libc::linkat(
+                old_parent.as_raw_fd(),
+                old_name.as_ptr(),
+                new_parent.as_raw_fd(),
+                new_name.as_ptr(),
+                flags,
+            )
+        })?;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn path_open(
+    fd: u32,
+    dirflags: u32,
+    path: u32,
+    path_len: u32,
+    oflags: u32,
+    rights_base: u64,
+    rights_inheriting: u64,
+    fdflags: u32,
+    opened_fd: u32,
+) -> i32 {
+    wasi(|| {
+        let dir = host_fd(fd)?;
+        let path = CString::new(read_path(path, path_len)?).unwrap();
+
+        let read = rights_base & (RIGHTS_FD_READ | RIGHTS_FD_READDIR) != 0;
+        let write =
+            rights_base & (RIGHTS_FD_WRITE | RIGHTS_FD_ALLOCATE | RIGHTS_FD_FILESTAT_SET_SIZE) != 0;
+        let mut flags = match (read, write) {
+            (_, false) => libc::O_RDONLY,
+            (false, true) => libc::O_WRONLY,
+            (true, true) => libc::O_RDWR,
+        };
+        if oflags & OFLAGS_CREAT != 0 {
+            flags |= libc::O_CREAT;
+        }
+        if oflags & OFLAGS_DIRECTORY != 0 {
+            flags |= libc::O_DIRECTORY;
+        }
+        if oflags & OFLAGS_EXCL != 0 {
+            flags |= libc::O_EXCL;
+        }
+        if oflags & OFLAGS_TRUNC != 0 {
+            flags |= libc::O_TRUNC;
+        }
+        if dirflags & LOOKUPFLAGS_SYMLINK_FOLLOW == 0 {
+            flags |= libc::O_NOFOLLOW;
+        }
+        flags |= fdflags_to_oflags(fdflags);
+
+        let file = match open_beneath(dir, &path, flags, 0o666) {
+            // Guests often ask for write rights on directories too
+            Err(Errno::Isdir) if write && oflags & (OFLAGS_CREAT | OFLAGS_TRUNC) == 0 => {
+                let flags = flags & !(libc::O_WRONLY | libc::O_RDWR) | libc::O_RDONLY;
+                open_beneath(dir, &path, flags, 0o666)?
+            }
+            result => result?,
+        };
+
+        let mut state = state();
+        let inheriting = state.get(fd)?.rights_inheriting;
+        let new_fd = state.insert(Entry {
+            fd: file,
+            preopen: None,
+            rights_base: rights_base & inheriting,
+            rights_inheriting: rights_inheriting & inheriting,
+        });
+        if let Err(errno) = memory::write_u32(opened_fd, new_fd) {
+            state.fds.remove(&new_fd);
+            return Err(errno);
+        }
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn path_readlink(
+    fd: u32,
+    path: u32,
+    path_len: u32,
+    buf: u32,
+    buf_len: u32,
+    bufused: u32,
+) -> i32 {
+    wasi(|| {
+        let (parent, name) = resolve_parent(fd, path, path_len)?;
+        let dst = memory::guest_ptr(buf, buf_len as usize)?;
+        // SAFETY: dst is checked by guest_ptr
+        let n = cvt_size(unsafe {
+            libc::readlinkat(
+                parent.as_raw_fd(),
+                name.as_ptr(),
+                dst as *mut libc::c_char,
+                buf_len as usize,
+            )
+        })?;
+        memory::write_u32(bufused, n as u32)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn path_remove_directory(fd: u32, path: u32, path_len: u32) -> i32 {
+    wasi(|| {
+        let (parent, name) = resolve_parent(fd, path, path_len)?;
+        // SAFETY: name is NUL-terminated
+        cvt(unsafe { libc::unlinkat(parent.as_raw_fd(), name.as_ptr(), libc::AT_REMOVEDIR) })?;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn path_rename(
+    fd: u32,
+    old_path: u32,
+    old_path_len: u32,
+    new_fd: u32,
+    new_path: u32,
+    new_path_len: u32,
+) -> i32 {
+    wasi(|| {
+        let (old_parent, old_name) = resolve_parent(fd, old_path, old_path_len)?;
+        let (new_parent, new_name) = resolve_parent(new_fd, new_path, new_path_len)?;
+        // SAFETY: names are NUL-terminated
+        cvt(unsafe {
+            libc::renameat(
+                old_parent.as_raw_fd(),
+                old_name.as_ptr(),
+                new_parent.as_raw_fd(),
+                new_name.as_ptr(),
+            )
+        })?;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn path_symlink(
+    old_path: u32,
+    old_path_len: u32,
+    fd: u32,
+    new_path: u32,
+    new_path_len: u32,
+) -> i32 {
+    wasi(|| {
+        // The target is resolved beneath the directory when the link is followed
+        let target = CString::new(read_path(old_path, old_path_len)?).unwrap();
+        let (parent, name) = resolve_parent(fd, new_path, new_path_len)?;
+        // SAFETY: target and name are NUL-terminated
+        cvt(unsafe { libc::symlinkat(target.as_ptr(), parent.as_raw_fd(), name.as_ptr()) })?;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn path_unlink_file(fd: u32, path: u32, path_len: u32) -> i32 {
+    wasi(|| {
+        let (parent, name) = resolve_parent(fd, path, path_len)?;
+        // SAFETY: name is NUL-terminated
+        cvt(unsafe { libc::unlinkat(parent.as_raw_fd(), name.as_ptr(), 0) })?;
+        Ok(())
+    })
+}
@@ -0,0 +1,186 @@
+//! `poll_oneoff`.
+
+use std::time::Duration;
+
+use crate::errno::{wasi, Errno, Result};
+use crate::memory::{self, Layout};
+use crate::proc::{clock_id, now};
+use crate::state::host_fd;
+
+// eventtype
+const EVENTTYPE_CLOCK: u8 = 0;
+const EVENTTYPE_FD_READ: u8 = 1;
+const EVENTTYPE_FD_WRITE: u8 = 2;
+
+// subclockflags
+const SUBCLOCKFLAGS_ABSTIME: u16 = 1 << 0;
+
+// eventrwflags
+const EVENTRWFLAGS_FD_READWRITE_HANGUP: u16 = 1 << 0;
+
+const SUBSCRIPTION_SIZE: u32 = 48;
+
+struct Event {
+    userdata: u64,
+    error: Errno,
+    ty: u8,
+    nbytes: u64,
+    flags: u16,
+}
+
+impl Event {
+    fn new(userdata: u64, ty: u8, error: Errno) -> Self {
+        Self {
+            userdata,
+            error,
+            ty,
+            nbytes: 0,
+            flags: 0,
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn poll_oneoff(
+    subscriptions: u32,
+    events: u32,
+    nsubscriptions: u32,
+    nevents: u32,
+) -> i32 {
+    wasi(|| {
+        if nsubscriptions == 0 {
+            return Err(Errno::Inval);
+        }
+        let size = nsubscriptions
+            .checked_mul(SUBSCRIPTION_SIZE)
+            .ok_or(Errno::Fault)?;
+        let subscriptions = memory::read_bytes(subscriptions, size)?;
+        let fired = poll(&subscriptions)?;
+
+        for (i, event) in fired.iter().enumerate() {
+            let mut layout = Layout::<32>::default();
+            layout
+                .u64(0, event.userdata)
+                .u16(8, event.error as u16)
+                .u8(10, event.ty)
+                .u64(16, event.nbytes)
+                .u16(24, event.flags);
+            let offset = (i as u32)
+                .checked_mul(32)
+                .and_then(|n| n.checked_add(events))
+                .ok_or(Errno::Fault)?;
+            memory::write_bytes(offset, &layout.0)?;
+        }
+        memory::write_u32(nevents, fired.len() as u32)
+    })
+}
+
+fn poll(subscriptions: &[u8]) -> Result<Vec<Event>> {
+    let u16_at = |s: &[u8], o: usize| u16::from_le_bytes(s[o..o + 2].try_into().unwrap());
+    let u32_at = |s: &[u8], o: usize| u32::from_le_bytes(s[o..o + 4].try_into().unwrap());
+    let u64_at = |s: &[u8], o: usize| u64::from_le_bytes(s[o..o + 8].try_into().unwrap());
+
+    let mut fired = Vec::new();
+    // (userdata, nanoseconds until the timeout)
+    let mut clocks = Vec::new();
+    // (userdata, type)
+    let mut fd_subscriptions = Vec::new();
+    let mut pollfds = Vec::new();
+
+    for sub in subscriptions.chunks_exact(SUBSCRIPTION_SIZE as usize) {
+        let userdata = u64_at(sub, 0);
+        match sub[8] {
+            EVENTTYPE_CLOCK => {
+                let timeout = u64_at(sub, 24);
+                let remaining = clock_id(u32_at(sub, 16)).and_then(|id| {
+                    if u16_at(sub, 40) & SUBCLOCKFLAGS_ABSTIME != 0 {
+                        Ok(timeout.saturating_sub(now(id)?))
+                    } else {
+                        Ok(timeout)
+                    }
+                });
+                match remaining {
+                    Ok(remaining) => clocks.push((userdata, remaining)),
+                    Err(errno) => fired.push(Event::new(userdata, EVENTTYPE_CLOCK, errno)),
+                }
+            }
+            ty @ (EVENTTYPE_FD_READ | EVENTTYPE_FD_WRITE) => match host_fd(u32_at(sub, 16)) {
+                Ok(fd) => {
+                    let events = if ty == EVENTTYPE_FD_READ {
+                        libc::POLLIN
+                    } else {
+                        libc::POLLOUT
+                    };
+                    pollfds.push(libc::pollfd {
+                        fd,
+                        events,
+                        revents: 0,
+                    });
+                    fd_subscriptions.push((userdata, ty));
+                }
+                Err(errno) => fired.push(Event::new(userdata, ty, errno)),
+            },
+            _ => return Err(Errno::Inval),
+        }
+    }
+
+    // Wait for the earliest clock, or forever if there are only fds
+    let timeout = if fired.is_empty() {
+        clocks.iter().map(|&(_, remaining)| remaining).min()
+    } else {
+        Some(0)
+    };
+    let timespec = timeout.map(|nanos| {
+        let duration = Duration::from_nanos(nanos);
+        libc::timespec {
+            tv_sec: duration.as_secs() as libc::time_t,
+            tv_nsec: duration.subsec_nanos() as libc::c_long,
+        }
+    });
+
+    let start = now(libc::CLOCK_MONOTONIC)?;
+    // SAFETY: pollfds and timespec live across the call
+    let ret = unsafe {
+        libc::ppoll(
+            pollfds.as_mut_ptr(),
+            pollfds.len() as libc::nfds_t,
+            timespec
+                .as_ref()
+                .map_or(std::ptr::null(), |ts| ts as *const _),
+            std::ptr::null(),
+        )
+    };
+    if ret == -1 {
+        return Err(Errno::last_os_error());
+    }
+    let elapsed = now(libc::CLOCK_MONOTONIC)? - start;
+
+    for (pollfd, &(userdata, ty)) in pollfds.iter().zip(&fd_subscriptions) {
+        if pollfd.revents == 0 {
+            continue;
+        }
+        let mut event = Event::new(userdata, ty, Errno::Success);
+        if pollfd.revents & libc::POLLNVAL != 0 {
+            event.error = Errno::Badf;
+        } else if pollfd.revents & libc::POLLERR != 0 {
+            event.error = Errno::Io;
+        }
+        if pollfd.revents & libc::POLLHUP != 0 {
+            event.flags |= EVENTRWFLAGS_FD_READWRITE_HANGUP;
+        }
+        if ty == EVENTTYPE_FD_READ && event.error == Errno::Success {
+            let mut nbytes: libc::c_int = 0;
+            // SAFETY: FIONREAD writes an int
+            if unsafe { libc::ioctl(pollfd.fd, libc::FIONREAD, &mut nbytes) } == 0 {
+                event.nbytes = nbytes as u64;
+            }
+        }
+        fired.push(event);
+    }
+    for (userdata, remaining) in clocks {
+        if remaining <= elapsed {
+            fired.push(Event::new(userdata, EVENTTYPE_CLOCK, Errno::Success));
+        }
+    }
+    Ok(fired)
+}
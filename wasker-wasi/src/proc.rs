@@ -0,0 +1,172 @@
+//! Arguments, environment variables, clocks, randomness and process control.
+
+use std::ffi::CString;
+use std::mem::MaybeUninit;
+
+use crate::errno::{cvt, cvt_size, wasi, Errno, Result};
+use crate::memory;
+use crate::state::state;
+
+/// Write NUL-terminated `strings` to `buf` and pointers to them to `ptrs`.
+fn write_strings(strings: &[CString], ptrs: u32, buf: u32) -> Result<()> {
+    let mut offset = buf;
+    for (i, s) in strings.iter().enumerate() {
+        let ptr = (i as u32)
+            .checked_mul(4)
+            .and_then(|n| n.checked_add(ptrs))
+            .ok_or(Errno::Fault)?;
+        memory::write_u32(ptr, offset)?;
+        memory::write_bytes(offset, s.as_bytes_with_nul())?;
+        offset = offset
+            .checked_add(s.as_bytes_with_nul().len() as u32)
+            .ok_or(Errno::Fault)?;
+    }
+    Ok(())
+}
+
+fn write_sizes(strings: &[CString], count: u32, buf_size: u32) -> Result<()> {
+    let size: usize = strings.iter().map(|s| s.as_bytes_with_nul().len()).sum();
+    memory::write_u32(count, strings.len() as u32)?;
+    memory::write_u32(buf_size, size as u32)
+}
+
+#[no_mangle]
+pub extern "C" fn args_get(argv: u32, argv_buf: u32) -> i32 {
+    wasi(|| write_strings(&state().args, argv, argv_buf))
+}
+
+#[no_mangle]
+pub extern "C" fn args_sizes_get(argc: u32, argv_buf_size: u32) -> i32 {
+    wasi(|| write_sizes(&state().args, argc, argv_buf_size))
+}
+
+#[no_mangle]
+pub extern "C" fn environ_get(environ: u32, environ_buf: u32) -> i32 {
+    wasi(|| write_strings(&state().env, environ, environ_buf))
+}
+
+#[no_mangle]
+pub extern "C" fn environ_sizes_get(environc: u32, environ_buf_size: u32) -> i32 {
+    wasi(|| write_sizes(&state().env, environc, environ_buf_size))
+}
+
+pub(crate) fn clock_id(id: u32) -> Result<libc::clockid_t> {
+    match id {
+        0 => Ok(libc::CLOCK_REALTIME),
+        1 => Ok(libc::CLOCK_MONOTONIC),
+        2 => Ok(libc::CLOCK_PROCESS_CPUTIME_ID),
+        3 => Ok(libc::CLOCK_THREAD_CPUTIME_ID),
+        _ => Err(Errno::Inval),
+    }
+}
+
+/// Current time of the host clock `id` in nanoseconds.
+pub(crate) fn now(id: libc::clockid_t) -> Result<u64> {
+    let mut ts = MaybeUninit::<libc::timespec>::uninit();
+    // SAFETY: ts is written by clock_gettime on success
+    cvt(unsafe { libc::clock_gettime(id, ts.as_mut_ptr()) })?;
+    let ts = unsafe { ts.assume_init() };
+    Ok(ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64)
+}
+
+#[no_mangle]
+pub extern "C" fn clock_res_get(id: u32, resolution: u32) -> i32 {
+    wasi(|| {
+        let mut ts = MaybeUninit::<libc::timespec>::uninit();
+        // SAFETY: ts is written by clock_getres on success
+        cvt(unsafe { libc::clock_getres(clock_id(id)?, ts.as_mut_ptr()) })?;
+        let ts = unsafe { ts.assume_init() };
+        memory::write_u64(
+            resolution,
+            ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64,
+        )
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn clock_time_get(id: u32, _precision: u64, time: u32) -> i32 {
+    wasi(|| memory::write_u64(time, now(clock_id(id)?)?))
+}
+
+#[no_mangle]
+pub extern "C" fn random_get(buf: u32, buf_len: u32) -> i32 {
+    wasi(|| {
+        let dst = memory::guest_ptr(buf, buf_len as usize)?;
+        let mut filled = 0;
+        while filled < buf_len as usize {
+            // SAFETY: dst is checked by guest_ptr
+            let n = cvt_size(unsafe {
+                libc::getrandom(
+                    dst.add(filled) as *mut libc::c_void,
+                    buf_len as usize - filled,
+                    0,
+                )
+            });
+            match n {
+                Ok(n) => filled += n,
+                Err(Errno::Intr) => continue,
+                Err(errno) => return Err(errno),
+            }
+        }
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn proc_exit(code: u32) -> ! {
+    // SAFETY: exit runs atexit handlers and never returns
+    unsafe { libc::exit(code as libc::c_int) }
+}
+
+#[no_mangle]
+pub extern "C" fn proc_raise(sig: u32) -> i32 {
+    wasi(|| {
+        let sig = match sig {
+            1 => libc::SIGHUP,
+            2 => libc::SIGINT,
+            3 => libc::SIGQUIT,
+            4 => libc::SIGILL,
+            5 => libc::SIGTRAP,
+            6 => libc::SIGABRT,
+            7 => libc::SIGBUS,
+            8 => libc::SIGFPE,
+            9 => libc::SIGKILL,
+            10 => libc::SIGUSR1,
+            11 => libc::SIGSEGV,
+            12 => libc::SIGUSR2,
+            13 => libc::SIGPIPE,
+            14 => libc::SIGALRM,
+            15 => libc::SIGTERM,
+            16 => libc::SIGCHLD,
+            17 => libc::SIGCONT,
+            18 => libc::SIGSTOP,
+            19 => libc::SIGTSTP,
+            20 => libc::SIGTTIN,
+            21 => libc::SIGTTOU,
+            22 => libc::SIGURG,
+            23 => libc::SIGXCPU,
+            24 => libc::SIGXFSZ,
+            25 => libc::SIGVTALRM,
+            26 => libc::SIGPROF,
+            27 => libc::SIGWINCH,
+            28 => libc::SIGPOLL,
+            29 => libc::SIGPWR,
+            30 => libc::SIGSYS,
+            _ => return Err(Errno::Inval),
+        };
+        // SAFETY: plain syscall
+        cvt(unsafe { libc::raise(sig) })?;
+        Ok(())
+    })
+}
+
+/// Shadows `sched_yield` of libc, so it calls the syscall directly.
+#[no_mangle]
+pub extern "C" fn sched_yield() -> i32 {
+    wasi(|| {
+        // SAFETY: plain syscall
+        let ret = unsafe { libc::syscall(libc::SYS_sched_yield) };
+        cvt(ret as libc::c_int)?;
+        Ok(())
+    })
+}
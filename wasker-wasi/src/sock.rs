@@ -0,0 +1,127 @@
+//! `sock_*` functions over sockets inherited from the host.
+
+use std::os::fd::{FromRawFd, OwnedFd};
+
+use crate::errno::{cvt, cvt_size, wasi, Errno};
+use crate::fd::FDFLAGS_NONBLOCK;
+use crate::memory;
+use crate::state::{host_fd, state, Entry};
+
+// riflags
+const RIFLAGS_RECV_PEEK: u32 = 1 << 0;
+const RIFLAGS_RECV_WAITALL: u32 = 1 << 1;
+
+// roflags
+const ROFLAGS_RECV_DATA_TRUNCATED: u16 = 1 << 0;
+
+// sdflags
+const SDFLAGS_RD: u32 = 1 << 0;
+const SDFLAGS_WR: u32 = 1 << 1;
+
+#[no_mangle]
+pub extern "C" fn sock_accept(fd: u32, flags: u32, ro_fd: u32) -> i32 {
+    wasi(|| {
+        let listener = host_fd(fd)?;
+        let mut sock_flags = libc::SOCK_CLOEXEC;
+        if flags & FDFLAGS_NONBLOCK != 0 {
+            sock_flags |= libc::SOCK_NONBLOCK;
+        }
+        // SAFETY: the peer address is not requested
+        let conn = cvt(unsafe {
+            libc::accept4(
+                listener,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                sock_flags,
+            )
+        })?;
+        // SAFETY: accept4 returned a new fd
+        let conn = unsafe { OwnedFd::from_raw_fd(conn) };
+
+        let mut state = state();
+        let inheriting = state.get(fd)?.rights_inheriting;
+        let new_fd = state.insert(Entry {
+            fd: conn,
+            preopen: None,
+            rights_base: inheriting,
+            rights_inheriting: inheriting,
+        });
+        if let Err(errno) = memory::write_u32(ro_fd, new_fd) {
+            state.fds.remove(&new_fd);
+            return Err(errno);
+        }
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn sock_recv(
+    fd: u32,
+    ri_data: u32,
+    ri_data_len: u32,
+    ri_flags: u32,
+    ro_datalen: u32,
+    ro_flags: u32,
+) -> i32 {
+    wasi(|| {
+        let mut iovecs = memory::read_iovecs(ri_data, ri_data_len)?;
+        let mut flags = 0;
+        if ri_flags & RIFLAGS_RECV_PEEK != 0 {
+            flags |= libc::MSG_PEEK;
+        }
+        if ri_flags & RIFLAGS_RECV_WAITALL != 0 {
+            flags |= libc::MSG_WAITALL;
+        }
+        // SAFETY: msghdr is plain data, and zero is a valid empty header
+        let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+        msg.msg_iov = iovecs.as_mut_ptr();
+        msg.msg_iovlen = iovecs.len();
+        let fd = host_fd(fd)?;
+        // SAFETY: iovecs point into the checked linear memory
+        let n = cvt_size(unsafe { libc::recvmsg(fd, &mut msg, flags) })?;
+        let truncated = if msg.msg_flags & libc::MSG_TRUNC != 0 {
+            ROFLAGS_RECV_DATA_TRUNCATED
+        } else {
+            0
+        };
+        memory::write_u32(ro_datalen, n as u32)?;
+        memory::write_bytes(ro_flags, &truncated.to_le_bytes())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn sock_send(
+    fd: u32,
+    si_data: u32,
+    si_data_len: u32,
+    _si_flags: u32,
+    so_datalen: u32,
+) -> i32 {
+    wasi(|| {
+        let mut iovecs = memory::read_iovecs(si_data, si_data_len)?;
+        // SAFETY: msghdr is plain data, and zero is a valid empty header
+        let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+        msg.msg_iov = iovecs.as_mut_ptr();
+        msg.msg_iovlen = iovecs.len();
+        let fd = host_fd(fd)?;
+        // A closed peer is reported as EPIPE instead of killing the process
+        // SAFETY: iovecs point into the checked linear memory
+        let n = cvt_size(unsafe { libc::sendmsg(fd, &msg, libc::MSG_NOSIGNAL) })?;
+        memory::write_u32(so_datalen, n as u32)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn sock_shutdown(fd: u32, how: u32) -> i32 {
+    wasi(|| {
+        let how = match how {
+            SDFLAGS_RD => libc::SHUT_RD,
+            SDFLAGS_WR => libc::SHUT_WR,
+            h if h == SDFLAGS_RD | SDFLAGS_WR => libc::SHUT_RDWR,
+            _ => return Err(Errno::Inval),
+        };
+        // SAFETY: plain syscall
+        cvt(unsafe { libc::shutdown(host_fd(fd)?, how) })?;
+        Ok(())
+    })
+}
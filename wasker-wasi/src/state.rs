@@ -0,0 +1,148 @@
+//! Host state shared by WASI functions: the fd table, arguments and environment variables.
+//!
+//! The state is created on first use from the process arguments, the environment and `WASKER_WASI_DIRS`.
+//! `WASKER_WASI_DIRS` is a colon-separated list of directories to preopen, each `HOST` or `HOST=GUEST`.
+
+use std::collections::BTreeMap;
+use std::ffi::{CString, OsStr};
+use std::fs::File;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+use crate::errno::{Errno, Result};
+
+/// All rights of preview1. Rights are recorded and reported, but not enforced.
+pub const RIGHTS_ALL: u64 = (1 << 29) - 1;
+
+pub(crate) struct Entry {
+    pub fd: OwnedFd,
+    // Guest path of a preopened directory
+    pub preopen: Option<String>,
+    pub rights_base: u64,
+    pub rights_inheriting: u64,
+}
+
+pub(crate) struct State {
+    pub fds: BTreeMap<u32, Entry>,
+    pub args: Vec<CString>,
+    pub env: Vec<CString>,
+}
+
+static STATE: OnceLock<Mutex<State>> = OnceLock::new();
+
+pub(crate) fn state() -> MutexGuard<'static, State> {
+    STATE
+        .get_or_init(|| Mutex::new(State::new()))
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+impl State {
+    fn new() -> Self {
+        let mut state = Self {
+            fds: BTreeMap::new(),
+            args: std::env::args_os().map(to_cstring).collect(),
+            env: std::env::vars_os()
+                .map(|(key, value)| {
+                    let mut pair = key;
+                    pair.push("=");
+                    pair.push(value);
+                    to_cstring(pair)
+                })
+                .collect(),
+        };
+        for fd in 0..3 {
+            state.fds.insert(
+                fd as u32,
+                Entry {
+                    // SAFETY: stdio is owned by the guest from now on
+                    fd: unsafe { OwnedFd::from_raw_fd(fd) },
+                    preopen: None,
+                    rights_base: RIGHTS_ALL,
+                    rights_inheriting: RIGHTS_ALL,
+                },
+            );
+        }
+        if let Some(dirs) = std::env::var_os("WASKER_WASI_DIRS") {
+            for dir in dirs
+                .as_bytes()
+                .split(|&b| b == b':')
+                .filter(|d| !d.is_empty())
+            {
+                let (host, guest) = match dir.iter().position(|&b| b == b'=') {
+                    Some(i) => (&dir[..i], &dir[i + 1..]),
+                    None => (dir, dir),
+                };
+                let host = Path::new(OsStr::from_bytes(host));
+                let guest = String::from_utf8_lossy(guest);
+                if let Err(err) = state.preopen(host, &guest) {
+                    eprintln!("wasker-wasi: failed to preopen {}: {err}", host.display());
+                }
+            }
+        }
+        state
+    }
+
+    /// Open the host directory `host` and expose it to the guest as `guest`.
+    pub fn preopen(&mut self, host: &Path, guest: &str) -> io::Result<u32> {
+        let dir = File::open(host)?;
+        if !dir.metadata()?.is_dir() {
+            return Err(io::Error::from_raw_os_error(libc::ENOTDIR));
+        }
+        Ok(self.insert(Entry {
+            fd: dir.into(),
+            preopen: Some(guest.to_string()),
+            rights_base: RIGHTS_ALL,
+            rights_inheriting: RIGHTS_ALL,
+        }))
+    }
+
+    /// Insert an entry at the lowest free fd.
+    pub fn insert(&mut self, entry: Entry) -> u32 {
+        let fd = (0..)
+            .find(|fd| !self.fds.contains_key(fd))
+            .expect("fd table full");
+        self.fds.insert(fd, entry);
+        fd
+    }
+
+    pub fn get(&self, fd: u32) -> Result<&Entry> {
+        self.fds.get(&fd).ok_or(Errno::Badf)
+    }
+
+    pub fn get_mut(&mut self, fd: u32) -> Result<&mut Entry> {
+        self.fds.get_mut(&fd).ok_or(Errno::Badf)
+    }
+}
+
+/// Host fd of the guest `fd`.
+///
+/// The lock is released on return, so that blocking calls don't hold it.
+pub(crate) fn host_fd(fd: u32) -> Result<RawFd> {
+    Ok(state().get(fd)?.fd.as_raw_fd())
+}
+
+fn to_cstring(s: impl AsRef<OsStr>) -> CString {
+    // Strings from the OS never contain NUL
+    CString::new(s.as_ref().as_bytes()).unwrap()
+}
+
+/// Preopen the host directory `host` as `guest` and return its fd, or -1 on failure.
+///
+/// # Safety
+///
+/// `host` and `guest` must be NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn wasker_wasi_preopen(
+    host: *const libc::c_char,
+    guest: *const libc::c_char,
+) -> i32 {
+    let host = OsStr::from_bytes(std::ffi::CStr::from_ptr(host).to_bytes());
+    let guest = std::ffi::CStr::from_ptr(guest).to_string_lossy();
+    state()
+        .preopen(Path::new(host), &guest)
+        .map_or(-1, |fd| fd as i32)
+}
@@ -0,0 +1,233 @@
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard, Once};
+
+use wasker_wasi::*;
+
+// Tests share the linear memory and the fd table
+static LOCK: Mutex<()> = Mutex::new(());
+
+const SUCCESS: i32 = Errno::Success as i32;
+
+fn setup() -> MutexGuard<'static, ()> {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| {
+        memory_base();
+        assert_eq!(memory_grow(1), 0);
+    });
+    LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn write_guest(offset: u32, bytes: &[u8]) {
+    assert!(offset as usize + bytes.len() <= memory_size());
+    unsafe {
+        std::ptr::copy_nonoverlapping(
+            bytes.as_ptr(),
+            memory_base().add(offset as usize),
+            bytes.len(),
+        )
+    };
+}
+
+fn read_guest(offset: u32, len: usize) -> Vec<u8> {
+    assert!(offset as usize + len <= memory_size());
+    unsafe { std::slice::from_raw_parts(memory_base().add(offset as usize), len).to_vec() }
+}
+
+fn read_u32(offset: u32) -> u32 {
+    u32::from_le_bytes(read_guest(offset, 4).try_into().unwrap())
+}
+
+fn read_u64(offset: u32) -> u64 {
+    u64::from_le_bytes(read_guest(offset, 8).try_into().unwrap())
+}
+
+/// Write a single iovec at `iov` pointing to `len` bytes at `buf`.
+fn write_iovec(iov: u32, buf: u32, len: u32) {
+    write_guest(iov, &buf.to_le_bytes());
+    write_guest(iov + 4, &len.to_le_bytes());
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("wasker-wasi-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Open `path` beneath `dir` with read and write rights and return the fd.
+fn open(dir: u32, path: &str, oflags: u32) -> Result<u32, i32> {
+    write_guest(0x100, path.as_bytes());
+    let ret = path_open(
+        dir,
+        0,
+        0x100,
+        path.len() as u32,
+        oflags,
+        RIGHTS_ALL,
+        RIGHTS_ALL,
+        0,
+        0x200,
+    );
+    if ret == SUCCESS {
+        Ok(read_u32(0x200))
+    } else {
+        Err(ret)
+    }
+}
+
+#[test]
+fn args_sizes() {
+    let _guard = setup();
+    let args: Vec<String> = std::env::args().collect();
+    assert_eq!(args_sizes_get(0, 4), SUCCESS);
+    assert_eq!(read_u32(0), args.len() as u32);
+    let buf_size = read_u32(4);
+    assert_eq!(
+        buf_size as usize,
+        args.iter().map(|arg| arg.len() + 1).sum::<usize>()
+    );
+
+    assert_eq!(args_get(0x1000, 0x2000), SUCCESS);
+    assert_eq!(read_u32(0x1000), 0x2000);
+    let first = read_guest(0x2000, args[0].len() + 1);
+    assert_eq!(&first[..args[0].len()], args[0].as_bytes());
+    assert_eq!(first[args[0].len()], 0);
+}
+
+#[test]
+fn out_of_bounds_pointers() {
+    let _guard = setup();
+    let end = memory_size() as u32;
+    assert_eq!(args_sizes_get(end - 2, 0), Errno::Fault as i32);
+    assert_eq!(clock_time_get(1, 0, end), Errno::Fault as i32);
+
+    // The iovec itself is inside, but its buffer is not
+    write_iovec(0x300, end - 4, 8);
+    assert_eq!(fd_write(1, 0x300, 1, 0x310), Errno::Fault as i32);
+    assert_eq!(random_get(end - 1, 2), Errno::Fault as i32);
+}
+
+#[test]
+fn memory_grow_limit() {
+    let _guard = setup();
+    assert_eq!(memory_grow(MAX_PAGES as u32), -1);
+    assert_eq!(memory_grow(0) as usize, memory_size() / PAGE_SIZE);
+}
+
+#[test]
+fn file_read_write() {
+    let _guard = setup();
+    let dir = temp_dir("file");
+    let dir_fd = preopen(&dir, "/sandbox").unwrap();
+
+    assert_eq!(fd_prestat_get(dir_fd, 0x400), SUCCESS);
+    assert_eq!(read_u32(0x404), "/sandbox".len() as u32);
+    assert_eq!(fd_prestat_dir_name(dir_fd, 0x410, 8), SUCCESS);
+    assert_eq!(read_guest(0x410, 8), b"/sandbox");
+
+    let fd = open(dir_fd, "hello.txt", 1).unwrap();
+    write_guest(0x500, b"hello, wasi");
+    write_iovec(0x600, 0x500, 11);
+    assert_eq!(fd_write(fd, 0x600, 1, 0x610), SUCCESS);
+    assert_eq!(read_u32(0x610), 11);
+    assert_eq!(
+        std::fs::read_to_string(dir.join("hello.txt")).unwrap(),
+        "hello, wasi"
+    );
+
+    assert_eq!(fd_seek(fd, 7, 0, 0x620), SUCCESS);
+    assert_eq!(read_u64(0x620), 7);
+    write_iovec(0x600, 0x700, 16);
+    assert_eq!(fd_read(fd, 0x600, 1, 0x610), SUCCESS);
+    assert_eq!(read_u32(0x610), 4);
+    assert_eq!(read_guest(0x700, 4), b"wasi");
+
+    // filetype at 16 and size at 32 of filestat
+    assert_eq!(fd_filestat_get(fd, 0x800), SUCCESS);
+    assert_eq!(read_guest(0x810, 1)[0], 4);
+    assert_eq!(read_u64(0x820), 11);
+
+    assert_eq!(fd_close(fd), SUCCESS);
+    assert_eq!(fd_close(fd), Errno::Badf as i32);
+
+    // d_namlen at 16 and the name after the 24 byte header
+    assert_eq!(fd_readdir(dir_fd, 0x900, 0x400, 0, 0x8f0), SUCCESS);
+    let used = read_u32(0x8f0) as usize;
+    let mut names = Vec::new();
+    let mut offset = 0;
+    while offset + 24 <= used {
+        let namlen = read_u32(0x900 + offset as u32 + 16) as usize;
+        names.push(read_guest(0x900 + offset as u32 + 24, namlen));
+        offset += 24 + namlen;
+    }
+    assert!(names.contains(&b"hello.txt".to_vec()));
+
+    assert_eq!(fd_close(dir_fd), SUCCESS);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn sandbox_escape() {
+    let _guard = setup();
+    let dir = temp_dir("sandbox");
+    std::fs::create_dir(dir.join("sub")).unwrap();
+    let dir_fd = preopen(&dir, ".").unwrap();
+
+    assert_eq!(
+        open(dir_fd, "/etc/passwd", 0),
+        Err(Errno::Notcapable as i32)
+    );
+    assert_eq!(open(dir_fd, "../outside", 1), Err(Errno::Notcapable as i32));
+    assert_eq!(
+        open(dir_fd, "sub/../../outside", 1),
+        Err(Errno::Notcapable as i32)
+    );
+    assert!(!dir.parent().unwrap().join("outside").exists());
+
+    // `..` inside the directory is fine
+    let fd = open(dir_fd, "sub/../inside", 1).unwrap();
+    assert_eq!(fd_close(fd), SUCCESS);
+
+    // A symbolic link can't be followed out of the directory
+    write_guest(0x100, b"/etc");
+    write_guest(0x180, b"link");
+    assert_eq!(path_symlink(0x100, 4, dir_fd, 0x180, 4), SUCCESS);
+    write_guest(0x100, b"link/passwd");
+    assert_eq!(
+        path_filestat_get(dir_fd, 1, 0x100, 11, 0x800),
+        Errno::Notcapable as i32
+    );
+
+    write_guest(0x100, b"..");
+    assert_eq!(
+        path_create_directory(dir_fd, 0x100, 2),
+        Errno::Notcapable as i32
+    );
+
+    assert_eq!(fd_close(dir_fd), SUCCESS);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn poll_clock() {
+    let _guard = setup();
+    // Relative timeout of 1ms on the monotonic clock
+    let mut subscription = [0u8; 48];
+    subscription[0..8].copy_from_slice(&42u64.to_le_bytes());
+    subscription[8] = 0;
+    subscription[16..20].copy_from_slice(&1u32.to_le_bytes());
+    subscription[24..32].copy_from_slice(&1_000_000u64.to_le_bytes());
+    write_guest(0x1000, &subscription);
+
+    assert_eq!(clock_time_get(1, 0, 0x1100), SUCCESS);
+    let before = read_u64(0x1100);
+    assert_eq!(poll_oneoff(0x1000, 0x1200, 1, 0x1300), SUCCESS);
+    assert_eq!(clock_time_get(1, 0, 0x1100), SUCCESS);
+    assert!(read_u64(0x1100) - before >= 1_000_000);
+
+    assert_eq!(read_u32(0x1300), 1);
+    assert_eq!(read_u64(0x1200), 42);
+    assert_eq!(read_guest(0x1208, 3), [0, 0, 0]);
+
+    assert_eq!(poll_oneoff(0x1000, 0x1200, 0, 0x1300), Errno::Inval as i32);
+}